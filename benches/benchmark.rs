@@ -157,6 +157,81 @@ fn call_append_callback(c: &mut Criterion) {
     });
 }
 
+fn call_table_callback(c: &mut Criterion) {
+    c.bench_function("call callback table registry 10", |b| {
+        b.iter_with_setup(
+            || {
+                let lua = Lua::new();
+                let f = {
+                    let table = lua.create_registry_value(lua.create_table().unwrap()).unwrap();
+                    let c: LuaFunction = lua
+                        .create_function(move |lua, i: i64| {
+                            let table: LuaTable = lua.registry_value(&table)?;
+                            table.raw_set(i, i)
+                        }).unwrap();
+                    lua.globals().set("callback", c).unwrap();
+                    let f: LuaFunction =
+                        lua.eval(
+                            r#"
+                            function()
+                                for i = 1,10 do
+                                    callback(i)
+                                end
+                            end
+                        "#,
+                            None,
+                        ).unwrap();
+                    lua.create_registry_value(f).unwrap()
+                };
+                (lua, f)
+            },
+            |(lua, f)| -> Lua {
+                {
+                    let entry_function: LuaFunction = lua.registry_value(&f).unwrap();
+                    entry_function.call::<_, ()>(()).unwrap();
+                }
+                lua
+            },
+        );
+    });
+
+    c.bench_function("call callback table upvalue 10", |b| {
+        b.iter_with_setup(
+            || {
+                let lua = Lua::new();
+                let f = {
+                    let c: LuaFunction = lua
+                        .create_function_with(lua.create_table().unwrap(), |lua, upvalues, i: i64| {
+                            let table: LuaTable = lua.unpack_multi(upvalues)?;
+                            table.raw_set(i, i)
+                        }).unwrap();
+                    lua.globals().set("callback", c).unwrap();
+                    let f: LuaFunction =
+                        lua.eval(
+                            r#"
+                            function()
+                                for i = 1,10 do
+                                    callback(i)
+                                end
+                            end
+                        "#,
+                            None,
+                        ).unwrap();
+                    lua.create_registry_value(f).unwrap()
+                };
+                (lua, f)
+            },
+            |(lua, f)| -> Lua {
+                {
+                    let entry_function: LuaFunction = lua.registry_value(&f).unwrap();
+                    entry_function.call::<_, ()>(()).unwrap();
+                }
+                lua
+            },
+        );
+    });
+}
+
 fn create_registry_values(c: &mut Criterion) {
     c.bench_function("create registry 10", |b| {
         b.iter_with_setup(
@@ -204,6 +279,7 @@ criterion_group! {
         call_add_function,
         call_add_callback,
         call_append_callback,
+        call_table_callback,
         create_registry_values,
         create_userdata
 }
@@ -3,7 +3,7 @@
 extern crate rlua;
 extern crate rustyline;
 
-use rlua::{Error, Lua, MultiValue};
+use rlua::{Lua, ReplResult};
 use rustyline::Editor;
 
 fn main() {
@@ -20,8 +20,8 @@ fn main() {
                 Err(_) => return,
             }
 
-            match lua.eval::<MultiValue>(&line, None) {
-                Ok(values) => {
+            match lua.eval_repl(&line) {
+                ReplResult::Values(values) => {
                     editor.add_history_entry(&line);
                     println!(
                         "{}",
@@ -33,15 +33,12 @@ fn main() {
                     );
                     break;
                 }
-                Err(Error::SyntaxError {
-                    incomplete_input: true,
-                    ..
-                }) => {
+                ReplResult::Incomplete => {
                     // continue reading input and append it to `line`
                     line.push_str("\n"); // separate input lines
                     prompt = ">> ";
                 }
-                Err(e) => {
+                ReplResult::Error(e) => {
                     eprintln!("error: {}", e);
                     break;
                 }
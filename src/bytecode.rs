@@ -0,0 +1,199 @@
+use error::{Error, Result};
+use ffi;
+
+// Lua 5.1 `lua_dump` has no way of omitting debug information, so stripping is done by re-encoding
+// the dumped binary chunk, which is what `luac -s` ends up producing.  The layout mirrors
+// `ldump.c`: a fixed size header describing the sizes of the native types, followed by the main
+// function prototype.  Since the chunk is re-encoded with the header it came with, the result can
+// be loaded by any Lua that could load the original chunk.
+pub fn strip(chunk: &[u8]) -> Result<Vec<u8>> {
+    let mut stripper = Stripper::new(chunk);
+    stripper.header()?;
+    stripper.function()?;
+    if stripper.pos != chunk.len() {
+        return Err(malformed());
+    }
+    Ok(stripper.out)
+}
+
+// Returns the number of fixed parameters of the main function of a binary chunk, and whether it
+// is variadic.  The Lua 5.1 API has no other way of finding these out.
+pub fn signature(chunk: &[u8]) -> Result<(u32, bool)> {
+    let mut reader = Stripper::new(chunk);
+    reader.header()?;
+    reader.skip_string()?;
+    // Line defined, last line defined and upvalue count
+    reader.take(2 * reader.int_size + 1)?;
+    let signature = reader.take(2)?;
+    Ok((signature[0] as u32, signature[1] & VARARG_ISVARARG != 0))
+}
+
+const HEADER_SIZE: usize = 12;
+
+const TNIL: u8 = ffi::LUA_TNIL as u8;
+const TBOOLEAN: u8 = ffi::LUA_TBOOLEAN as u8;
+const TNUMBER: u8 = ffi::LUA_TNUMBER as u8;
+const TSTRING: u8 = ffi::LUA_TSTRING as u8;
+
+// Set in the vararg flags of every function declared with `...`
+const VARARG_ISVARARG: u8 = 2;
+
+struct Stripper<'a> {
+    chunk: &'a [u8],
+    pos: usize,
+    out: Vec<u8>,
+    little_endian: bool,
+    int_size: usize,
+    size_t_size: usize,
+    instruction_size: usize,
+    number_size: usize,
+}
+
+impl<'a> Stripper<'a> {
+    fn new(chunk: &'a [u8]) -> Stripper<'a> {
+        Stripper {
+            chunk,
+            pos: 0,
+            out: Vec::with_capacity(chunk.len()),
+            little_endian: true,
+            int_size: 0,
+            size_t_size: 0,
+            instruction_size: 0,
+            number_size: 0,
+        }
+    }
+
+    fn header(&mut self) -> Result<()> {
+        let header = self.take(HEADER_SIZE)?;
+        if !header.starts_with(ffi::LUA_SIGNATURE) {
+            return Err(malformed());
+        }
+        self.little_endian = header[6] == 1;
+        self.int_size = header[7] as usize;
+        self.size_t_size = header[8] as usize;
+        self.instruction_size = header[9] as usize;
+        self.number_size = header[10] as usize;
+        self.out.extend_from_slice(header);
+        Ok(())
+    }
+
+    fn function(&mut self) -> Result<()> {
+        // Source name
+        self.skip_string()?;
+        self.put_uint(0, self.size_t_size);
+        // Line defined, last line defined, upvalue count, parameter count, vararg flag and max
+        // stack size
+        self.copy(2 * self.int_size + 4)?;
+
+        let ncode = self.copy_int()?;
+        self.copy(ncode * self.instruction_size)?;
+
+        let nconstants = self.copy_int()?;
+        for _ in 0..nconstants {
+            let ty = self.copy(1)?[0];
+            match ty {
+                TNIL => {}
+                TBOOLEAN => {
+                    self.copy(1)?;
+                }
+                TNUMBER => {
+                    self.copy(self.number_size)?;
+                }
+                TSTRING => {
+                    let len = self.uint(self.size_t_size)?;
+                    self.put_uint(len, self.size_t_size);
+                    self.copy(len)?;
+                }
+                _ => return Err(malformed()),
+            }
+        }
+
+        let nprotos = self.copy_int()?;
+        for _ in 0..nprotos {
+            self.function()?;
+        }
+
+        // Line info
+        let nlines = self.int()?;
+        self.take(nlines * self.int_size)?;
+        // Local variables
+        let nlocals = self.int()?;
+        for _ in 0..nlocals {
+            self.skip_string()?;
+            self.take(2 * self.int_size)?;
+        }
+        // Upvalue names
+        let nupvalues = self.int()?;
+        for _ in 0..nupvalues {
+            self.skip_string()?;
+        }
+        for _ in 0..3 {
+            self.put_uint(0, self.int_size);
+        }
+
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let chunk = self.chunk;
+        match self.pos.checked_add(len) {
+            Some(end) if end <= chunk.len() => {
+                self.pos = end;
+                Ok(&chunk[end - len..end])
+            }
+            _ => Err(malformed()),
+        }
+    }
+
+    fn copy(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self.take(len)?;
+        self.out.extend_from_slice(bytes);
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, size: usize) -> Result<usize> {
+        let bytes = self.take(size)?;
+        let mut n: u64 = 0;
+        for i in 0..size {
+            let b = if self.little_endian {
+                bytes[size - 1 - i]
+            } else {
+                bytes[i]
+            };
+            n = n.checked_mul(256).ok_or_else(malformed)? | b as u64;
+        }
+        if n > self.chunk.len() as u64 {
+            // No count or length can be larger than the chunk itself
+            return Err(malformed());
+        }
+        Ok(n as usize)
+    }
+
+    fn int(&mut self) -> Result<usize> {
+        let size = self.int_size;
+        self.uint(size)
+    }
+
+    fn copy_int(&mut self) -> Result<usize> {
+        let n = self.int()?;
+        self.put_uint(n, self.int_size);
+        Ok(n)
+    }
+
+    fn skip_string(&mut self) -> Result<()> {
+        let len = self.uint(self.size_t_size)?;
+        self.take(len)?;
+        Ok(())
+    }
+
+    fn put_uint(&mut self, n: usize, size: usize) {
+        for i in 0..size {
+            let shift = if self.little_endian { i } else { size - 1 - i };
+            self.out.push((n as u64).checked_shr(8 * shift as u32).unwrap_or(0) as u8);
+        }
+    }
+}
+
+fn malformed() -> Error {
+    Error::RuntimeError("malformed Lua bytecode".to_owned())
+}
@@ -1,6 +1,14 @@
+use std::char;
 use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+#[cfg(unix)]
+use std::ffi::OsStr;
 use std::hash::{BuildHasher, Hash};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::string::String as StdString;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use error::{Error, Result};
 use function::Function;
@@ -29,6 +37,12 @@ impl<'lua> ToLua<'lua> for String<'lua> {
     }
 }
 
+impl<'lua, 'a> ToLua<'lua> for &'a String<'lua> {
+    fn to_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::String(self.clone()))
+    }
+}
+
 impl<'lua> FromLua<'lua> for String<'lua> {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<String<'lua>> {
         lua.coerce_string(value)
@@ -164,17 +178,125 @@ impl<'lua, 'a> ToLua<'lua> for &'a str {
     }
 }
 
+/// Paths are converted to Lua strings.  On Unix, the raw bytes of the path are used, so every path
+/// can be converted back unchanged, even if it is not valid UTF-8.  On other platforms, such as
+/// Windows, paths that are not valid Unicode are converted lossily, replacing invalid parts with
+/// U+FFFD, and only Lua strings that are valid UTF-8 can be converted to a `PathBuf`.
+impl<'lua, 'a> ToLua<'lua> for &'a Path {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::String(path_to_string(lua, self)?))
+    }
+}
+
+impl<'lua> ToLua<'lua> for PathBuf {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::String(path_to_string(lua, &self)?))
+    }
+}
+
+impl<'lua> FromLua<'lua> for PathBuf {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        path_from_string(lua.coerce_string(value)?)
+    }
+}
+
+#[cfg(unix)]
+fn path_to_string<'lua>(lua: &'lua Lua, path: &Path) -> Result<String<'lua>> {
+    lua.create_string(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_to_string<'lua>(lua: &'lua Lua, path: &Path) -> Result<String<'lua>> {
+    lua.create_string(&*path.to_string_lossy())
+}
+
+#[cfg(unix)]
+fn path_from_string(s: String) -> Result<PathBuf> {
+    Ok(PathBuf::from(OsStr::from_bytes(s.as_bytes())))
+}
+
+#[cfg(not(unix))]
+fn path_from_string(s: String) -> Result<PathBuf> {
+    Ok(PathBuf::from(s.to_str()?))
+}
+
+/// A `char` is converted to a Lua string containing its UTF-8 encoding.  It can be converted back
+/// from a string of exactly one character, or from a number giving its code point.
+impl<'lua> ToLua<'lua> for char {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        let mut buf = [0; 4];
+        Ok(Value::String(lua.create_string(self.encode_utf8(&mut buf))?))
+    }
+}
+
+impl<'lua> FromLua<'lua> for char {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let ty = value.type_name();
+        let conversion_error = |message| Error::FromLuaConversionError {
+            from: ty,
+            to: "char",
+            message: Some(message),
+        };
+        match value {
+            Value::String(s) => {
+                let s = s.to_str()?;
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(c),
+                    _ => Err(conversion_error(format!(
+                        "expected a string of one character, got {} characters",
+                        s.chars().count()
+                    ))),
+                }
+            }
+            value @ Value::Integer(_) | value @ Value::Number(_) => {
+                let code = u32::from_lua(value, lua)?;
+                char::from_u32(code)
+                    .ok_or_else(|| conversion_error(format!("{} is not a valid code point", code)))
+            }
+            _ => Err(conversion_error("expected a string or a code point".to_owned())),
+        }
+    }
+}
+
+// Integers are stored as Lua integers when they fit in `Integer`, and as numbers otherwise, which
+// is lossy above 2^53.  Converting back checks that the value is integral and in range for the
+// target type, rather than truncating it.
 macro_rules! lua_convert_int {
     ($x:ty) => {
         impl<'lua> ToLua<'lua> for $x {
             fn to_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
-                Ok(Value::Integer(self as Integer))
+                match Integer::try_from(self) {
+                    Ok(i) => Ok(Value::Integer(i)),
+                    Err(_) => Ok(Value::Number(self as Number)),
+                }
             }
         }
 
         impl<'lua> FromLua<'lua> for $x {
             fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
-                Ok(lua.coerce_integer(value)? as $x)
+                let ty = value.type_name();
+                let out_of_range = |message| Error::FromLuaConversionError {
+                    from: ty,
+                    to: stringify!($x),
+                    message: Some(message),
+                };
+                match value {
+                    Value::Integer(i) => <$x>::try_from(i)
+                        .map_err(|_| out_of_range(format!("{} is out of range", i))),
+                    value => {
+                        let n = lua.coerce_number(value)?;
+                        if n.fract() != 0.0 {
+                            Err(out_of_range(format!("{} has no integer representation", n)))
+                        } else if n >= <$x>::min_value() as Number
+                            && n < <$x>::max_value() as Number + 1.0
+                        {
+                            Ok(n as $x)
+                        } else {
+                            Err(out_of_range(format!("{} is out of range", n)))
+                        }
+                    }
+                }
             }
         }
     };
@@ -191,6 +313,8 @@ lua_convert_int!(u64);
 lua_convert_int!(isize);
 lua_convert_int!(usize);
 
+// Converting a number to `f32` rounds it to the nearest `f32`, but finite numbers too large for an
+// `f32` are an error rather than becoming infinite.
 macro_rules! lua_convert_float {
     ($x:ty) => {
         impl<'lua> ToLua<'lua> for $x {
@@ -201,7 +325,18 @@ macro_rules! lua_convert_float {
 
         impl<'lua> FromLua<'lua> for $x {
             fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
-                Ok(lua.coerce_number(value)? as $x)
+                let ty = value.type_name();
+                let n = lua.coerce_number(value)?;
+                let x = n as $x;
+                if x.is_infinite() && n.is_finite() {
+                    Err(Error::FromLuaConversionError {
+                        from: ty,
+                        to: stringify!($x),
+                        message: Some(format!("{} is out of range", n)),
+                    })
+                } else {
+                    Ok(x)
+                }
             }
         }
     };
@@ -210,16 +345,71 @@ macro_rules! lua_convert_float {
 lua_convert_float!(f32);
 lua_convert_float!(f64);
 
+/// A `Duration` is converted to a Lua number of seconds, including the fractional part.
+impl<'lua> ToLua<'lua> for Duration {
+    fn to_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Number(self.as_secs_f64()))
+    }
+}
+
+impl<'lua> FromLua<'lua> for Duration {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let secs = lua.coerce_number(value)?;
+        Duration::try_from_secs_f64(secs).map_err(|_| Error::FromLuaConversionError {
+            from: "number",
+            to: "Duration",
+            message: Some(format!("{} is not a valid number of seconds", secs)),
+        })
+    }
+}
+
+/// A `SystemTime` is converted to a Lua number of seconds since the Unix epoch, which is negative
+/// for times before the epoch.
+impl<'lua> ToLua<'lua> for SystemTime {
+    fn to_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        let secs = match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs_f64(),
+            Err(err) => -err.duration().as_secs_f64(),
+        };
+        Ok(Value::Number(secs))
+    }
+}
+
+impl<'lua> FromLua<'lua> for SystemTime {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let secs = lua.coerce_number(value)?;
+        let time = Duration::try_from_secs_f64(secs.abs())
+            .ok()
+            .and_then(|since| {
+                if secs < 0.0 {
+                    UNIX_EPOCH.checked_sub(since)
+                } else {
+                    UNIX_EPOCH.checked_add(since)
+                }
+            });
+        time.ok_or_else(|| Error::FromLuaConversionError {
+            from: "number",
+            to: "SystemTime",
+            message: Some(format!("{} is not a valid Unix timestamp", secs)),
+        })
+    }
+}
+
 impl<'lua, T: ToLua<'lua>> ToLua<'lua> for Vec<T> {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_sequence_from(self)?))
     }
 }
 
+/// Converts a Lua sequence by reading the indices `1..=#t`.
+///
+/// The length is the result of the Lua `#` operator, so for a table with holes (`nil` values in the
+/// middle) it may be any border of the table, and the holes are converted as `nil`. Use
+/// `Vec<Option<T>>` to accept such tables.
 impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Vec<T> {
     fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
         if let Value::Table(table) = value {
-            table.sequence_values().collect()
+            sequence_from_table(table)
         } else {
             Err(Error::FromLuaConversionError {
                 from: value.type_name(),
@@ -230,6 +420,34 @@ impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Vec<T> {
     }
 }
 
+impl<'lua, T: ToLua<'lua>, const N: usize> ToLua<'lua> for [T; N] {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(lua.create_sequence_from(self)?))
+    }
+}
+
+/// Converts a Lua sequence of exactly `N` elements, with the same semantics as the `Vec`
+/// conversion.
+impl<'lua, T: FromLua<'lua>, const N: usize> FromLua<'lua> for [T; N] {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        if let Value::Table(table) = value {
+            let vec = sequence_from_table(table)?;
+            let len = vec.len();
+            <[T; N]>::try_from(vec).map_err(|_| Error::FromLuaConversionError {
+                from: "table",
+                to: "array",
+                message: Some(format!("expected table of length {}, got {}", N, len)),
+            })
+        } else {
+            Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "array",
+                message: Some("expected table".to_string()),
+            })
+        }
+    }
+}
+
 impl<'lua, K: Eq + Hash + ToLua<'lua>, V: ToLua<'lua>, S: BuildHasher> ToLua<'lua>
     for HashMap<K, V, S>
 {
@@ -291,3 +509,12 @@ impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Option<T> {
         }
     }
 }
+
+fn sequence_from_table<'lua, T: FromLua<'lua>>(table: Table<'lua>) -> Result<Vec<T>> {
+    let len = table.len()?;
+    let mut vec = Vec::with_capacity(len.max(0) as usize);
+    for i in 1..=len {
+        vec.push(table.get(i)?);
+    }
+    Ok(vec)
+}
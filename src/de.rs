@@ -0,0 +1,302 @@
+use std::result::Result as StdResult;
+use std::string::String as StdString;
+
+use serde::de::value::StringDeserializer;
+use serde::de::{self, Deserialize, DeserializeSeed, IntoDeserializer, Visitor};
+
+use error::{Error, Result, SerdeError};
+use table::{Table, TablePairs};
+use types::Integer;
+use value::Value;
+
+/// Converts a Lua value into a Rust value implementing `Deserialize`.
+///
+/// This is the inverse of [`to_value`].  A table is deserialized as a sequence if its keys are
+/// exactly the integers `1..n`, otherwise it is deserialized as a map.  When the target type
+/// explicitly asks for a map or a struct, a table is always treated as a map, so an empty table can
+/// be deserialized as either.  `nil` is deserialized as `None` or `()`.
+///
+/// Returns `Error::DeserializeError` if the value does not match the shape of the target type, for
+/// example when a table is missing a field of the target struct.
+///
+/// [`to_value`]: fn.to_value.html
+pub fn from_value<'de, T: Deserialize<'de>>(value: Value) -> Result<T> {
+    T::deserialize(Deserializer { value }).map_err(|e| e.0)
+}
+
+struct Deserializer<'lua> {
+    value: Value<'lua>,
+}
+
+impl<'lua, 'de> de::Deserializer<'de> for Deserializer<'lua> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> StdResult<V::Value, SerdeError> {
+        match self.value {
+            Value::Nil => visitor.visit_unit(),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Integer(i) => visitor.visit_i64(i as i64),
+            Value::Number(n) => visitor.visit_f64(n),
+            Value::String(s) => match s.to_str() {
+                Ok(s) => visitor.visit_str(s),
+                Err(_) => visitor.visit_bytes(s.as_bytes()),
+            },
+            Value::Table(t) => match sequence_len(&t)? {
+                Some(len) => visitor.visit_seq(SeqDeserializer {
+                    table: t,
+                    index: 1,
+                    len,
+                }),
+                None => visitor.visit_map(MapDeserializer {
+                    pairs: t.pairs(),
+                    value: None,
+                }),
+            },
+            ref v => Err(SerdeError(Error::DeserializeError(format!(
+                "cannot deserialize a Lua {}",
+                v.type_name()
+            )))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> StdResult<V::Value, SerdeError> {
+        match self.value {
+            Value::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> StdResult<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> StdResult<V::Value, SerdeError> {
+        match self.value {
+            Value::Table(t) => visitor.visit_map(MapDeserializer {
+                pairs: t.pairs(),
+                value: None,
+            }),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> StdResult<V::Value, SerdeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> StdResult<V::Value, SerdeError> {
+        match self.value {
+            Value::String(s) => visitor.visit_enum(EnumDeserializer {
+                variant: s.to_str()?.to_owned(),
+                value: None,
+            }),
+            Value::Table(t) => {
+                let mut pairs = t.pairs::<StdString, Value>();
+                let (variant, value) = match pairs.next() {
+                    Some(pair) => pair?,
+                    None => {
+                        return Err(SerdeError(Error::DeserializeError(
+                            "expected a table with a single key for an enum variant".to_owned(),
+                        )))
+                    }
+                };
+                if pairs.next().is_some() {
+                    return Err(SerdeError(Error::DeserializeError(
+                        "expected a table with a single key for an enum variant".to_owned(),
+                    )));
+                }
+                visitor.visit_enum(EnumDeserializer {
+                    variant,
+                    value: Some(value),
+                })
+            }
+            ref v => Err(SerdeError(Error::DeserializeError(format!(
+                "cannot deserialize a Lua {} as an enum",
+                v.type_name()
+            )))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit unit_struct
+        seq tuple tuple_struct identifier ignored_any
+    }
+}
+
+// Returns the length of the table if its keys are exactly the integers `1..n`.
+fn sequence_len(table: &Table) -> StdResult<Option<usize>, SerdeError> {
+    let len = table.raw_len();
+    let mut count = 0;
+    for pair in table.clone().pairs::<Value, Value>() {
+        let (key, _) = pair?;
+        match key {
+            Value::Integer(i) if i >= 1 && i <= len => count += 1,
+            _ => return Ok(None),
+        }
+    }
+    // Keys are unique, so if every key is in `1..len` and there are `len` of them, the range is
+    // contiguous.
+    if count == len {
+        Ok(Some(len as usize))
+    } else {
+        Ok(None)
+    }
+}
+
+struct SeqDeserializer<'lua> {
+    table: Table<'lua>,
+    index: Integer,
+    len: usize,
+}
+
+impl<'lua, 'de> de::SeqAccess<'de> for SeqDeserializer<'lua> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> StdResult<Option<T::Value>, SerdeError> {
+        if self.index as usize > self.len {
+            return Ok(None);
+        }
+        let value = self.table.raw_get(self.index)?;
+        self.index += 1;
+        seed.deserialize(Deserializer { value }).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len + 1 - self.index as usize)
+    }
+}
+
+struct MapDeserializer<'lua> {
+    pairs: TablePairs<'lua, Value<'lua>, Value<'lua>>,
+    value: Option<Value<'lua>>,
+}
+
+impl<'lua, 'de> de::MapAccess<'de> for MapDeserializer<'lua> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> StdResult<Option<K::Value>, SerdeError> {
+        match self.pairs.next() {
+            Some(pair) => {
+                let (key, value) = pair?;
+                self.value = Some(value);
+                seed.deserialize(Deserializer { value: key }).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> StdResult<V::Value, SerdeError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(Deserializer { value }),
+            None => Err(SerdeError(Error::DeserializeError(
+                "map value requested before its key".to_owned(),
+            ))),
+        }
+    }
+}
+
+struct EnumDeserializer<'lua> {
+    variant: StdString,
+    value: Option<Value<'lua>>,
+}
+
+impl<'lua, 'de> de::EnumAccess<'de> for EnumDeserializer<'lua> {
+    type Error = SerdeError;
+    type Variant = VariantDeserializer<'lua>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> StdResult<(V::Value, VariantDeserializer<'lua>), SerdeError> {
+        let variant: StringDeserializer<SerdeError> = self.variant.into_deserializer();
+        let variant = seed.deserialize(variant)?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer<'lua> {
+    value: Option<Value<'lua>>,
+}
+
+impl<'lua> VariantDeserializer<'lua> {
+    fn into_value(self) -> StdResult<Value<'lua>, SerdeError> {
+        match self.value {
+            Some(value) => Ok(value),
+            None => Err(SerdeError(Error::DeserializeError(
+                "expected a table for a non-unit enum variant".to_owned(),
+            ))),
+        }
+    }
+}
+
+impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> StdResult<(), SerdeError> {
+        match self.value {
+            None | Some(Value::Nil) => Ok(()),
+            Some(value) => Err(SerdeError(Error::DeserializeError(format!(
+                "expected a unit enum variant, got a Lua {}",
+                value.type_name()
+            )))),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> StdResult<T::Value, SerdeError> {
+        seed.deserialize(Deserializer {
+            value: self.into_value()?,
+        })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> StdResult<V::Value, SerdeError> {
+        de::Deserializer::deserialize_seq(
+            Deserializer {
+                value: self.into_value()?,
+            },
+            visitor,
+        )
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> StdResult<V::Value, SerdeError> {
+        de::Deserializer::deserialize_map(
+            Deserializer {
+                value: self.into_value()?,
+            },
+            visitor,
+        )
+    }
+}
@@ -0,0 +1,244 @@
+use std::cmp;
+use std::ffi::CStr;
+use std::ops::{BitOr, BitOrAssign};
+use std::os::raw::{c_char, c_int};
+use std::string::String as StdString;
+
+use ffi;
+
+/// Information about a function on the Lua call stack, returned by [`Lua::debug_info`].
+///
+/// [`Lua::debug_info`]: struct.Lua.html#method.debug_info
+#[derive(Debug, Clone)]
+pub struct DebugInfo {
+    /// The source of the chunk that defined the function, which is the chunk name for chunks
+    /// loaded from a string, or `"=[C]"` for C and Rust functions.
+    pub source: StdString,
+    /// A short, printable version of `source`, as used in Lua error messages.
+    pub short_source: StdString,
+    /// The line currently being executed by the function, or `None` if that is unknown, which is
+    /// always the case for C and Rust functions.
+    pub current_line: Option<u32>,
+    /// A name for the function, derived from how it was called, if Lua could find one.
+    pub name: Option<StdString>,
+    /// How `name` was found: `"global"`, `"local"`, `"method"`, `"field"` or `"upvalue"`, or
+    /// `None` if there is no name.
+    pub name_what: Option<StdString>,
+    /// The kind of function: `"Lua"` for a Lua function, `"C"` for a C or Rust function, `"main"`
+    /// for the main part of a chunk and `"tail"` for a function that did a tail call, about which
+    /// Lua kept no other information.
+    pub what: StdString,
+}
+
+impl DebugInfo {
+    // Builds a `DebugInfo` from a `lua_Debug` filled in by `lua_getinfo` with at least "nSl".
+    pub(crate) unsafe fn from_lua_debug(ar: &ffi::lua_Debug) -> DebugInfo {
+        DebugInfo {
+            source: ptr_to_string(ar.source).unwrap_or_default(),
+            short_source: ptr_to_string(ar.short_src.as_ptr()).unwrap_or_default(),
+            current_line: if ar.currentline >= 0 {
+                Some(ar.currentline as u32)
+            } else {
+                None
+            },
+            name: ptr_to_string(ar.name),
+            name_what: ptr_to_string(ar.namewhat).filter(|s| !s.is_empty()),
+            what: ptr_to_string(ar.what).unwrap_or_default(),
+        }
+    }
+}
+
+/// Information about a function, returned by [`Function::info`].
+///
+/// [`Function::info`]: struct.Function.html#method.info
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    /// True for Rust functions and other C functions, which have no source, line information or
+    /// declared parameters.
+    pub is_c: bool,
+    /// The source of the chunk that defined the function, which is the chunk name for chunks
+    /// loaded from a string, or `None` for C functions.
+    pub source: Option<StdString>,
+    /// A short, printable version of `source`, as used in Lua error messages.
+    pub short_source: Option<StdString>,
+    /// The line where the definition of the function starts, or `None` for C functions.  This is
+    /// 0 for the main function of a chunk.
+    pub line_defined: Option<u32>,
+    /// The line where the definition of the function ends, or `None` for C functions.
+    pub last_line_defined: Option<u32>,
+    /// The number of upvalues of the function.
+    pub num_upvalues: u32,
+    /// The number of named parameters of the function, 0 for C functions.
+    pub num_params: u32,
+    /// True if the function accepts a variable number of arguments, that is, if it is declared
+    /// with `...`.  This is always true for C functions, which can accept any arguments.
+    pub is_vararg: bool,
+}
+
+impl FunctionInfo {
+    // Builds a `FunctionInfo` from a `lua_Debug` filled in by `lua_getinfo` with at least "Su",
+    // and the signature of the function for Lua functions.
+    pub(crate) unsafe fn from_lua_debug(
+        ar: &ffi::lua_Debug,
+        signature: Option<(u32, bool)>,
+    ) -> FunctionInfo {
+        let line = |line: c_int| if line >= 0 { Some(line as u32) } else { None };
+        match signature {
+            Some((num_params, is_vararg)) => FunctionInfo {
+                is_c: false,
+                source: ptr_to_string(ar.source),
+                short_source: ptr_to_string(ar.short_src.as_ptr()),
+                line_defined: line(ar.linedefined),
+                last_line_defined: line(ar.lastlinedefined),
+                num_upvalues: ar.nups as u32,
+                num_params,
+                is_vararg,
+            },
+            None => FunctionInfo {
+                is_c: true,
+                source: None,
+                short_source: None,
+                line_defined: None,
+                last_line_defined: None,
+                num_upvalues: ar.nups as u32,
+                num_params: 0,
+                is_vararg: true,
+            },
+        }
+    }
+}
+
+unsafe fn ptr_to_string(s: *const c_char) -> Option<StdString> {
+    if s.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+    }
+}
+
+/// The events that trigger a hook set with [`Lua::set_hook`].
+///
+/// Masks can be combined with the `|` operator.
+///
+/// [`Lua::set_hook`]: struct.Lua.html#method.set_hook
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HookMask {
+    mask: c_int,
+    count: u32,
+}
+
+impl HookMask {
+    /// No events.
+    pub const NONE: HookMask = HookMask { mask: 0, count: 0 };
+    /// Calling a function, before the function starts running.
+    pub const CALL: HookMask = HookMask {
+        mask: ffi::LUA_MASKCALL,
+        count: 0,
+    };
+    /// Returning from a function, just before the function returns.
+    pub const RETURN: HookMask = HookMask {
+        mask: ffi::LUA_MASKRET,
+        count: 0,
+    };
+    /// Starting to run a new line of Lua code, or jumping back to a line (even the same line).
+    pub const LINE: HookMask = HookMask {
+        mask: ffi::LUA_MASKLINE,
+        count: 0,
+    };
+
+    /// Running `instructions` Lua VM instructions, which triggers a `HookEvent::Count` event
+    /// after every `instructions` instructions.  A count of zero is the same as `NONE`.
+    ///
+    /// When combined with another mask that also has a count, the count on the right hand side of
+    /// the `|` is used.
+    pub fn count(instructions: u32) -> HookMask {
+        if instructions == 0 {
+            HookMask::NONE
+        } else {
+            HookMask {
+                mask: ffi::LUA_MASKCOUNT,
+                count: instructions,
+            }
+        }
+    }
+
+    /// Returns true if all of the events in `mask` are contained in this mask.
+    pub fn contains(self, mask: HookMask) -> bool {
+        self.mask & mask.mask == mask.mask
+    }
+
+    pub(crate) fn lua_mask(self) -> c_int {
+        self.mask
+    }
+
+    pub(crate) fn lua_count(self) -> c_int {
+        cmp::min(self.count, c_int::max_value() as u32) as c_int
+    }
+}
+
+impl BitOr for HookMask {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        HookMask {
+            mask: self.mask | rhs.mask,
+            count: if rhs.count != 0 { rhs.count } else { self.count },
+        }
+    }
+}
+
+impl BitOrAssign for HookMask {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = *self | rhs
+    }
+}
+
+/// The kind of event that triggered a hook.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HookEvent {
+    /// A function was called.
+    Call,
+    /// A function is returning.  This is also reported for functions that end with a tail call.
+    Return,
+    /// A new line of Lua code is about to be run.
+    Line,
+    /// The configured number of instructions has been run.
+    Count,
+}
+
+/// Information passed to a hook set with [`Lua::set_hook`].
+///
+/// [`Lua::set_hook`]: struct.Lua.html#method.set_hook
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HookInfo {
+    /// The event that triggered the hook.
+    pub event: HookEvent,
+    /// The line of Lua code being run, or `None` if the running function is a C or Rust function.
+    /// For `HookEvent::Line` this is the new line.
+    pub current_line: Option<u32>,
+}
+
+impl HookInfo {
+    // Builds a `HookInfo` from the `lua_Debug` passed to a hook function.
+    pub(crate) unsafe fn from_lua_debug(
+        state: *mut ffi::lua_State,
+        ar: *mut ffi::lua_Debug,
+    ) -> HookInfo {
+        let event = match (*ar).event {
+            ffi::LUA_HOOKCALL => HookEvent::Call,
+            ffi::LUA_HOOKRET | ffi::LUA_HOOKTAILRET => HookEvent::Return,
+            ffi::LUA_HOOKLINE => HookEvent::Line,
+            _ => HookEvent::Count,
+        };
+        if event != HookEvent::Line {
+            ffi::lua_getinfo(state, cstr!("l"), ar);
+        }
+        HookInfo {
+            event,
+            current_line: if (*ar).currentline >= 0 {
+                Some((*ar).currentline as u32)
+            } else {
+                None
+            },
+        }
+    }
+}
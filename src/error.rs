@@ -1,8 +1,13 @@
+#[cfg(feature = "serde")]
+use std::error::Error as StdError;
 use std::fmt;
+use std::io;
 use std::result::Result as StdResult;
 use std::sync::Arc;
 
 use failure;
+#[cfg(feature = "serde")]
+use serde;
 
 /// Error type returned by `rlua` methods.
 #[derive(Debug, Clone)]
@@ -11,6 +16,8 @@ pub enum Error {
     SyntaxError {
         /// The error message as returned by Lua.
         message: String,
+        /// The line of the source code at which the error was detected, if Lua reported one.
+        line: Option<usize>,
         /// `true` if the error can likely be fixed by appending more input to the source code.
         ///
         /// This is useful for implementing REPLs as they can query the user for more input if this
@@ -27,10 +34,34 @@ pub enum Error {
     ///
     /// The Lua VM returns this error when there is an error running a `__gc` metamethod.
     GarbageCollectorError(String),
+    /// Lua memory error, aka `LUA_ERRMEM`.
+    ///
+    /// The Lua VM returns this error when an allocation fails, which only happens once the memory
+    /// limit set with [`Lua::set_memory_limit`] has been reached.  This applies to running Lua
+    /// code as well as to loading chunks and to rlua calls that create Lua values, such as
+    /// `Lua::create_table`.  Lua code can still catch a memory error with `pcall`, and a memory
+    /// error inside of a Rust callback reaches the caller as the cause of an
+    /// `Error::CallbackError`.
+    ///
+    /// [`Lua::set_memory_limit`]: struct.Lua.html#method.set_memory_limit
+    MemoryError(String),
     /// A mutable callback has triggered Lua code that has called the same mutable callback again.
     ///
     /// This is an error because a mutable callback can only be borrowed mutably once.
     RecursiveMutCallback,
+    /// A Rust callback was called while the maximum number of nested Rust callbacks set with
+    /// [`Lua::set_max_call_depth`] were already executing.
+    ///
+    /// [`Lua::set_max_call_depth`]: struct.Lua.html#method.set_max_call_depth
+    CallDepthExceeded,
+    /// A script run with [`Lua::run_untrusted`] exceeded its instruction or time limit.
+    ///
+    /// [`Lua::run_untrusted`]: struct.Lua.html#method.run_untrusted
+    Timeout,
+    /// Lua code was aborted because an [`InterruptHandle`] of the `Lua` state was cancelled.
+    ///
+    /// [`InterruptHandle`]: struct.InterruptHandle.html
+    Interrupted,
     /// Either a callback or a userdata method has been called, but the callback or userdata has
     /// been destructed.
     ///
@@ -102,15 +133,85 @@ pub enum Error {
     /// [`AnyUserData`]: struct.AnyUserData.html
     /// [`UserData`]: trait.UserData.html
     UserDataBorrowMutError,
+    /// Borrowing app data stored with [`Lua::set_app_data`] failed because it is already borrowed
+    /// mutably.
+    ///
+    /// This error can occur when a Rust callback that holds the result of [`Lua::app_data_mut`]
+    /// calls back into Lua, which then calls a callback that borrows the same data.
+    ///
+    /// [`Lua::set_app_data`]: struct.Lua.html#method.set_app_data
+    /// [`Lua::app_data_mut`]: struct.Lua.html#method.app_data_mut
+    AppDataBorrowError,
+    /// Mutably borrowing, replacing or removing app data stored with [`Lua::set_app_data`] failed
+    /// because it is already borrowed.
+    ///
+    /// [`Lua::set_app_data`]: struct.Lua.html#method.set_app_data
+    AppDataBorrowMutError,
     /// A `RegistryKey` produced from a different Lua state was used.
     MismatchedRegistryKey,
     /// A Rust callback returned `Err`, raising the contained `Error` as a Lua error.
+    ///
+    /// Inside Lua, the raised value is a userdata wrapping the original `Error` (unless a
+    /// converter was set with [`Lua::set_error_converter`]).  Lua code can catch it with `pcall`
+    /// and raise it again with `error`, and the Rust code receives the exact same `Error` as the
+    /// `cause`.
+    ///
+    /// [`Lua::set_error_converter`]: struct.Lua.html#method.set_error_converter
     CallbackError {
         /// Lua call stack backtrace.
         traceback: String,
         /// Original error returned by the Rust code.
         cause: Arc<Error>,
     },
+    /// A Rust callback panicked.
+    ///
+    /// Panics in callbacks never unwind through Lua: they are caught at the callback boundary and
+    /// travel through Lua as an error, and the panic is resumed once it reaches the Rust code that
+    /// called into Lua.  This error is returned instead if the panic policy is set to
+    /// [`PanicPolicy::Convert`], or when the panic can no longer be resumed, because Lua code
+    /// caught the panic (through `coroutine.resume`) and raised it again after it had already been
+    /// resumed.
+    ///
+    /// Contains the panic message, if the panic payload was a string.
+    ///
+    /// [`PanicPolicy::Convert`]: enum.PanicPolicy.html#variant.Convert
+    CallbackPanic(String),
+    /// Converting one of several Lua values failed, such as one of the arguments of a Rust
+    /// callback taking a tuple or a [`Variadic`].
+    ///
+    /// Only conversions to tuples and to `Variadic` report the position this way.  A value
+    /// converted to a single type `T` rather than a tuple, such as the argument of a callback
+    /// taking `n: i64`, fails with the error of the conversion itself (usually an
+    /// `Error::FromLuaConversionError`), which does not say which value was at fault.  Take a
+    /// one-element tuple such as `(n,): (i64,)` to get a `BadArgument` for it.
+    ///
+    /// [`Variadic`]: struct.Variadic.html
+    BadArgument {
+        /// Position of the value that could not be converted, starting at 1.
+        position: usize,
+        /// The error returned by the conversion of the value.
+        cause: Arc<Error>,
+    },
+    /// Loading or running one of the chunks passed to [`Lua::exec_all`] failed.
+    ///
+    /// [`Lua::exec_all`]: struct.Lua.html#method.exec_all
+    ChunkError {
+        /// Name of the chunk that failed.
+        name: String,
+        /// The error returned by loading or running the chunk.
+        cause: Arc<Error>,
+    },
+    /// A Rust value could not be serialized into a Lua value by [`to_value`].
+    ///
+    /// [`to_value`]: fn.to_value.html
+    SerializeError(String),
+    /// A Lua value could not be deserialized into a Rust value by [`from_value`].
+    ///
+    /// This is returned when the Lua value does not have the expected shape, for example when a
+    /// table is missing a field required by the target struct.
+    ///
+    /// [`from_value`]: fn.from_value.html
+    DeserializeError(String),
     /// A custom error.
     ///
     /// This can be used for returning user-defined errors from callbacks.
@@ -118,6 +219,9 @@ pub enum Error {
     /// Returning `Err(ExternalError(...))` from a Rust callback will raise the error as a Lua
     /// error. The Rust code that originally invoked the Lua code then receives a `CallbackError`,
     /// from which the original error (and a stack traceback) can be recovered.
+    ///
+    /// `std::io::Error` converts into this variant, so `?` can be used on IO operations in
+    /// functions returning `rlua::Result`.
     ExternalError(Arc<failure::Error>),
 }
 
@@ -129,10 +233,14 @@ impl fmt::Display for Error {
         match *self {
             Error::SyntaxError { ref message, .. } => write!(fmt, "syntax error: {}", message),
             Error::RuntimeError(ref msg) => write!(fmt, "runtime error: {}", msg),
+            Error::MemoryError(ref msg) => write!(fmt, "memory error: {}", msg),
             Error::GarbageCollectorError(ref msg) => {
                 write!(fmt, "garbage collector error: {}", msg)
             }
             Error::RecursiveMutCallback => write!(fmt, "mutable callback called recursively"),
+            Error::CallDepthExceeded => write!(fmt, "maximum depth of nested callbacks exceeded"),
+            Error::Timeout => write!(fmt, "script exceeded its instruction or time limit"),
+            Error::Interrupted => write!(fmt, "execution interrupted"),
             Error::CallbackDestructed => write!(
                 fmt,
                 "a destructed callback or destructed userdata method was called"
@@ -171,12 +279,24 @@ impl fmt::Display for Error {
             Error::UserDataTypeMismatch => write!(fmt, "userdata is not expected type"),
             Error::UserDataBorrowError => write!(fmt, "userdata already mutably borrowed"),
             Error::UserDataBorrowMutError => write!(fmt, "userdata already borrowed"),
+            Error::AppDataBorrowError => write!(fmt, "app data already mutably borrowed"),
+            Error::AppDataBorrowMutError => write!(fmt, "app data already borrowed"),
             Error::MismatchedRegistryKey => {
                 write!(fmt, "RegistryKey used from different Lua state")
             }
             Error::CallbackError { ref traceback, .. } => {
                 write!(fmt, "callback error: {}", traceback)
             }
+            Error::CallbackPanic(ref msg) => write!(fmt, "panic in Rust callback: {}", msg),
+            Error::BadArgument {
+                position,
+                ref cause,
+            } => write!(fmt, "bad argument #{}: {}", position, cause),
+            Error::ChunkError { ref name, ref cause } => {
+                write!(fmt, "error in chunk '{}': {}", name, cause)
+            }
+            Error::SerializeError(ref msg) => write!(fmt, "serialize error: {}", msg),
+            Error::DeserializeError(ref msg) => write!(fmt, "deserialize error: {}", msg),
             Error::ExternalError(ref err) => err.fmt(fmt),
         }
     }
@@ -186,6 +306,8 @@ impl failure::Fail for Error {
     fn cause(&self) -> Option<&failure::Fail> {
         match *self {
             Error::CallbackError { ref cause, .. } => Some(cause.as_ref()),
+            Error::BadArgument { ref cause, .. } => Some(cause.as_ref()),
+            Error::ChunkError { ref cause, .. } => Some(cause.as_ref()),
             Error::ExternalError(ref err) => err.as_fail().cause(),
             _ => None,
         }
@@ -203,6 +325,133 @@ impl Error {
     pub fn external<T: Into<failure::Error>>(err: T) -> Error {
         Error::ExternalError(Arc::new(err.into()))
     }
+
+    /// Attempts to find an error of type `E` among the causes of this error.
+    ///
+    /// This looks through the causes of `CallbackError`, `BadArgument` and `ChunkError`, and
+    /// through the whole cause chain of the error wrapped in an `ExternalError`, so an error
+    /// returned with `to_lua_err()` from a callback can be recovered with its concrete type after
+    /// it has passed through Lua.  Any `std::error::Error` that is also `Send + Sync + 'static`
+    /// implements `failure::Fail` and can be found this way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use std::{error, fmt};
+    /// # use rlua::{Lua, Function, ExternalError, Result};
+    /// #[derive(Debug)]
+    /// struct NotAuthorized;
+    ///
+    /// impl fmt::Display for NotAuthorized {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         write!(f, "not authorized")
+    ///     }
+    /// }
+    ///
+    /// impl error::Error for NotAuthorized {}
+    ///
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let delete_file = lua.create_function(|_, ()| -> Result<()> {
+    ///     Err(NotAuthorized.to_lua_err())
+    /// })?;
+    /// lua.globals().set("delete_file", delete_file)?;
+    ///
+    /// let script: Function = lua.eval("return function() delete_file() end", None)?;
+    /// let err = script.call::<_, ()>(()).unwrap_err();
+    /// assert!(err.downcast_ref::<NotAuthorized>().is_some());
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn downcast_ref<E: failure::Fail>(&self) -> Option<&E> {
+        match *self {
+            Error::CallbackError { ref cause, .. }
+            | Error::BadArgument { ref cause, .. }
+            | Error::ChunkError { ref cause, .. } => cause.downcast_ref(),
+            Error::ExternalError(ref err) => {
+                for fail in err.iter_chain() {
+                    if let Some(err) = fail.downcast_ref::<E>() {
+                        return Some(err);
+                    }
+                    if let Some(err) = fail.downcast_ref::<Error>() {
+                        return err.downcast_ref();
+                    }
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the Lua stack traceback captured when this error was raised, if there is one.
+    ///
+    /// Errors raised while running Lua code called from Rust carry a traceback of the Lua call
+    /// stack at the point of failure.  For a `CallbackError` this is its `traceback` field, for a
+    /// `RuntimeError` it is the part of the message starting at `stack traceback:`, which Lua
+    /// appends to the error message.  Errors that did not pass through Lua, as well as Lua errors
+    /// that were caught and re-raised without a traceback, return `None`.  A `ChunkError` returns
+    /// the traceback of its cause.
+    pub fn traceback(&self) -> Option<&str> {
+        match *self {
+            Error::CallbackError { ref traceback, .. } => Some(traceback),
+            Error::ChunkError { ref cause, .. } => cause.traceback(),
+            Error::RuntimeError(ref msg) => msg.find("stack traceback:").map(|i| &msg[i..]),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this is an `Error::SyntaxError`.
+    pub fn is_syntax_error(&self) -> bool {
+        match *self {
+            Error::SyntaxError { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this is an `Error::RuntimeError`.
+    ///
+    /// Errors raised by Lua code and errors of the Lua VM are runtime errors, but errors returned
+    /// by Rust callbacks are wrapped in an `Error::CallbackError` instead.
+    pub fn is_runtime_error(&self) -> bool {
+        match *self {
+            Error::RuntimeError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this is an `Error::MemoryError`, which means a memory limit was reached.
+    pub fn is_memory_error(&self) -> bool {
+        match *self {
+            Error::MemoryError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this is an `Error::CallbackError`.
+    pub fn is_callback_error(&self) -> bool {
+        match *self {
+            Error::CallbackError { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this is an `Error::FromLuaConversionError`.
+    pub fn is_from_lua_conversion_error(&self) -> bool {
+        match *self {
+            Error::FromLuaConversionError { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::external(err)
+    }
 }
 
 pub trait ExternalError {
@@ -230,3 +479,40 @@ where
         self.map_err(|e| e.to_lua_err())
     }
 }
+
+// `Error` cannot implement `std::error::Error` because of the blanket `failure::Fail` impl, but serde
+// requires its error types to do so, so serialization uses this wrapper internally.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub(crate) struct SerdeError(pub Error);
+
+#[cfg(feature = "serde")]
+impl fmt::Display for SerdeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(fmt)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl StdError for SerdeError {}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError(Error::SerializeError(msg.to_string()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError(Error::DeserializeError(msg.to_string()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Error> for SerdeError {
+    fn from(err: Error) -> SerdeError {
+        SerdeError(err)
+    }
+}
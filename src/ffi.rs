@@ -3,7 +3,7 @@
 #![allow(unused)]
 
 use std::mem;
-use std::os::raw::{c_char, c_double, c_int, c_longlong, c_uchar, c_void};
+use std::os::raw::{c_char, c_double, c_int, c_longlong, c_void};
 use std::ptr;
 
 pub type lua_Integer = c_longlong;
@@ -17,6 +17,12 @@ pub type lua_KContext = *mut c_void;
 pub type lua_KFunction =
     unsafe extern "C" fn(state: *mut lua_State, status: c_int, ctx: lua_KContext) -> c_int;
 pub type lua_CFunction = unsafe extern "C" fn(state: *mut lua_State) -> c_int;
+pub type lua_Hook = unsafe extern "C" fn(state: *mut lua_State, ar: *mut lua_Debug);
+pub type lua_Reader =
+    unsafe extern "C" fn(state: *mut lua_State, ud: *mut c_void, size: *mut usize) -> *const c_char;
+pub type lua_Writer =
+    unsafe extern "C" fn(state: *mut lua_State, p: *const c_void, size: usize, ud: *mut c_void)
+        -> c_int;
 
 #[repr(C)]
 pub struct lua_Debug {
@@ -26,14 +32,11 @@ pub struct lua_Debug {
     pub what: *const c_char,
     pub source: *const c_char,
     pub currentline: c_int,
+    pub nups: c_int,
     pub linedefined: c_int,
     pub lastlinedefined: c_int,
-    pub nups: c_uchar,
-    pub nparams: c_uchar,
-    pub isvararg: c_char,
-    pub istailcall: c_char,
     pub short_src: [c_char; LUA_IDSIZE as usize],
-    i_ci: *mut c_void,
+    i_ci: c_int,
 }
 
 pub const LUA_OK: c_int = 0;
@@ -47,6 +50,8 @@ pub const LUA_ERRGCMM: c_int = LUA_ERRERR + 2;
 pub const LUA_NOREF: c_int = -2;
 pub const LUA_REFNIL: c_int = -1;
 
+pub const LUA_SIGNATURE: &[u8] = b"\x1bLua";
+
 pub const LUA_MULTRET: c_int = -1;
 pub const LUAI_MAXSTACK: c_int = 1_000_000;
 pub const LUA_REGISTRYINDEX: c_int = -10000;
@@ -69,6 +74,21 @@ pub const LUA_TFUNCTION: c_int = 6;
 pub const LUA_TUSERDATA: c_int = 7;
 pub const LUA_TTHREAD: c_int = 8;
 
+pub const LUA_HOOKCALL: c_int = 0;
+pub const LUA_HOOKRET: c_int = 1;
+pub const LUA_HOOKLINE: c_int = 2;
+pub const LUA_HOOKCOUNT: c_int = 3;
+pub const LUA_HOOKTAILRET: c_int = 4;
+
+pub const LUA_MASKCALL: c_int = 1 << LUA_HOOKCALL;
+pub const LUA_MASKRET: c_int = 1 << LUA_HOOKRET;
+pub const LUA_MASKLINE: c_int = 1 << LUA_HOOKLINE;
+pub const LUA_MASKCOUNT: c_int = 1 << LUA_HOOKCOUNT;
+
+pub const LUA_OPEQ: c_int = 0;
+pub const LUA_OPLT: c_int = 1;
+pub const LUA_OPLE: c_int = 2;
+
 pub const LUA_GCSTOP: c_int = 0;
 pub const LUA_GCRESTART: c_int = 1;
 pub const LUA_GCCOLLECT: c_int = 2;
@@ -82,8 +102,17 @@ pub const LUA_GCISRUNNING: c_int = 9;
 #[link(name = "lua5.1")]
 extern "C" {
     pub fn lua_newstate(alloc: lua_Alloc, ud: *mut c_void) -> *mut lua_State;
+    pub fn lua_getallocf(state: *mut lua_State, ud: *mut *mut c_void) -> lua_Alloc;
     pub fn lua_close(state: *mut lua_State);
 
+    pub fn lua_load(
+        state: *mut lua_State,
+        reader: lua_Reader,
+        data: *mut c_void,
+        chunkname: *const c_char,
+    ) -> c_int;
+    pub fn lua_dump(state: *mut lua_State, writer: lua_Writer, data: *mut c_void) -> c_int;
+
     pub fn lua_call(state: *mut lua_State, nargs: c_int, nresults: c_int);
     pub fn lua_pcallk(
         state: *mut lua_State,
@@ -94,6 +123,7 @@ extern "C" {
         k: Option<lua_KFunction>,
     ) -> c_int;
     pub fn lua_resume(state: *mut lua_State, nargs: c_int) -> c_int;
+    pub fn lua_yield(state: *mut lua_State, nresults: c_int) -> c_int;
     pub fn lua_status(state: *mut lua_State) -> c_int;
 
     pub fn lua_pushnil(state: *mut lua_State);
@@ -104,6 +134,7 @@ extern "C" {
     pub fn lua_pushlstring(state: *mut lua_State, s: *const c_char, len: usize) -> *const c_char;
     pub fn lua_pushstring(state: *mut lua_State, s: *const c_char) -> *const c_char;
     pub fn lua_pushlightuserdata(state: *mut lua_State, data: *mut c_void);
+    pub fn lua_pushthread(state: *mut lua_State) -> c_int;
     pub fn lua_pushcclosure(state: *mut lua_State, function: lua_CFunction, n: c_int);
 
     pub fn lua_tointeger(state: *mut lua_State, index: c_int) -> lua_Integer;
@@ -114,6 +145,8 @@ extern "C" {
     pub fn lua_tonumberx(state: *mut lua_State, index: c_int, isnum: *mut c_int) -> lua_Number;
     pub fn lua_touserdata(state: *mut lua_State, index: c_int) -> *mut c_void;
     pub fn lua_tothread(state: *mut lua_State, index: c_int) -> *mut lua_State;
+    pub fn lua_topointer(state: *mut lua_State, index: c_int) -> *const c_void;
+    pub fn lua_tocfunction(state: *mut lua_State, index: c_int) -> Option<lua_CFunction>;
 
     pub fn lua_gettop(state: *const lua_State) -> c_int;
     pub fn lua_settop(state: *mut lua_State, n: c_int);
@@ -148,17 +181,32 @@ extern "C" {
     pub fn lua_setupvalue(state: *mut lua_State, funcindex: c_int, n: c_int) -> *const c_char;
 
     pub fn lua_settable(state: *mut lua_State, index: c_int);
+    pub fn lua_setfield(state: *mut lua_State, index: c_int, k: *const c_char);
     pub fn lua_rawset(state: *mut lua_State, index: c_int);
     pub fn lua_setmetatable(state: *mut lua_State, index: c_int);
+    pub fn lua_setfenv(state: *mut lua_State, index: c_int) -> c_int;
+    pub fn lua_getfenv(state: *mut lua_State, index: c_int);
 
     pub fn lua_len(state: *mut lua_State, index: c_int);
+    pub fn lua_concat(state: *mut lua_State, n: c_int);
     pub fn lua_next(state: *mut lua_State, index: c_int) -> c_int;
     pub fn lua_rawequal(state: *mut lua_State, index1: c_int, index2: c_int) -> c_int;
+    pub fn lua_compare(state: *mut lua_State, index1: c_int, index2: c_int, op: c_int) -> c_int;
 
     pub fn lua_error(state: *mut lua_State) -> !;
     pub fn lua_atpanic(state: *mut lua_State, panic: lua_CFunction) -> lua_CFunction;
     pub fn lua_gc(state: *mut lua_State, what: c_int, data: c_int) -> c_int;
+    pub fn lua_getstack(state: *mut lua_State, level: c_int, ar: *mut lua_Debug) -> c_int;
     pub fn lua_getinfo(state: *mut lua_State, what: *const c_char, ar: *mut lua_Debug) -> c_int;
+    pub fn lua_sethook(
+        state: *mut lua_State,
+        func: Option<lua_Hook>,
+        mask: c_int,
+        count: c_int,
+    ) -> c_int;
+    pub fn lua_gethook(state: *mut lua_State) -> Option<lua_Hook>;
+    pub fn lua_gethookmask(state: *mut lua_State) -> c_int;
+    pub fn lua_gethookcount(state: *mut lua_State) -> c_int;
 
     pub fn luaopen_base(state: *mut lua_State) -> c_int;
     pub fn luaopen_table(state: *mut lua_State) -> c_int;
@@ -194,6 +242,9 @@ extern "C" {
         level: c_int,
     );
     pub fn luaL_len(push_state: *mut lua_State, index: c_int) -> lua_Integer;
+    pub fn luaL_tolstring(state: *mut lua_State, index: c_int, len: *mut usize) -> *const c_char;
+    pub fn luaL_getmetafield(state: *mut lua_State, obj: c_int, e: *const c_char) -> c_int;
+    pub fn luaL_where(state: *mut lua_State, lvl: c_int);
 
     pub fn lua_pcall(
         state: *mut lua_State,
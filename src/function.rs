@@ -1,16 +1,28 @@
-use std::os::raw::c_int;
-use std::ptr;
+use std::cell::{Cell, RefCell};
+use std::ops::ControlFlow;
+use std::os::raw::{c_int, c_void};
+use std::result::Result as StdResult;
+use std::{mem, ptr, slice};
 
+use bytecode;
+use debug::{FunctionInfo, HookInfo, HookMask};
 use error::{Error, Result};
 use ffi;
-use types::LuaRef;
+use lua::{set_hook_mask, HookGuard, Lua};
+use table::Table;
+use types::{LuaRef, ScopedHookCallback};
 use util::{
-    assert_stack, check_stack, error_traceback, pop_error, protect_lua_closure, StackGuard,
+    assert_stack, check_stack, error_traceback, is_uncatchable_error, pop_error,
+    protect_lua_closure, StackGuard,
 };
-use value::{FromLuaMulti, MultiValue, ToLuaMulti};
+use value::{FromLua, FromLuaMulti, MultiValue, ToLuaMulti};
 
 /// Handle to an internal Lua function.
-#[derive(Clone, Debug)]
+///
+/// Handles compare equal, and hash the same, if they refer to the same Lua function.  The hash is
+/// based on the address of the function, so it is only meaningful within one `Lua` state and while
+/// the function is alive.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Function<'lua>(pub(crate) LuaRef<'lua>);
 
 impl<'lua> Function<'lua> {
@@ -94,6 +106,176 @@ impl<'lua> Function<'lua> {
         R::from_lua_multi(results, lua)
     }
 
+    /// Calls the function like Lua's `pcall`, returning the raised error value instead of
+    /// converting it to an `Error`.
+    ///
+    /// If the function returns normally, its results are converted to `R` and returned as
+    /// `Ok(Ok(results))`.  If it raises an error, the error value is converted to `E` and returned
+    /// as `Ok(Err(value))`, so Lua code can raise structured errors such as `error({code = 5})`
+    /// and the Rust side receives the table rather than an error message.  An error returned by a
+    /// Rust callback is delivered as `Value::Error`, which converts to `E = Error` as the original
+    /// error.  `Err` is only returned if converting the arguments, results or error value fails,
+    /// for errors other than runtime errors (such as memory errors), and when the call is
+    /// interrupted through an [`InterruptHandle`], since interrupts cannot be caught.
+    ///
+    /// Unlike with [`call`], no traceback is added to the error value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Function, Result, Table};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let check: Function = lua.eval(r#"
+    ///     function(n)
+    ///         if n < 0 then
+    ///             error({code = 5, message = "negative"})
+    ///         end
+    ///         return n
+    ///     end
+    /// "#, None)?;
+    ///
+    /// assert_eq!(check.call_catch::<_, i64, Table>(1)?.unwrap(), 1);
+    /// let err = check.call_catch::<_, i64, Table>(-1)?.unwrap_err();
+    /// assert_eq!(err.get::<_, i64>("code")?, 5);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    /// [`InterruptHandle`]: struct.InterruptHandle.html
+    pub fn call_catch<A, R, E>(&self, args: A) -> Result<StdResult<R, E>>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+        E: FromLua<'lua>,
+    {
+        let lua = self.0.lua;
+
+        let args = args.to_lua_multi(lua)?;
+        let nargs = args.len() as c_int;
+
+        let results = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, nargs + 2)?;
+
+            let stack_start = ffi::lua_gettop(lua.state);
+            lua.push_ref(&self.0);
+            for arg in args {
+                lua.push_value(arg);
+            }
+            match ffi::lua_pcall(lua.state, nargs, ffi::LUA_MULTRET, 0) {
+                ffi::LUA_OK => {
+                    let nresults = ffi::lua_gettop(lua.state) - stack_start;
+                    let mut results = MultiValue::new();
+                    assert_stack(lua.state, 2);
+                    for _ in 0..nresults {
+                        results.push_front(lua.pop_value());
+                    }
+                    Ok(results)
+                }
+                // Panics and interrupts are not error values, so they are still handled by
+                // `pop_error`.
+                ffi::LUA_ERRRUN if !is_uncatchable_error(lua.state, -1) => {
+                    assert_stack(lua.state, 2);
+                    Err(lua.pop_value())
+                }
+                ret => return Err(pop_error(lua.state, ret)),
+            }
+        };
+        match results {
+            Ok(results) => Ok(Ok(R::from_lua_multi(results, lua)?)),
+            Err(value) => Ok(Err(E::from_lua(value, lua)?)),
+        }
+    }
+
+    /// Calls the function like [`call`], invoking `on_hook` every `count` Lua instructions.
+    ///
+    /// `on_hook` decides whether the call may continue: returning `ControlFlow::Break(())` aborts
+    /// the call, which then returns `Error::RuntimeError`.  Once the call is aborted, the hook
+    /// raises the error again on every instruction until the call returns, so Lua code cannot
+    /// catch the cancellation with `pcall` and keep running.
+    ///
+    /// The hook is only set for the duration of this call, and replaces any hook set with
+    /// [`Lua::set_hook_count`] meanwhile.  The previous hook is restored when the call returns.
+    /// `count` must be greater than zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Function, Result};
+    /// # use std::ops::ControlFlow;
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let forever: Function = lua.eval("function() while true do end end", None)?;
+    ///
+    /// let mut budget = 10;
+    /// let result = forever.call_with_hook::<_, (), _>((), 1000, |_| {
+    ///     budget -= 1;
+    ///     if budget > 0 {
+    ///         ControlFlow::Continue(())
+    ///     } else {
+    ///         ControlFlow::Break(())
+    ///     }
+    /// });
+    /// assert!(result.is_err());
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    /// [`Lua::set_hook_count`]: struct.Lua.html#method.set_hook_count
+    pub fn call_with_hook<A, R, F>(&self, args: A, count: u32, on_hook: F) -> Result<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+        F: FnMut(&Lua) -> ControlFlow<()>,
+    {
+        let lua = self.0.lua;
+        let on_hook = RefCell::new(on_hook);
+        let cancelled = Cell::new(false);
+        let callback = |lua: &Lua, _: HookInfo| {
+            if !cancelled.get() {
+                let mut on_hook = on_hook
+                    .try_borrow_mut()
+                    .map_err(|_| Error::RecursiveMutCallback)?;
+                if let ControlFlow::Break(()) = (&mut *on_hook)(lua) {
+                    cancelled.set(true);
+                }
+            }
+            if cancelled.get() {
+                // Run the hook on every instruction from now on, so that the error is also raised
+                // outside of any `pcall` that caught it.
+                unsafe { set_hook_mask(lua.state, HookMask::count(1)) };
+                Err(Error::RuntimeError("call cancelled by hook".to_owned()))
+            } else {
+                Ok(())
+            }
+        };
+
+        // The callback borrows from this stack frame, which is fine since the guard removes it
+        // before returning.
+        let _hook_guard = unsafe {
+            let guard = HookGuard::new(lua.state);
+            lua.install_scoped_hook(HookMask::count(count), ScopedHookCallback::new(&callback));
+            guard
+        };
+        match self.call(args) {
+            Err(_) if cancelled.get() => Err(Error::RuntimeError(
+                "call cancelled by hook".to_owned(),
+            )),
+            res => res,
+        }
+    }
+
     /// Returns a function that, when called, calls `self`, passing `args` as the first set of
     /// arguments.
     ///
@@ -151,24 +333,285 @@ impl<'lua> Function<'lua> {
         let args = args.to_lua_multi(lua)?;
         let nargs = args.len() as c_int;
 
-        if nargs + 2 > ffi::LUA_MAX_UPVALUES {
-            return Err(Error::BindError);
-        }
-
         unsafe {
             let _sg = StackGuard::new(lua.state);
-            check_stack(lua.state, nargs + 5)?;
+            check_stack(lua.state, 2)?;
             lua.push_ref(&self.0);
-            ffi::lua_pushinteger(lua.state, nargs as ffi::lua_Integer);
+            let func = ffi::lua_gettop(lua.state);
+
+            // Binding an already bound function binds the original function with all of the
+            // arguments instead, so that repeated binds do not nest calls.
+            let rebind = match ffi::lua_tocfunction(lua.state, func) {
+                Some(f) => f as usize == bind_call_impl as ffi::lua_CFunction as usize,
+                None => false,
+            };
+            let nbound = if rebind {
+                ffi::lua_getupvalue(lua.state, func, 2);
+                let nbound = ffi::lua_tointeger(lua.state, -1) as c_int;
+                ffi::lua_pop(lua.state, 1);
+                nbound
+            } else {
+                0
+            };
+            let nbinds = nbound + nargs;
+
+            if nbinds + 2 > ffi::LUA_MAX_UPVALUES {
+                return Err(Error::BindError);
+            }
+
+            check_stack(lua.state, nbinds + 5)?;
+            if rebind {
+                ffi::lua_getupvalue(lua.state, func, 1);
+            } else {
+                ffi::lua_pushvalue(lua.state, func);
+            }
+            ffi::lua_pushinteger(lua.state, nbinds as ffi::lua_Integer);
+            for i in 0..nbound {
+                ffi::lua_getupvalue(lua.state, func, i + 3);
+            }
             for arg in args {
                 lua.push_value(arg);
             }
 
-            protect_lua_closure(lua.state, nargs + 2, 1, |state| {
-                ffi::lua_pushcclosure(state, bind_call_impl, nargs + 2);
+            protect_lua_closure(lua.state, nbinds + 2, 1, |state| {
+                ffi::lua_pushcclosure(state, bind_call_impl, nbinds + 2);
             })?;
 
             Ok(Function(lua.pop_ref()))
         }
     }
+
+    /// Dumps the function as a binary chunk of Lua bytecode.
+    ///
+    /// If `strip` is true, the chunk will not contain any debug information (source name, line
+    /// numbers, local variable and upvalue names), which makes it smaller but also makes error
+    /// messages and tracebacks less useful.
+    ///
+    /// The resulting bytes can be loaded again with [`Lua::load_bytecode`].  Be aware that the
+    /// bytecode format is specific to the Lua version and platform it was created on.
+    ///
+    /// Only functions defined in Lua can be dumped, trying to dump a Rust callback or any other C
+    /// function results in an error.
+    ///
+    /// [`Lua::load_bytecode`]: struct.Lua.html#method.load_bytecode
+    pub fn dump(&self, strip: bool) -> Result<Vec<u8>> {
+        unsafe extern "C" fn write_chunk(
+            _: *mut ffi::lua_State,
+            p: *const c_void,
+            size: usize,
+            data: *mut c_void,
+        ) -> c_int {
+            // Empty blocks, such as the missing line info of a stripped function, may be passed as
+            // a null pointer.
+            if size > 0 {
+                (*(data as *mut Vec<u8>))
+                    .extend_from_slice(slice::from_raw_parts(p as *const u8, size));
+            }
+            0
+        }
+
+        let lua = self.0.lua;
+        let chunk = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+
+            lua.push_ref(&self.0);
+            if ffi::lua_iscfunction(lua.state, -1) != 0 {
+                return Err(Error::RuntimeError(
+                    "unable to dump a C function".to_owned(),
+                ));
+            }
+
+            let mut chunk = Vec::new();
+            ffi::lua_dump(
+                lua.state,
+                write_chunk,
+                &mut chunk as *mut Vec<u8> as *mut c_void,
+            );
+            chunk
+        };
+
+        if strip {
+            bytecode::strip(&chunk)
+        } else {
+            Ok(chunk)
+        }
+    }
+
+    /// Returns a copy of this function without debug information.
+    ///
+    /// This dumps the function with [`dump`] and loads the stripped bytecode again, so a chunk
+    /// can be compiled and stripped in one step.  The copy behaves like the original and shares
+    /// its environment, but it takes less memory, since it carries no line numbers or local
+    /// variable names.  In exchange, error messages and tracebacks raised from it have no line
+    /// numbers, and debug hooks and [`info`] cannot report where it was defined.  The original
+    /// function is not modified.
+    ///
+    /// Lua 5.1 cannot set the upvalues of a stripped function, so only functions without upvalues,
+    /// such as chunks returned by [`Lua::load`], can be stripped; other Lua functions and C
+    /// functions result in an error.
+    ///
+    /// [`dump`]: #method.dump
+    /// [`info`]: #method.info
+    /// [`Lua::load`]: struct.Lua.html#method.load
+    pub fn strip_debug(&self) -> Result<Function<'lua>> {
+        let lua = self.0.lua;
+        let has_upvalues = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            lua.push_ref(&self.0);
+            rlua_assert!(
+                ffi::lua_getinfo(lua.state, cstr!(">u"), &mut ar) != 0,
+                "lua_getinfo failed with valid options"
+            );
+            ar.nups != 0
+        };
+        if has_upvalues && !self.is_c_function() {
+            return Err(Error::RuntimeError(
+                "unable to strip a function with upvalues".to_owned(),
+            ));
+        }
+
+        let stripped = lua.load_bytecode(&self.dump(true)?, None)?;
+        stripped.set_environment(self.get_environment()?)?;
+        Ok(stripped)
+    }
+
+    /// Returns information about this function: where it was defined and which parameters it
+    /// declares.
+    ///
+    /// This can be used to check that a function provided by a script has the expected signature
+    /// before calling it.  For Rust functions and other C functions, `is_c` is set and there is no
+    /// source or line information.
+    ///
+    /// The parameters of a Lua function are read from its dumped bytecode, so this returns an error
+    /// if dumping the function fails, for example because of a memory error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Function, Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let callback: Function = lua.eval("function(event, ...) end", Some("script"))?;
+    ///
+    /// let info = callback.info()?;
+    /// assert!(!info.is_c);
+    /// assert_eq!(info.num_params, 1);
+    /// assert!(info.is_vararg);
+    /// assert_eq!(info.line_defined, Some(1));
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn info(&self) -> Result<FunctionInfo> {
+        let lua = self.0.lua;
+        let signature = if self.is_c_function() {
+            None
+        } else {
+            // The parameters are only recorded in the prototype of the function, so they are read
+            // from its binary chunk.
+            Some(bytecode::signature(&self.dump(false)?)?)
+        };
+
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            lua.push_ref(&self.0);
+            rlua_assert!(
+                ffi::lua_getinfo(lua.state, cstr!(">Su"), &mut ar) != 0,
+                "lua_getinfo failed with valid options"
+            );
+            Ok(FunctionInfo::from_lua_debug(&ar, signature))
+        }
+    }
+
+    fn is_c_function(&self) -> bool {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+            lua.push_ref(&self.0);
+            ffi::lua_iscfunction(lua.state, -1) != 0
+        }
+    }
+
+    /// Returns the environment of this function, the table used to look up its global variables.
+    ///
+    /// Functions defined in Lua inherit the environment of the function that created them, which
+    /// is the globals table unless it was changed with [`set_environment`].
+    ///
+    /// Returns an error for Rust functions and other C functions.
+    ///
+    /// [`set_environment`]: #method.set_environment
+    pub fn get_environment(&self) -> Result<Table<'lua>> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 2);
+
+            lua.push_ref(&self.0);
+            if ffi::lua_iscfunction(lua.state, -1) != 0 {
+                return Err(Error::RuntimeError(
+                    "C functions have no environment".to_owned(),
+                ));
+            }
+            ffi::lua_getfenv(lua.state, -1);
+            Ok(Table(lua.pop_ref()))
+        }
+    }
+
+    /// Sets the environment of this function, the table used to look up its global variables.
+    ///
+    /// This can be used to run a chunk with its own set of globals, for example a table containing
+    /// only the functions that a sandboxed script may use.  Functions created by the chunk after
+    /// this call inherit the new environment.  The function keeps a reference to `env`, so it
+    /// stays alive as long as the function does.
+    ///
+    /// Returns an error for Rust functions and other C functions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{ChunkMode, Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let chunk = lua.load(b"x = 1; return print", None, ChunkMode::Text)?;
+    ///
+    /// let env = lua.create_table()?;
+    /// chunk.set_environment(env.clone())?;
+    /// assert!(chunk.call::<_, Option<rlua::Function>>(())?.is_none());
+    /// assert_eq!(env.get::<_, i64>("x")?, 1);
+    /// assert!(lua.globals().get::<_, Option<i64>>("x")?.is_none());
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn set_environment(&self, env: Table<'lua>) -> Result<()> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 2);
+
+            lua.push_ref(&self.0);
+            if ffi::lua_iscfunction(lua.state, -1) != 0 {
+                return Err(Error::RuntimeError(
+                    "cannot set the environment of a C function".to_owned(),
+                ));
+            }
+            lua.push_ref(&env.0);
+            ffi::lua_setfenv(lua.state, -2);
+            Ok(())
+        }
+    }
 }
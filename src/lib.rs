@@ -19,6 +19,12 @@
 //! Most code in `rlua` is generic over implementors of those traits, so in most places the normal
 //! Rust data structures are accepted without having to write any boilerplate.
 //!
+//! With the optional `serde` feature enabled, `to_value` and `from_value` convert between Lua
+//! values and any Rust type implementing serde's `Serialize` and `Deserialize` traits.
+//!
+//! With the optional `async` feature enabled, [`Lua::create_async_function`] wraps Rust functions
+//! returning futures, which can be called from Lua coroutines driven by an [`AsyncThread`].
+//!
 //! # Custom Userdata
 //!
 //! The [`UserData`] trait can be implemented by user-defined types to make them available to Lua.
@@ -35,6 +41,8 @@
 //! [`FromLuaMulti`]: trait.FromLuaMulti.html
 //! [`UserData`]: trait.UserData.html
 //! [`UserDataMethods`]: struct.UserDataMethods.html
+//! [`Lua::create_async_function`]: struct.Lua.html#method.create_async_function
+//! [`AsyncThread`]: struct.AsyncThread.html
 
 // Deny warnings inside doc tests / examples. When this isn't present, rustdoc doesn't show *any*
 // warnings at all.
@@ -42,16 +50,27 @@
 
 extern crate failure;
 extern crate libc;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 
 mod error;
 mod ffi;
 #[macro_use]
 mod macros;
+mod bytecode;
 mod conversion;
+#[cfg(feature = "serde")]
+mod de;
+mod debug;
 mod function;
 mod lua;
 mod multi;
+mod sandbox;
 mod scope;
+#[cfg(feature = "serde")]
+mod ser;
+mod stdlib;
 mod string;
 mod table;
 mod thread;
@@ -59,14 +78,25 @@ mod types;
 mod util;
 mod value;
 
+#[cfg(feature = "serde")]
+pub use de::from_value;
+pub use debug::{DebugInfo, FunctionInfo, HookEvent, HookInfo, HookMask};
 pub use error::{Error, ExternalError, ExternalResult, Result};
 pub use function::Function;
-pub use lua::Lua;
+pub use lua::{
+    ChunkMode, GlobalsSnapshot, InterruptHandle, Lua, LuaBuilder, PanicPolicy, ReplResult,
+};
 pub use multi::Variadic;
+pub use sandbox::Limits;
 pub use scope::Scope;
+pub use stdlib::StdLib;
+#[cfg(feature = "serde")]
+pub use ser::to_value;
 pub use string::String;
-pub use table::{Table, TablePairs, TableSequence};
-pub use thread::{Thread, ThreadStatus};
+pub use table::{Entry, OccupiedEntry, Table, TablePairs, TableSequence, VacantEntry, WeakMode};
+#[cfg(feature = "async")]
+pub use thread::AsyncThread;
+pub use thread::{Thread, ThreadIter, ThreadStatus};
 pub use types::{Integer, LightUserData, Number, RegistryKey};
 pub use value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
 
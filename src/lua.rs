@@ -1,24 +1,48 @@
-use std::cell::{RefCell, UnsafeCell};
+use std::any::{Any, TypeId};
+use std::cell::{Cell, Ref, RefCell, RefMut, UnsafeCell};
+use std::collections::HashMap;
 use std::ffi::CString;
+use std::fs::File;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
 use std::marker::PhantomData;
 use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::rc::Rc;
+use std::result::Result as StdResult;
+use std::string::String as StdString;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::{mem, ptr, str};
+#[cfg(feature = "async")]
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+use std::{cmp, mem, ptr, str};
 
+use failure;
 use libc;
 
+use debug::{DebugInfo, HookInfo, HookMask};
 use error::{Error, Result};
 use ffi;
 use function::Function;
+use multi::Variadic;
+use sandbox::Limits;
 use scope::Scope;
+use stdlib::StdLib;
 use string::String;
-use table::Table;
+use table::{Table, WeakMode};
 use thread::Thread;
-use types::{Callback, Integer, LightUserData, LuaRef, Number, RegistryKey};
+use types::{
+    Callback, ErrorConverter, HookCallback, Integer, LightUserData, LuaRef, Number, RegistryKey,
+    ScopedHookCallback,
+};
 use util::{
     assert_stack, callback_error, check_stack, gc_guard, get_userdata, get_wrapped_error,
-    init_error_metatables, pop_error, protect_lua, protect_lua_closure, push_string, push_userdata,
-    push_wrapped_error, safe_pcall, safe_xpcall, userdata_destructor, StackGuard,
+    grow_stack, init_error_metatables, pop_error, protect_lua, protect_lua_closure, push_string,
+    push_userdata, push_wrapped_error, safe_pcall, safe_xpcall, userdata_destructor, StackGuard,
 };
 use value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
 
@@ -33,6 +57,148 @@ pub struct Lua {
 
 unsafe impl Send for Lua {}
 
+/// Configures and creates a new Lua state, returned by [`Lua::builder`].
+///
+/// [`Lua::builder`]: struct.Lua.html#method.builder
+#[derive(Debug, Clone)]
+pub struct LuaBuilder {
+    libs: StdLib,
+    load_debug: bool,
+    memory_limit: Option<usize>,
+}
+
+impl LuaBuilder {
+    /// Sets the standard libraries to load, `StdLib::ALL` by default.
+    pub fn stdlib(mut self, libs: StdLib) -> LuaBuilder {
+        self.libs = libs;
+        self
+    }
+
+    /// Sets whether to load the `debug` library, which is not loaded by default.
+    ///
+    /// The debug library is very unsound, loading it and using it breaks all the guarantees of
+    /// rlua.
+    pub unsafe fn load_debug(mut self, load_debug: bool) -> LuaBuilder {
+        self.load_debug = load_debug;
+        self
+    }
+
+    /// Sets a limit on the amount of memory (in bytes) the Lua state may allocate.
+    ///
+    /// See [`Lua::set_memory_limit`] for details.  The memory used by the new state itself counts
+    /// towards the limit.
+    ///
+    /// [`Lua::set_memory_limit`]: struct.Lua.html#method.set_memory_limit
+    pub fn memory_limit(mut self, bytes: usize) -> LuaBuilder {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Creates the Lua state.
+    ///
+    /// Returns `Error::RuntimeError` if the memory limit is lower than the memory used by the new
+    /// state.
+    pub fn build(self) -> Result<Lua> {
+        let lua = unsafe { create_lua(self.libs, self.load_debug) };
+        if let Some(limit) = self.memory_limit {
+            let used_memory = unsafe { (*memory_info(lua.state)).used_memory };
+            if used_memory > limit {
+                return Err(Error::RuntimeError(format!(
+                    "memory limit of {} bytes is lower than the {} bytes used by a new Lua state",
+                    limit, used_memory
+                )));
+            }
+            lua.set_memory_limit(Some(limit));
+        }
+        Ok(lua)
+    }
+}
+
+/// The kinds of chunks accepted by [`Lua::load`].
+///
+/// [`Lua::load`]: struct.Lua.html#method.load
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChunkMode {
+    /// Only accept chunks of Lua source code.
+    Text,
+    /// Only accept precompiled binary chunks.
+    Binary,
+    /// Accept both text and binary chunks.
+    Both,
+}
+
+/// The outcome of evaluating a line of interactive input with [`Lua::eval_repl`].
+///
+/// [`Lua::eval_repl`]: struct.Lua.html#method.eval_repl
+#[derive(Debug)]
+pub enum ReplResult<'lua> {
+    /// The input was run, returning these values.
+    Values(MultiValue<'lua>),
+    /// The input is the beginning of a valid chunk, more input should be appended to it before
+    /// evaluating it again.
+    Incomplete,
+    /// The input could not be compiled, or running it raised an error.
+    Error(Error),
+}
+
+/// A copy of the global environment taken with [`Lua::snapshot_globals`], which can be put back
+/// with [`Lua::restore_globals`].
+///
+/// The copy is stored in the registry, so like a `RegistryKey` a snapshot does not borrow the
+/// `Lua` it was taken from, and the copy is removed from the registry like the value of a dropped
+/// `RegistryKey`.
+///
+/// [`Lua::snapshot_globals`]: struct.Lua.html#method.snapshot_globals
+/// [`Lua::restore_globals`]: struct.Lua.html#method.restore_globals
+#[derive(Debug)]
+pub struct GlobalsSnapshot(RegistryKey);
+
+/// A handle for interrupting Lua code running in a `Lua` state, returned by
+/// [`Lua::interrupt_handle`].
+///
+/// Unlike the `Lua` itself, a handle can be sent to, and used from, any thread.  All handles of a
+/// `Lua` share the same flag: once any of them is cancelled, all Lua code running in the state is
+/// aborted with `Error::Interrupted` until the flag is [reset].
+///
+/// [`Lua::interrupt_handle`]: struct.Lua.html#method.interrupt_handle
+/// [reset]: #method.reset
+#[derive(Debug, Clone)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Aborts the Lua code running in the state, or the next Lua code to run if none is running.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if the handle has been cancelled and not reset since.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Clears the flag set by `cancel`, so that Lua code can run again.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// What happens to a panic in a Rust callback once it reaches the Rust code that called into Lua,
+/// set with [`Lua::set_panic_handler`].
+///
+/// In either case the panic never unwinds through Lua itself, and Lua code cannot catch it with
+/// `pcall` or `xpcall`.
+///
+/// [`Lua::set_panic_handler`]: struct.Lua.html#method.set_panic_handler
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// The original panic is resumed, so it keeps unwinding in Rust as if no Lua code had been in
+    /// between.  This is the default.
+    Resume,
+    /// The panic is discarded and `Error::CallbackPanic` is returned instead, carrying the panic
+    /// message.
+    Convert,
+}
+
 impl Drop for Lua {
     fn drop(&mut self) {
         unsafe {
@@ -44,9 +210,11 @@ impl Drop for Lua {
                     "reference leak detected"
                 );
                 *(*extra).registry_unref_list.lock().unwrap() = None;
-                Box::from_raw(extra);
+                let mem_info = (*extra).mem_info;
+                drop(Box::from_raw(extra));
 
                 ffi::lua_close(self.state);
+                drop(Box::from_raw(mem_info));
             }
         }
     }
@@ -55,7 +223,7 @@ impl Drop for Lua {
 impl Lua {
     /// Creates a new Lua state and loads standard library without the `debug` library.
     pub fn new() -> Lua {
-        unsafe { create_lua(false) }
+        unsafe { create_lua(StdLib::ALL, false) }
     }
 
     /// Creates a new Lua state and loads the standard library including the `debug` library.
@@ -63,41 +231,399 @@ impl Lua {
     /// The debug library is very unsound, loading it and using it breaks all the guarantees of
     /// rlua.
     pub unsafe fn new_with_debug() -> Lua {
-        create_lua(true)
+        create_lua(StdLib::ALL, true)
+    }
+
+    /// Returns a builder to configure a new Lua state, for example to only load some of the
+    /// standard libraries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result, StdLib};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::builder()
+    ///     .stdlib(StdLib::BASE | StdLib::STRING | StdLib::TABLE | StdLib::MATH)
+    ///     .memory_limit(16 * 1024 * 1024)
+    ///     .build()?;
+    ///
+    /// assert_eq!(lua.eval::<String>("string.rep('a', 3)", None)?, "aaa");
+    /// assert!(lua.eval::<bool>("os == nil and io == nil", None)?);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn builder() -> LuaBuilder {
+        LuaBuilder {
+            libs: StdLib::ALL,
+            load_debug: false,
+            memory_limit: None,
+        }
+    }
+
+    /// Runs an untrusted script in a new, restricted Lua state and returns its results.
+    ///
+    /// The state only loads the libraries in `limits.libs` and enforces the memory, instruction
+    /// and time limits of `limits`.  The `dofile`, `loadfile`, `load` and `loadstring` functions
+    /// are removed, since they could read files or load bytecode, which Lua does not verify, and
+    /// `source` itself must be a text chunk.  The state is dropped before this returns, so the
+    /// results are converted to a type that does not borrow the state, such as `(i64, String)`.
+    ///
+    /// Exceeding the instruction or time limit returns `Error::Timeout`.  Once either limit is
+    /// exceeded the error is raised again on every instruction, so the script cannot keep running
+    /// by catching it with `pcall`.  Running out of memory returns `Error::MemoryError`, like
+    /// with [`set_memory_limit`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Error, Limits, Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let limits = Limits {
+    ///     max_memory: Some(1024 * 1024),
+    ///     max_instructions: Some(100_000),
+    ///     ..Limits::default()
+    /// };
+    ///
+    /// let sum = Lua::run_untrusted::<i64>("return 1 + 2", limits.clone())?;
+    /// assert_eq!(sum, 3);
+    ///
+    /// match Lua::run_untrusted::<()>("while true do pcall(error) end", limits) {
+    ///     Err(Error::Timeout) => {}
+    ///     r => panic!("unexpected result {:?}", r),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`set_memory_limit`]: #method.set_memory_limit
+    pub fn run_untrusted<R>(source: &str, limits: Limits) -> Result<R>
+    where
+        R: for<'lua> FromLuaMulti<'lua>,
+    {
+        const CHECK_INTERVAL: u64 = 1000;
+
+        let mut builder = Lua::builder().stdlib(limits.libs);
+        if let Some(bytes) = limits.max_memory {
+            builder = builder.memory_limit(bytes);
+        }
+        let lua = builder.build()?;
+
+        let globals = lua.globals();
+        for name in &["dofile", "loadfile", "load", "loadstring"] {
+            globals.raw_set(*name, Nil)?;
+        }
+
+        if limits.max_instructions.is_some() || limits.timeout.is_some() {
+            let max_instructions = limits.max_instructions;
+            let interval = cmp::max(
+                cmp::min(max_instructions.unwrap_or(CHECK_INTERVAL), CHECK_INTERVAL),
+                1,
+            );
+            let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+            let executed = Cell::new(0);
+            lua.set_hook_count(interval as u32, move |lua| {
+                executed.set(executed.get() + interval);
+                if max_instructions.map_or(false, |max| executed.get() >= max)
+                    || deadline.map_or(false, |deadline| Instant::now() >= deadline)
+                {
+                    lua.set_hook_count(1, |_| Err(Error::Timeout));
+                    Err(Error::Timeout)
+                } else {
+                    Ok(())
+                }
+            });
+        }
+
+        let result = lua
+            .load(source.as_bytes(), Some("untrusted"), ChunkMode::Text)
+            .and_then(|chunk| chunk.call(()));
+        if let Err(Error::CallbackError { ref cause, .. }) = result {
+            if let Error::Timeout = **cause {
+                return Err(Error::Timeout);
+            }
+        }
+        result
+    }
+
+    /// Loads the given standard libraries into this state, in addition to the libraries that are
+    /// already loaded.
+    ///
+    /// Each library is only opened once, loading a library that is already loaded only sets its
+    /// global again.  Loading `StdLib::BASE` sets up `_G` and replaces `pcall` and `xpcall` with
+    /// versions that cannot catch Rust panics, like `Lua::new` does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result, StdLib};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::builder().stdlib(StdLib::NONE).build()?;
+    /// lua.load_stdlib(StdLib::BASE | StdLib::TABLE | StdLib::STRING | StdLib::MATH)?;
+    ///
+    /// assert!(lua.eval::<bool>("_G == _G._G and os == nil", None)?);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn load_stdlib(&self, libs: StdLib) -> Result<()> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 3);
+            protect_lua_closure(self.state, 0, 0, |state| load_std_libs(state, libs))
+        }
     }
 
     /// Loads a chunk of Lua code and returns it as a function.
     ///
     /// The source can be named by setting the `name` parameter. This is generally recommended as it
-    /// results in better error traces.
+    /// results in better error traces.  Lua treats a name starting with `@` as a file name and a
+    /// name starting with `=` as a name to be shown as is, any other name is prefixed with `=` so
+    /// that error messages start with `name:line:`.
+    ///
+    /// `mode` controls whether `source` may be a text chunk, a binary chunk (precompiled bytecode),
+    /// or both.  A chunk of the wrong kind is rejected with `Error::SyntaxError` without being
+    /// parsed.  Since Lua does not verify bytecode and malicious bytecode can crash the
+    /// interpreter, use `ChunkMode::Text` for any untrusted input.
     ///
     /// Equivalent to Lua's `load` function.
-    pub fn load(&self, source: &str, name: Option<&str>) -> Result<Function> {
+    pub fn load(&self, source: &[u8], name: Option<&str>, mode: ChunkMode) -> Result<Function> {
+        let name = match name {
+            Some(name) => Some(chunk_name(name)?),
+            None => None,
+        };
+        let mode = match mode {
+            ChunkMode::Text => cstr!("t"),
+            ChunkMode::Binary => cstr!("b"),
+            ChunkMode::Both => cstr!("bt"),
+        };
+
         unsafe {
             let _sg = StackGuard::new(self.state);
             assert_stack(self.state, 1);
 
-            match if let Some(name) = name {
-                let name =
-                    CString::new(name.to_owned()).map_err(|e| Error::ToLuaConversionError {
-                        from: "&str",
-                        to: "string",
-                        message: Some(e.to_string()),
-                    })?;
-                ffi::luaL_loadbuffer(
-                    self.state,
-                    source.as_ptr() as *const c_char,
-                    source.len(),
-                    name.as_ptr(),
-                )
-            } else {
-                ffi::luaL_loadbuffer(
-                    self.state,
-                    source.as_ptr() as *const c_char,
-                    source.len(),
-                    ptr::null(),
-                )
-            } {
+            match ffi::luaL_loadbufferx(
+                self.state,
+                source.as_ptr() as *const c_char,
+                source.len(),
+                name.as_ref().map_or(ptr::null(), |n| n.as_ptr()),
+                mode,
+            ) {
+                ffi::LUA_OK => Ok(Function(self.pop_ref())),
+                err => Err(pop_error(self.state, err)),
+            }
+        }
+    }
+
+    /// Loads a precompiled chunk of Lua bytecode (such as the output of `luac`) and returns it as
+    /// a function.
+    ///
+    /// Only binary chunks are accepted: if `bytes` does not start with the Lua bytecode signature,
+    /// this returns `Error::RuntimeError` without ever invoking the Lua parser.  Malformed chunks,
+    /// or chunks compiled for a different platform or Lua version, result in an error as well.
+    ///
+    /// The chunk can be named by setting the `name` parameter, in the same way as with [`load`].
+    ///
+    /// [`load`]: #method.load
+    pub fn load_bytecode(&self, bytes: &[u8], name: Option<&str>) -> Result<Function> {
+        unsafe extern "C" fn read_chunk(
+            _: *mut ffi::lua_State,
+            data: *mut c_void,
+            size: *mut usize,
+        ) -> *const c_char {
+            // The whole chunk is handed out with the first call, every further call signals the
+            // end of the chunk.
+            match (*(data as *mut Option<&[u8]>)).take() {
+                Some(chunk) => {
+                    *size = chunk.len();
+                    chunk.as_ptr() as *const c_char
+                }
+                None => {
+                    *size = 0;
+                    ptr::null()
+                }
+            }
+        }
+
+        if !bytes.starts_with(ffi::LUA_SIGNATURE) {
+            return Err(Error::RuntimeError(
+                "attempt to load a text chunk as bytecode".to_owned(),
+            ));
+        }
+
+        let name = match name {
+            Some(name) => Some(chunk_name(name)?),
+            None => None,
+        };
+
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+
+            let mut chunk = Some(bytes);
+            match ffi::lua_load(
+                self.state,
+                read_chunk,
+                &mut chunk as *mut Option<&[u8]> as *mut c_void,
+                name.as_ref().map_or(ptr::null(), |n| n.as_ptr()),
+            ) {
+                ffi::LUA_OK => Ok(Function(self.pop_ref())),
+                err => Err(pop_error(self.state, err)),
+            }
+        }
+    }
+
+    /// Loads a chunk of Lua code from a `Read` implementation and returns it as a function.
+    ///
+    /// The source is read incrementally in fixed-size blocks while Lua parses it, so the whole
+    /// chunk never has to be held in memory at once.  The chunk can be named by setting the `name`
+    /// parameter, and `mode` selects whether it may be text or precompiled bytecode, in the same
+    /// way as with [`load`].  A chunk is binary if it starts with the `"\x1bLua"` signature, and
+    /// such a chunk is rejected with `Error::SyntaxError` unless `mode` allows bytecode.  Lua does
+    /// not verify bytecode, and malicious bytecode can crash the interpreter or corrupt memory, so
+    /// only pass `ChunkMode::Binary` or `ChunkMode::Both` for readers whose contents are trusted.
+    ///
+    /// If reading from `reader` fails, loading is aborted and the IO error is returned as an
+    /// `Error::ExternalError`.
+    ///
+    /// [`load`]: #method.load
+    pub fn load_from_reader<R: Read>(
+        &self,
+        reader: R,
+        name: Option<&str>,
+        mode: ChunkMode,
+    ) -> Result<Function> {
+        struct ReaderState<R> {
+            reader: R,
+            buffer: [u8; READER_BUFFER_SIZE],
+            mode: ChunkMode,
+            started: bool,
+            rejected: Option<&'static str>,
+            error: Option<io::Error>,
+            panic: Option<Box<Any + Send>>,
+        }
+
+        unsafe extern "C" fn read_block<R: Read>(
+            _: *mut ffi::lua_State,
+            data: *mut c_void,
+            size: *mut usize,
+        ) -> *const c_char {
+            let state = &mut *(data as *mut ReaderState<R>);
+            *size = 0;
+            if state.rejected.is_some() || state.error.is_some() || state.panic.is_some() {
+                return ptr::null();
+            }
+
+            // Panics must not unwind through `lua_load`, so they are caught here and resumed once
+            // Lua has returned.
+            let read = {
+                let ReaderState {
+                    ref mut reader,
+                    ref mut buffer,
+                    ..
+                } = *state;
+                catch_unwind(AssertUnwindSafe(|| loop {
+                    match reader.read(buffer) {
+                        Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        res => return res,
+                    }
+                }))
+            };
+
+            match read {
+                Ok(Ok(0)) => ptr::null(),
+                Ok(Ok(n)) => {
+                    // The kind of the chunk is decided by its first byte, as Lua itself does.
+                    if !state.started {
+                        state.started = true;
+                        let binary = state.buffer[0] == ffi::LUA_SIGNATURE[0];
+                        let allowed = match state.mode {
+                            ChunkMode::Text => !binary,
+                            ChunkMode::Binary => binary,
+                            ChunkMode::Both => true,
+                        };
+                        if !allowed {
+                            state.rejected = Some(if binary { "binary" } else { "text" });
+                            return ptr::null();
+                        }
+                    }
+                    // The returned block points into `state.buffer`, which stays alive and is not
+                    // written to again until Lua asks for the next block.
+                    *size = n;
+                    state.buffer.as_ptr() as *const c_char
+                }
+                Ok(Err(err)) => {
+                    state.error = Some(err);
+                    ptr::null()
+                }
+                Err(p) => {
+                    state.panic = Some(p);
+                    ptr::null()
+                }
+            }
+        }
+
+        let name = match name {
+            Some(name) => Some(chunk_name(name)?),
+            None => None,
+        };
+
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+
+            let mut state = ReaderState {
+                reader,
+                buffer: [0; READER_BUFFER_SIZE],
+                mode,
+                started: false,
+                rejected: None,
+                error: None,
+                panic: None,
+            };
+            let res = ffi::lua_load(
+                self.state,
+                read_block::<R>,
+                &mut state as *mut ReaderState<R> as *mut c_void,
+                name.as_ref().map_or(ptr::null(), |n| n.as_ptr()),
+            );
+
+            if let Some(p) = state.panic {
+                resume_unwind(p);
+            }
+            if let Some(err) = state.error {
+                // Whatever Lua managed to parse before the read failed is discarded.
+                return Err(err.into());
+            }
+            // An empty source counts as text, as with `load`.
+            let rejected = match state.rejected {
+                None if !state.started && mode == ChunkMode::Binary => Some("text"),
+                rejected => rejected,
+            };
+            if let Some(kind) = rejected {
+                // Lua has loaded an empty chunk, which the stack guard removes.
+                let mode = match mode {
+                    ChunkMode::Text => "t",
+                    ChunkMode::Binary => "b",
+                    ChunkMode::Both => "bt",
+                };
+                return Err(Error::SyntaxError {
+                    message: format!("attempt to load a {} chunk (mode is '{}')", kind, mode),
+                    line: None,
+                    incomplete_input: false,
+                });
+            }
+            match res {
                 ffi::LUA_OK => Ok(Function(self.pop_ref())),
                 err => Err(pop_error(self.state, err)),
             }
@@ -106,8 +632,8 @@ impl Lua {
 
     /// Execute a chunk of Lua code.
     ///
-    /// This is equivalent to simply loading the source with `load` and then calling the resulting
-    /// function with no arguments.
+    /// This is equivalent to simply loading the source with `load` (accepting only text chunks)
+    /// and then calling the resulting function with no arguments.
     ///
     /// Returns the values returned by the chunk.
     pub fn exec<'lua, R: FromLuaMulti<'lua>>(
@@ -115,28 +641,191 @@ impl Lua {
         source: &str,
         name: Option<&str>,
     ) -> Result<R> {
-        self.load(source, name)?.call(())
+        self.compile_cached(source, name, false)?.call(())
+    }
+
+    /// Loads and executes several chunks of Lua code in order, stopping at the first failure.
+    ///
+    /// Each item of `chunks` is a name and the source of a text chunk, such as the path and the
+    /// contents of a script file.  All chunks run in the same global environment, so later chunks
+    /// can use what earlier ones defined.  If a chunk fails to load or raises an error, the error
+    /// is returned as `Error::ChunkError` with the name of that chunk, and no further chunks are
+    /// run.
+    /// Changes made by the chunks that already ran, including the part of the failing chunk that
+    /// ran before the error, are not rolled back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Error, Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let result = lua.exec_all(vec![
+    ///     ("config.lua", "width = 80"),
+    ///     ("layout.lua", "columns = width / column_width"),
+    ///     ("never.lua", "ran = true"),
+    /// ]);
+    /// match result {
+    ///     Err(Error::ChunkError { ref name, .. }) => assert_eq!(name, "layout.lua"),
+    ///     r => panic!("unexpected result {:?}", r),
+    /// }
+    /// assert_eq!(lua.globals().get::<_, i64>("width")?, 80);
+    /// assert_eq!(lua.globals().get::<_, Option<bool>>("ran")?, None);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn exec_all<I, N, S>(&self, chunks: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (N, S)>,
+        N: AsRef<str>,
+        S: AsRef<str>,
+    {
+        for (name, source) in chunks {
+            let name = name.as_ref();
+            self.load(source.as_ref().as_bytes(), Some(name), ChunkMode::Text)
+                .and_then(|chunk| chunk.call::<_, ()>(()))
+                .map_err(|err| Error::ChunkError {
+                    name: name.to_owned(),
+                    cause: Arc::new(err),
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Loads and executes the Lua source file at `path`.
+    ///
+    /// The file is read with [`load_from_reader`] and must contain source code: precompiled
+    /// bytecode is rejected with `Error::SyntaxError`, use [`load_from_reader`] with a
+    /// [`ChunkMode`] allowing binary chunks to run trusted bytecode files.  The chunk is named
+    /// after the file name of `path` without its leading directories, so error messages and
+    /// tracebacks show `script.lua:line:` instead of `[string "..."]` or a long absolute path.  If
+    /// the file cannot be opened or read, the IO error is returned as an `Error::ExternalError`.
+    ///
+    /// [`load_from_reader`]: #method.load_from_reader
+    /// [`ChunkMode`]: enum.ChunkMode.html
+    pub fn exec_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let name = match path.file_name() {
+            Some(file_name) => format!("@{}", Path::new(file_name).display()),
+            None => format!("@{}", path.display()),
+        };
+        self.load_from_reader(file, Some(&name), ChunkMode::Text)?.call(())
     }
 
     /// Evaluate the given expression or chunk inside this Lua state.
     ///
     /// If `source` is an expression, returns the value it evaluates to. Otherwise, returns the
     /// values returned by the chunk (if any).
+    ///
+    /// The values are converted to `R` like the results of any function call: a tuple takes as
+    /// many values as it has elements, filling missing ones with `nil` and discarding the rest.  To
+    /// get every value regardless of how many the chunk returns, use [`eval_multi`].
+    ///
+    /// [`eval_multi`]: #method.eval_multi
     pub fn eval<'lua, R: FromLuaMulti<'lua>>(
         &'lua self,
         source: &str,
         name: Option<&str>,
     ) -> Result<R> {
-        // First, try interpreting the lua as an expression by adding
-        // "return", then as a statement.  This is the same thing the
-        // actual lua repl does.
-        self.load(&format!("return {}", source), name)
-            .or_else(|_| self.load(source, name))?
-            .call(())
+        self.compile_cached(source, name, true)?.call(())
+    }
+
+    /// Evaluate the given expression or chunk inside this Lua state, returning all of the values
+    /// it produces.
+    ///
+    /// This is the same as [`eval`] with a `MultiValue` target, which is convenient for scripts
+    /// whose number of return values is not known in advance.
+    ///
+    /// [`eval`]: #method.eval
+    pub fn eval_multi<'lua>(
+        &'lua self,
+        source: &str,
+        name: Option<&str>,
+    ) -> Result<MultiValue<'lua>> {
+        self.eval(source, name)
+    }
+
+    /// Sets the number of chunks compiled by `exec` and `eval` that are kept for reuse.
+    ///
+    /// When the cache is enabled, running the same source with the same chunk name again reuses
+    /// the compiled chunk instead of parsing the source again.  Once the cache is full, the least
+    /// recently used chunk is discarded.  A size of 0, the default, disables the cache and discards
+    /// all chunks in it.
+    ///
+    /// Every run of a cached chunk is a new call of it, so its local variables, and any closures it
+    /// creates, are fresh on every run, exactly as if the source had been compiled again.
+    pub fn set_compiled_cache_size(&self, entries: usize) {
+        unsafe {
+            let extra = extra_data(self.state);
+            (*extra).compiled_cache.capacity = entries;
+            while (*extra).compiled_cache.entries.len() > entries {
+                (*extra).compiled_cache.evict(self.state);
+            }
+        }
+    }
+
+    /// Evaluate a line of input the way the interactive `lua` interpreter does.
+    ///
+    /// `line` is first compiled as `return <line>`, so that a bare expression evaluates to its
+    /// values, and otherwise as a sequence of statements.  If `line` is only the beginning of a
+    /// valid chunk, such as the first line of a function definition, `ReplResult::Incomplete` is
+    /// returned, and the caller should read another line, append it to `line` separated by a
+    /// newline, and try again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, ReplResult};
+    /// # fn main() {
+    /// let lua = Lua::new();
+    ///
+    /// match lua.eval_repl("function add(a, b)") {
+    ///     ReplResult::Incomplete => {}
+    ///     r => panic!("unexpected {:?}", r),
+    /// }
+    /// match lua.eval_repl("function add(a, b)\nreturn a + b end") {
+    ///     ReplResult::Values(values) => assert!(values.is_empty()),
+    ///     r => panic!("unexpected {:?}", r),
+    /// }
+    /// match lua.eval_repl("add(1, 2)") {
+    ///     ReplResult::Values(values) => assert_eq!(values.len(), 1),
+    ///     r => panic!("unexpected {:?}", r),
+    /// };
+    /// # }
+    /// ```
+    pub fn eval_repl<'lua>(&'lua self, line: &str) -> ReplResult<'lua> {
+        let name = Some("stdin");
+        let chunk = match self.load(format!("return {}", line).as_bytes(), name, ChunkMode::Text) {
+            Ok(chunk) => chunk,
+            Err(_) => match self.load(line.as_bytes(), name, ChunkMode::Text) {
+                Ok(chunk) => chunk,
+                Err(Error::SyntaxError {
+                    incomplete_input: true,
+                    ..
+                }) => return ReplResult::Incomplete,
+                Err(err) => return ReplResult::Error(err),
+            },
+        };
+        match chunk.call(()) {
+            Ok(values) => ReplResult::Values(values),
+            Err(err) => ReplResult::Error(err),
+        }
     }
 
-    /// Pass a `&str` slice to Lua, creating and returning an interned Lua string.
-    pub fn create_string(&self, s: &str) -> Result<String> {
+    /// Pass a string slice to Lua, creating and returning an interned Lua string.
+    ///
+    /// Lua strings are byte strings, so besides `&str` this accepts anything that can be viewed as
+    /// bytes, such as `&[u8]`, and the bytes do not need to be valid UTF-8.
+    pub fn create_string<S>(&self, s: &S) -> Result<String>
+    where
+        S: ?Sized + AsRef<[u8]>,
+    {
         unsafe {
             let _sg = StackGuard::new(self.state);
             assert_stack(self.state, 4);
@@ -145,59 +834,139 @@ impl Lua {
         }
     }
 
-    /// Creates and returns a new table.
-    pub fn create_table(&self) -> Result<Table> {
+    /// Returns a handle to a Lua string with the contents of `s`, creating the string only the
+    /// first time this is called with those contents.
+    ///
+    /// The string is kept in the registry for as long as this `Lua` lives, and later calls with
+    /// the same contents return a handle to it without building a new Lua string.  Any `String`
+    /// handle is itself a reference to an existing Lua string, so keeping the result around and
+    /// passing `&key` as a key to `Table::get` and `Table::set` pushes the string without
+    /// recreating it, which helps in hot loops that use the same keys over and over.
+    ///
+    /// Interned strings are never freed before the `Lua` is dropped, so this is meant for a fixed
+    /// set of keys such as field names, not for arbitrary data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let position = lua.create_table()?;
+    /// let x = lua.intern("x")?;
+    /// for i in 0..10 {
+    ///     position.set(&x, i)?;
+    /// }
+    /// assert_eq!(position.get::<_, i64>(&x)?, 9);
+    /// assert_eq!(position.get::<_, i64>("x")?, 9);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn intern<S>(&self, s: &S) -> Result<String>
+    where
+        S: ?Sized + AsRef<[u8]>,
+    {
+        let bytes = s.as_ref();
         unsafe {
+            let extra = extra_data(self.state);
             let _sg = StackGuard::new(self.state);
-            assert_stack(self.state, 3);
-            unsafe extern "C" fn new_table(state: *mut ffi::lua_State) -> c_int {
-                ffi::lua_newtable(state);
-                1
+            assert_stack(self.state, 4);
+
+            if let Some(&registry_id) = (*extra).interned_strings.get(bytes) {
+                ffi::lua_rawgeti(self.state, ffi::LUA_REGISTRYINDEX, registry_id as Integer);
+                return Ok(String(self.pop_ref()));
             }
-            protect_lua(self.state, 0, new_table)?;
-            Ok(Table(self.pop_ref()))
+
+            push_string(self.state, bytes)?;
+            ffi::lua_pushvalue(self.state, -1);
+            let registry_id = protect_lua_closure(self.state, 1, 0, |state| {
+                ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+            })?;
+            (*extra)
+                .interned_strings
+                .insert(bytes.to_vec(), registry_id);
+            Ok(String(self.pop_ref()))
         }
     }
 
-    /// Creates a table and fills it with values from an iterator.
-    pub fn create_table_from<'lua, K, V, I>(&'lua self, cont: I) -> Result<Table<'lua>>
-    where
-        K: ToLua<'lua>,
-        V: ToLua<'lua>,
-        I: IntoIterator<Item = (K, V)>,
-    {
+    /// Creates and returns a new table.
+    pub fn create_table(&self) -> Result<Table> {
         unsafe {
             let _sg = StackGuard::new(self.state);
-            // `Lua` instance assumes that on any callback, the Lua stack has at least LUA_MINSTACK
-            // slots available to avoid panics.
-            check_stack(self.state, 5 + ffi::LUA_MINSTACK)?;
-
+            assert_stack(self.state, 3);
             unsafe extern "C" fn new_table(state: *mut ffi::lua_State) -> c_int {
                 ffi::lua_newtable(state);
                 1
             }
             protect_lua(self.state, 0, new_table)?;
-
-            for (k, v) in cont {
-                self.push_value(k.to_lua(self)?);
-                self.push_value(v.to_lua(self)?);
-                unsafe extern "C" fn raw_set(state: *mut ffi::lua_State) -> c_int {
-                    ffi::lua_rawset(state, -3);
-                    1
-                }
-                protect_lua(self.state, 3, raw_set)?;
-            }
             Ok(Table(self.pop_ref()))
         }
     }
 
+    /// Creates a table and fills it with values from an iterator.
+    ///
+    /// The hash part of the table is preallocated using the lower bound of the iterator's
+    /// `size_hint`.
+    pub fn create_table_from<'lua, K, V, I>(&'lua self, cont: I) -> Result<Table<'lua>>
+    where
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let cont = cont.into_iter();
+        let nrec = cont.size_hint().0;
+        self.create_table_with_capacity_from(0, nrec, cont)
+    }
+
     /// Creates a table from an iterator of values, using `1..` as the keys.
+    ///
+    /// The array part of the table is preallocated using the lower bound of the iterator's
+    /// `size_hint`.
     pub fn create_sequence_from<'lua, T, I>(&'lua self, cont: I) -> Result<Table<'lua>>
     where
         T: ToLua<'lua>,
         I: IntoIterator<Item = T>,
     {
-        self.create_table_from(cont.into_iter().enumerate().map(|(k, v)| (k + 1, v)))
+        let cont = cont.into_iter();
+        let narr = cont.size_hint().0;
+        self.create_table_with_capacity_from(narr, 0, cont.enumerate().map(|(k, v)| (k + 1, v)))
+    }
+
+    /// Creates a new empty table whose keys, values, or both are weak references.
+    ///
+    /// The table gets a new metatable with its `__mode` field set according to `mode`.  Entries
+    /// whose weak key or value is not referenced from anywhere else are removed by the garbage
+    /// collector, which is useful for caches.  Only collectable values such as tables, functions
+    /// and threads are weak; strings, numbers and booleans are never removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result, Table, WeakMode};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let cache = lua.create_weak_table(WeakMode::Values)?;
+    /// cache.set("result", lua.create_table()?)?;
+    ///
+    /// lua.gc_collect()?;
+    /// assert!(cache.get::<_, Option<Table>>("result")?.is_none());
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn create_weak_table(&self, mode: WeakMode) -> Result<Table> {
+        let metatable = self.create_table()?;
+        metatable.raw_set("__mode", mode.as_str())?;
+        let table = self.create_table()?;
+        table.set_metatable(Some(metatable));
+        Ok(table)
     }
 
     /// Wraps a Rust function or closure, creating a callable Lua function handle to it.
@@ -211,6 +980,11 @@ impl Lua {
     /// values. For details on Rust-to-Lua conversions, refer to the [`ToLua`] and [`ToLuaMulti`]
     /// traits.
     ///
+    /// The created function can be used as the body of a coroutine, but it can never yield: calling
+    /// `coroutine.yield` from Lua code that was called by a Rust function raises an "attempt to
+    /// yield across metamethod/C-call boundary" error.  Return values from the Rust function are
+    /// simply returned from the coroutine.
+    ///
     /// # Examples
     ///
     /// Create a function which prints its argument:
@@ -289,17 +1063,262 @@ impl Lua {
         })
     }
 
+    /// Wraps a Rust function returning any error type, creating a callable Lua function handle to
+    /// it.
+    ///
+    /// This is a version of [`create_function`] for callbacks that fail with their own error type
+    /// `E` instead of `Error`, so `?` can be used on them without calling `to_lua_err()` first.
+    /// Any error that converts into a `failure::Error`, which includes every `std::error::Error`
+    /// that is also `Send + Sync + 'static`, is turned into an `Error::ExternalError` as with
+    /// [`Error::external`].
+    /// If the callback returns a `failure::Error` that wraps an `Error`, the `Error` is unwrapped
+    /// again, so a callback that uses `?` on both its own errors and rlua's can simply return
+    /// `failure::Error`.
+    ///
+    /// A separate method is needed because `create_function` could not infer `E` for callbacks that
+    /// only ever return `Ok`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result};
+    /// # use std::num::ParseIntError;
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    ///
+    /// let parse = lua.create_function_ext(|_, s: String| s.trim().parse::<i64>())?;
+    /// assert_eq!(parse.call::<_, i64>(" 42 ")?, 42);
+    ///
+    /// let err = parse.call::<_, i64>("forty-two").unwrap_err();
+    /// assert!(err.downcast_ref::<ParseIntError>().is_some());
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    /// [`Error::external`]: enum.Error.html#method.external
+    pub fn create_function_ext<'lua, 'callback, A, R, E, F>(
+        &'lua self,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        E: Into<failure::Error>,
+        F: 'static + Send + Fn(&'callback Lua, A) -> StdResult<R, E>,
+    {
+        self.create_function(move |lua, args| {
+            func(lua, args).map_err(|err| match err.into().downcast::<Error>() {
+                Ok(err) => err,
+                Err(err) => Error::ExternalError(Arc::new(err)),
+            })
+        })
+    }
+
+    /// Wraps a Rust function, storing the given Lua values as upvalues of the created function.
+    ///
+    /// This is like [`create_function`], but the callback also receives the values of `upvalues`
+    /// as a `MultiValue` before its arguments.  Since a callback must be `'static`, it cannot
+    /// capture Lua handles such as a `Table` directly, and would otherwise have to store them in
+    /// the registry and fetch them with [`registry_value`] on every call.  Upvalues are kept alive
+    /// by the function itself, so they need no `RegistryKey` and are collected together with the
+    /// function.  Converting the upvalues costs about as much as a registry lookup, so this is not
+    /// faster than using the registry; callbacks that only need Rust state should keep using
+    /// [`create_function`].
+    ///
+    /// A function can have at most 253 upvalues.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result, Table};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let counts = lua.create_table()?;
+    ///
+    /// let count = lua.create_function_with(counts.clone(), |lua, upvalues, word: String| {
+    ///     let counts: Table = lua.unpack_multi(upvalues)?;
+    ///     counts.set(word.clone(), counts.get::<_, Option<i64>>(word.clone())?.unwrap_or(0) + 1)
+    /// })?;
+    /// count.call::<_, ()>("apple")?;
+    /// count.call::<_, ()>("apple")?;
+    /// assert_eq!(counts.get::<_, i64>("apple")?, 2);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    /// [`registry_value`]: #method.registry_value
+    pub fn create_function_with<'lua, 'callback, U, A, R, F>(
+        &'lua self,
+        upvalues: U,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        U: ToLuaMulti<'lua>,
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        F: 'static + Send + Fn(&'callback Lua, MultiValue<'callback>, A) -> Result<R>,
+    {
+        let upvalues = upvalues.to_lua_multi(self)?;
+        let nupvalues = upvalues.len();
+        self.create_callback_with_upvalues(
+            Box::new(move |lua, mut args| {
+                let mut upvalues = Vec::with_capacity(nupvalues);
+                for _ in 0..nupvalues {
+                    upvalues.push(args.pop_front().unwrap_or(Nil));
+                }
+                let upvalues = MultiValue::from_vec(upvalues);
+                func(lua, upvalues, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+            }),
+            upvalues,
+        )
+    }
+
+    /// Wraps a Rust function returning a future, creating a callable Lua function handle to it.
+    ///
+    /// Requires the `async` feature.  Calling the function creates the future with `func` and
+    /// polls it.  While the future is pending, the calling coroutine yields and the [`AsyncThread`]
+    /// driving it returns `Poll::Pending`; once the future wakes its task and the `AsyncThread` is
+    /// polled again, the coroutine is resumed and the future polled again.  The function must
+    /// therefore be called directly from a thread polled through [`Thread::into_async`], not from
+    /// the main thread or from a coroutine it resumes itself; calling it anywhere else is an
+    /// error.
+    ///
+    /// The future must be `'static`, so it cannot hold on to the `Lua` or to Lua values: the
+    /// arguments are converted before the future is created, and its output is converted once
+    /// it completes.
+    ///
+    /// [`AsyncThread`]: struct.AsyncThread.html
+    /// [`Thread::into_async`]: struct.Thread.html#method.into_async
+    #[cfg(feature = "async")]
+    pub fn create_async_function<'lua, 'callback, A, R, F, FR>(
+        &'lua self,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        F: 'static + Send + Fn(&'callback Lua, A) -> FR,
+        FR: 'static + Send + Future<Output = Result<R>>,
+    {
+        // Calls `start` with the arguments to create a function polling the future, and yields
+        // until the poll function reports that the future is ready.
+        const TRAMPOLINE: &[u8] = br#"
+            local start, yield_pending = ...
+            local function step(poll, ready, ...)
+                if ready then
+                    return ...
+                end
+                yield_pending()
+                return step(poll, poll())
+            end
+            return function(...)
+                local poll = start(...)
+                return step(poll, poll())
+            end
+        "#;
+
+        // Rust callbacks cannot yield, so the trampoline yields through this plain C function.
+        unsafe extern "C" fn yield_pending(state: *mut ffi::lua_State) -> c_int {
+            ffi::lua_settop(state, 0);
+            ffi::lua_pushlightuserdata(state, async_pending());
+            ffi::lua_yield(state, 1)
+        }
+
+        let start = self.create_function(move |lua, args: A| {
+            let mut future = Some(Box::pin(func(lua, args)));
+            lua.create_function_mut(move |lua, ()| {
+                let poll = match future {
+                    Some(ref mut future) => {
+                        let waker = unsafe { async_waker(lua.state) }.ok_or_else(|| {
+                            Error::RuntimeError(
+                                "async function called outside of an AsyncThread".to_owned(),
+                            )
+                        })?;
+                        future.as_mut().poll(&mut Context::from_waker(&waker))
+                    }
+                    None => {
+                        return Err(Error::RuntimeError(
+                            "future polled after it has completed".to_owned(),
+                        ))
+                    }
+                };
+                match poll {
+                    Poll::Pending => Ok(MultiValue::from_vec(vec![Value::Boolean(false)])),
+                    Poll::Ready(results) => {
+                        future = None;
+                        let mut results = results?.to_lua_multi(lua)?;
+                        results.push_front(Value::Boolean(true));
+                        Ok(results)
+                    }
+                }
+            })
+        })?;
+
+        let yield_pending = unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+            ffi::lua_pushcfunction(self.state, yield_pending);
+            Function(self.pop_ref())
+        };
+
+        self.load(TRAMPOLINE, Some("async function"), ChunkMode::Text)?
+            .call((start, yield_pending))
+    }
+
     /// Wraps a Lua function into a new thread (or coroutine).
     ///
-    /// Equivalent to `coroutine.create`.
+    /// Equivalent to `coroutine.create`.  The function may also be a Rust function created with
+    /// [`create_function`], although such a function cannot yield.  This is only possible from
+    /// Rust: the Lua `coroutine.create` function still only accepts Lua functions.
+    ///
+    /// [`create_function`]: #method.create_function
     pub fn create_thread<'lua>(&'lua self, func: Function<'lua>) -> Result<Thread<'lua>> {
+        self.create_thread_from(Value::Function(func))
+    }
+
+    /// Wraps any callable Lua value into a new thread (or coroutine).
+    ///
+    /// This is like [`create_thread`], but also accepts values that are callable through a `__call`
+    /// metamethod, such as tables.  Returns an error if the value is not callable.
+    ///
+    /// [`create_thread`]: #method.create_thread
+    pub fn create_thread_from<'lua>(&'lua self, callable: Value<'lua>) -> Result<Thread<'lua>> {
         unsafe {
             let _sg = StackGuard::new(self.state);
-            assert_stack(self.state, 2);
+            assert_stack(self.state, 4);
+
+            let type_name = callable.type_name();
+            let is_function = match callable {
+                Value::Function(_) => true,
+                _ => false,
+            };
+            self.push_value(callable);
+            if !is_function {
+                ffi::lua_pushvalue(self.state, -1);
+                let has_call = protect_lua_closure(self.state, 1, 0, |state| {
+                    ffi::luaL_getmetafield(state, -1, cstr!("__call")) != 0
+                })?;
+                if !has_call {
+                    return Err(Error::RuntimeError(format!(
+                        "cannot create a thread from a non-callable {} value",
+                        type_name
+                    )));
+                }
+            }
 
             let thread_state =
                 protect_lua_closure(self.state, 0, 1, |state| ffi::lua_newthread(state))?;
-            self.push_ref(&func.0);
+            ffi::lua_pushvalue(self.state, -2);
             ffi::lua_xmove(self.state, thread_state, 1);
 
             Ok(Thread(self.pop_ref()))
@@ -316,6 +1335,160 @@ impl Lua {
         }
     }
 
+    /// Takes a snapshot of the global environment, to be restored later with
+    /// [`restore_globals`].
+    ///
+    /// This makes a shallow copy of the globals table: the snapshot remembers which value each
+    /// global had, but tables such as `string` are shared with the running state, so changes made
+    /// inside of them are not undone by a restore.  This is much cheaper than creating a new `Lua`,
+    /// which is useful to give each of many tests a clean global environment.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let baseline = lua.snapshot_globals()?;
+    ///
+    /// lua.exec::<()>("counter = 1; print = nil", None)?;
+    ///
+    /// lua.restore_globals(&baseline)?;
+    /// assert_eq!(lua.eval::<bool>("counter == nil and print ~= nil", None)?, true);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`restore_globals`]: #method.restore_globals
+    pub fn snapshot_globals(&self) -> Result<GlobalsSnapshot> {
+        let copy = self.create_table()?;
+        for pair in self.globals().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            copy.raw_set(key, value)?;
+        }
+        Ok(GlobalsSnapshot(self.create_registry_value(copy)?))
+    }
+
+    /// Restores the global environment to the state saved in `snapshot`.
+    ///
+    /// Globals that were added since the snapshot was taken are removed, and all others are set
+    /// back to the value they had in the snapshot.  The globals table is modified in place with
+    /// raw accesses, so handles to it stay valid and its metatable is neither invoked nor changed.
+    /// The snapshot can be restored any number of times.
+    ///
+    /// Returns `Error::MismatchedRegistryKey` if the snapshot was taken from a different Lua
+    /// state.
+    pub fn restore_globals(&self, snapshot: &GlobalsSnapshot) -> Result<()> {
+        let saved: Table = self.registry_value(&snapshot.0)?;
+        let globals = self.globals();
+
+        let mut added = Vec::new();
+        for pair in globals.clone().pairs::<Value, Value>() {
+            let (key, _) = pair?;
+            if saved.raw_get::<_, Value>(key.clone())?.is_nil() {
+                added.push(key);
+            }
+        }
+        for key in added {
+            globals.raw_set(key, Nil)?;
+        }
+
+        for pair in saved.pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            globals.raw_set(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a handle to the thread this `Lua` is running on.
+    ///
+    /// Inside of a Rust callback, this is the coroutine that called the callback, or the main
+    /// thread if it was not called from a coroutine.  Outside of callbacks this is always the main
+    /// thread.  The returned handle keeps the thread alive like any other `Thread`.
+    pub fn current_thread(&self) -> Thread {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+            ffi::lua_pushthread(self.state);
+            Thread(self.pop_ref())
+        }
+    }
+
+    /// Returns true if this `Lua` is running on the main thread rather than on a coroutine.
+    ///
+    /// This can be used by callbacks which may only yield, or only be called, from a coroutine.
+    pub fn is_main_thread(&self) -> bool {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+            ffi::lua_pushthread(self.state) != 0
+        }
+    }
+
+    /// Registers a module implemented in Rust, so that scripts can load it with `require(name)`.
+    ///
+    /// The loader is stored in `package.preload[name]` and is only called the first time the module
+    /// is required.  Whatever it returns is what `require` yields, and `require` caches it in
+    /// `package.loaded[name]` so that the loader runs at most once.  As with any Lua module, a
+    /// loader returning `nil` makes `require` yield `true`.
+    ///
+    /// Returns an error if the `package` library is not loaded.
+    pub fn register_module<'lua, 'callback, F>(&'lua self, name: &str, loader: F) -> Result<()>
+    where
+        F: 'static + Send + Fn(&'callback Lua) -> Result<Value<'callback>>,
+    {
+        let preload = self.package_table()?.get::<_, Table>("preload")?;
+        let loader = self.create_function(move |lua, _: MultiValue| loader(lua))?;
+        preload.set(name, loader)
+    }
+
+    /// Sets `package.path`, the template of paths `require` searches for Lua modules.
+    ///
+    /// Returns an error if the `package` library is not loaded.
+    pub fn set_package_path(&self, path: &str) -> Result<()> {
+        self.package_table()?.set("path", path)
+    }
+
+    /// Sets `package.cpath`, the template of paths `require` searches for C modules.
+    ///
+    /// Returns an error if the `package` library is not loaded.
+    pub fn set_package_cpath(&self, path: &str) -> Result<()> {
+        self.package_table()?.set("cpath", path)
+    }
+
+    /// Adds a searcher implemented in Rust in front of the searchers `require` tries.
+    ///
+    /// The searcher is called with the name of the module being required, and returns the loader
+    /// function of the module if it can resolve it, or `None` to let `require` try the next
+    /// searcher.  This allows loading modules from sources other than the file system, such as an
+    /// archive or a virtual file system.  Like every other loader, the returned function is called
+    /// with the module name and its return value is what `require` yields.
+    ///
+    /// Lua 5.1 keeps the searchers in `package.loaders` rather than `package.searchers`.  Returns
+    /// an error if the `package` library is not loaded.
+    pub fn add_package_searcher<'lua, 'callback, F>(&'lua self, searcher: F) -> Result<()>
+    where
+        F: 'static + Send + Fn(&'callback Lua, &str) -> Result<Option<Function<'callback>>>,
+    {
+        let loaders = self.package_table()?.get::<_, Table>("loaders")?;
+        let searcher = self.create_function(move |lua, name: StdString| {
+            match searcher(lua, &name)? {
+                Some(loader) => Ok(Value::Function(loader)),
+                // Returned messages are collected by `require` into the error it raises when no
+                // searcher finds the module.
+                None => Ok(Value::String(lua.create_string(&format!(
+                    "\n\tno module '{}' in Rust searcher",
+                    name
+                ))?)),
+            }
+        })?;
+        loaders.insert(1, searcher)
+    }
+
     /// Calls the given function with a `Scope` parameter, giving the function the ability to create
     /// userdata and callbacks from rust types that are !Send or non-'static.
     ///
@@ -333,6 +1506,36 @@ impl Lua {
     /// not very useful, because after the scope is dropped, all references to scoped values,
     /// whether in Lua or in rust, are invalidated.  `Function` types will error when called, and
     /// `AnyUserData` types will be typeless.
+    ///
+    /// Returns whatever `f` returns, after all scoped values have been invalidated and dropped.
+    /// This makes it possible to compute a value inside of the scope, as long as it does not borrow
+    /// from the scope: an `i32` or a `std::string::String` can be returned, but a handle created
+    /// inside the scope cannot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let mut calls = 0;
+    /// let result = lua.scope(|scope| {
+    ///     let count = scope.create_function_mut(|_, ()| {
+    ///         calls += 1;
+    ///         Ok(())
+    ///     })?;
+    ///     lua.globals().set("count", count)?;
+    ///     lua.eval::<String>("count() count() return 'done'", None)
+    /// })?;
+    /// assert_eq!(result, "done");
+    /// assert_eq!(calls, 2);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
     pub fn scope<'scope, 'lua: 'scope, F, R>(&'lua self, f: F) -> R
     where
         F: FnOnce(&Scope<'scope>) -> R,
@@ -343,6 +1546,33 @@ impl Lua {
         r
     }
 
+    /// Like [`scope`], but for fallible work: `f` returns a `Result`, which is returned once all
+    /// scoped values have been invalidated and dropped.
+    ///
+    /// Scoped values are invalidated whether `f` succeeds or not, so `?` can be used freely inside
+    /// of `f`.  If `f` returns an error and dropping one of the scoped values then panics, the
+    /// panic is discarded and the original error is returned, so that the error is never masked by
+    /// a failure while cleaning up.  A panic while dropping the scoped values after `f` succeeded
+    /// is propagated as usual.
+    ///
+    /// [`scope`]: #method.scope
+    pub fn try_scope<'scope, 'lua: 'scope, F, R>(&'lua self, f: F) -> Result<R>
+    where
+        F: FnOnce(&Scope<'scope>) -> Result<R>,
+    {
+        let scope = Scope::new(self);
+        match f(&scope) {
+            Ok(r) => {
+                drop(scope);
+                Ok(r)
+            }
+            Err(err) => {
+                let _ = catch_unwind(AssertUnwindSafe(move || drop(scope)));
+                Err(err)
+            }
+        }
+    }
+
     /// Coerces a Lua value to a string.
     ///
     /// The value must be a string (in which case this is a no-op) or a number.
@@ -426,22 +1656,95 @@ impl Lua {
         }
     }
 
+    /// Converts any Lua value to a string, the way Lua's `tostring` and `print` do.
+    ///
+    /// Unlike [`coerce_string`], this accepts every value: a value with a `__tostring` metamethod
+    /// is converted by calling it, and tables, functions and threads without one are shown with
+    /// their type and address.  An error raised by `__tostring`, or a `__tostring` that does not
+    /// return a string, is returned as an `Err`.  See also [`Value::tostring`], which returns a
+    /// Rust string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result, Value};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let point: Value = lua.eval(r#"
+    ///     setmetatable({ x = 1, y = 2 }, {
+    ///         __tostring = function(p) return "(" .. p.x .. ", " .. p.y .. ")" end,
+    ///     })
+    /// "#, None)?;
+    ///
+    /// assert_eq!(lua.coerce_to_string(&point)?, "(1, 2)");
+    /// assert_eq!(lua.coerce_to_string(&Value::Nil)?, "nil");
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`coerce_string`]: #method.coerce_string
+    /// [`Value::tostring`]: enum.Value.html#method.tostring
+    pub fn coerce_to_string<'lua>(&'lua self, v: &Value<'lua>) -> Result<String<'lua>> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 4);
+
+            self.push_value(v.clone());
+            protect_lua_closure(self.state, 1, 1, |state| {
+                ffi::luaL_tolstring(state, -1, ptr::null_mut());
+            })?;
+            Ok(String(self.pop_ref()))
+        }
+    }
+
     /// Converts a value that implements `ToLua` into a `Value` instance.
+    ///
+    /// This converts a single value.  To convert a tuple or other `ToLuaMulti` value into a
+    /// `MultiValue`, use [`pack_multi`].
+    ///
+    /// [`pack_multi`]: #method.pack_multi
     pub fn pack<'lua, T: ToLua<'lua>>(&'lua self, t: T) -> Result<Value<'lua>> {
         t.to_lua(self)
     }
 
     /// Converts a `Value` instance into a value that implements `FromLua`.
+    ///
+    /// This converts a single value.  To convert a `MultiValue` into a tuple or other
+    /// `FromLuaMulti` value, use [`unpack_multi`].
+    ///
+    /// [`unpack_multi`]: #method.unpack_multi
     pub fn unpack<'lua, T: FromLua<'lua>>(&'lua self, value: Value<'lua>) -> Result<T> {
         T::from_lua(value, self)
     }
 
     /// Converts a value that implements `ToLuaMulti` into a `MultiValue` instance.
+    ///
+    /// This performs the same conversion as passing `t` as the arguments of `Function::call`, so
+    /// the resulting values can be stored and later passed to any function.
+    ///
+    /// This is the multi-value counterpart of [`pack`], which keeps its single-value `ToLua`
+    /// signature so that existing callers are not broken.
+    ///
+    /// [`pack`]: #method.pack
     pub fn pack_multi<'lua, T: ToLuaMulti<'lua>>(&'lua self, t: T) -> Result<MultiValue<'lua>> {
         t.to_lua_multi(self)
     }
 
     /// Converts a `MultiValue` instance into a value that implements `FromLuaMulti`.
+    ///
+    /// This performs the same conversion as is applied to the arguments of a Rust callback.
+    /// Missing values are treated as `nil`, so unpacking too few values into a tuple fails with
+    /// `Error::BadArgument` for the first missing value whose type does not accept `nil`, and
+    /// extra values are ignored.
+    ///
+    /// This is the multi-value counterpart of [`unpack`], which keeps its single-value `FromLua`
+    /// signature so that existing callers are not broken.
+    ///
+    /// [`unpack`]: #method.unpack
     pub fn unpack_multi<'lua, T: FromLuaMulti<'lua>>(
         &'lua self,
         value: MultiValue<'lua>,
@@ -453,11 +1756,16 @@ impl Lua {
     ///
     /// This value will be available to rust from all `Lua` instances which share the same main
     /// state.
+    ///
+    /// The names `_LOADED`, `_PRELOAD` and `FILE*` are used by the Lua standard library for the
+    /// loaded module table, the module preloaders and the file handle metatable, and setting one
+    /// of them returns an error.
     pub fn set_named_registry_value<'lua, T: ToLua<'lua>>(
         &'lua self,
         name: &str,
         t: T,
     ) -> Result<()> {
+        check_registry_name(name)?;
         let t = t.to_lua(self)?;
         unsafe {
             let _sg = StackGuard::new(self.state);
@@ -510,8 +1818,14 @@ impl Lua {
     ///
     /// This value will be available to rust from all `Lua` instances which share the same main
     /// state.
+    ///
+    /// Registry values whose `RegistryKey`s have been dropped are removed first (see
+    /// [`expire_registry_values`]), so their slots are reused instead of growing the registry.
+    ///
+    /// [`expire_registry_values`]: #method.expire_registry_values
     pub fn create_registry_value<'lua, T: ToLua<'lua>>(&'lua self, t: T) -> Result<RegistryKey> {
         let t = t.to_lua(self)?;
+        self.expire_registry_values();
         unsafe {
             let _sg = StackGuard::new(self.state);
             assert_stack(self.state, 2);
@@ -533,6 +1847,29 @@ impl Lua {
     /// Any Lua instance which shares the underlying main state may call this method to get a value
     /// previously placed by [`create_registry_value`].
     ///
+    /// The value is converted to `T` with `FromLua`, so a value stored as a `Function` or `Table`
+    /// can be retrieved as one directly, without matching on a `Value`.  If the stored value cannot
+    /// be converted to `T`, this returns the conversion error and the value stays in the registry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Function, Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let double: Function = lua.eval("function(n) return n * 2 end", None)?;
+    /// let key = lua.create_registry_value(double)?;
+    ///
+    /// let double = lua.registry_value::<Function>(&key)?;
+    /// assert_eq!(double.call::<_, i32>(21)?, 42);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
     /// [`create_registry_value`]: #method.create_registry_value
     pub fn registry_value<'lua, T: FromLua<'lua>>(&'lua self, key: &RegistryKey) -> Result<T> {
         let value = unsafe {
@@ -553,46 +1890,628 @@ impl Lua {
         T::from_lua(value, self)
     }
 
-    /// Removes a value from the Lua registry.
+    /// Removes a value from the Lua registry.
+    ///
+    /// You may call this function to manually remove a value placed in the registry with
+    /// [`create_registry_value`]. In addition to manual `RegistryKey` removal, you can also call
+    /// [`expire_registry_values`] to automatically remove values from the registry whose
+    /// `RegistryKey`s have been dropped.
+    ///
+    /// [`create_registry_value`]: #method.create_registry_value
+    /// [`expire_registry_values`]: #method.expire_registry_values
+    pub fn remove_registry_value(&self, key: RegistryKey) -> Result<()> {
+        unsafe {
+            if !self.owns_registry_value(&key) {
+                return Err(Error::MismatchedRegistryKey);
+            }
+
+            ffi::luaL_unref(self.state, ffi::LUA_REGISTRYINDEX, key.take());
+            Ok(())
+        }
+    }
+
+    /// Returns true if the given `RegistryKey` was created by a `Lua` which shares the underlying
+    /// main state with this `Lua` instance.
+    ///
+    /// Other than this, methods that accept a `RegistryKey` will return
+    /// `Error::MismatchedRegistryKey` if passed a `RegistryKey` that was not created with a
+    /// matching `Lua` state.
+    pub fn owns_registry_value(&self, key: &RegistryKey) -> bool {
+        unsafe {
+            Arc::ptr_eq(
+                &key.unref_list,
+                &(*extra_data(self.state)).registry_unref_list,
+            )
+        }
+    }
+
+    /// Returns the amount of memory (in bytes) currently used inside this Lua state.
+    pub fn gc_count(&self) -> usize {
+        unsafe {
+            let kbytes = ffi::lua_gc(self.state, ffi::LUA_GCCOUNT, 0) as usize;
+            let bytes = ffi::lua_gc(self.state, ffi::LUA_GCCOUNTB, 0) as usize;
+            kbytes * 1024 + bytes
+        }
+    }
+
+    /// Returns true if the garbage collector is currently running automatically.
+    pub fn gc_is_running(&self) -> bool {
+        unsafe { ffi::lua_gc(self.state, ffi::LUA_GCISRUNNING, 0) != 0 }
+    }
+
+    /// Stops the Lua GC from running automatically.
+    ///
+    /// Explicit collections through [`gc_collect`] and [`gc_step`] are still possible while the GC
+    /// is stopped.
+    ///
+    /// [`gc_collect`]: #method.gc_collect
+    /// [`gc_step`]: #method.gc_step
+    pub fn gc_stop(&self) {
+        unsafe {
+            ffi::lua_gc(self.state, ffi::LUA_GCSTOP, 0);
+        }
+    }
+
+    /// Restarts the Lua GC if it was previously stopped with [`gc_stop`].
+    ///
+    /// [`gc_stop`]: #method.gc_stop
+    pub fn gc_restart(&self) {
+        unsafe {
+            ffi::lua_gc(self.state, ffi::LUA_GCRESTART, 0);
+        }
+    }
+
+    /// Performs a full garbage-collection cycle.
+    ///
+    /// It may be necessary to call this function twice to collect all currently unreachable
+    /// objects, once to finish the current gc cycle, and once to start and finish the next cycle.
+    /// If the GC was stopped with [`gc_stop`], it stays stopped afterwards.
+    ///
+    /// Registry values whose `RegistryKey`s have been dropped are removed first (see
+    /// [`expire_registry_values`]), so that they can be collected in this cycle.
+    ///
+    /// [`gc_stop`]: #method.gc_stop
+    /// [`expire_registry_values`]: #method.expire_registry_values
+    pub fn gc_collect(&self) -> Result<()> {
+        self.expire_registry_values();
+        let was_running = self.gc_is_running();
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 3);
+            let r = protect_lua_closure(self.state, 0, 0, |state| {
+                ffi::lua_gc(state, ffi::LUA_GCCOLLECT, 0);
+            });
+            // Explicit collections in Lua 5.1 reset the collector threshold, which restarts a
+            // stopped GC.
+            if !was_running {
+                ffi::lua_gc(self.state, ffi::LUA_GCSTOP, 0);
+            }
+            r
+        }
+    }
+
+    /// Performs an incremental garbage-collection step, as if `kbytes` kilobytes had been
+    /// allocated.
+    ///
+    /// Returns true if this step finished a collection cycle.  If the GC was stopped with
+    /// [`gc_stop`], it stays stopped afterwards.
+    ///
+    /// [`gc_stop`]: #method.gc_stop
+    pub fn gc_step(&self, kbytes: c_int) -> Result<bool> {
+        let was_running = self.gc_is_running();
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 3);
+            let r = protect_lua_closure(self.state, 0, 0, |state| {
+                ffi::lua_gc(state, ffi::LUA_GCSTEP, kbytes) != 0
+            });
+            // As in `gc_collect`, a step resets the collector threshold.
+            if !was_running {
+                ffi::lua_gc(self.state, ffi::LUA_GCSTOP, 0);
+            }
+            r
+        }
+    }
+
+    /// Sets the 'pause' value of the incremental collector and returns the previous value.
+    ///
+    /// The pause controls how long the collector waits before starting a new cycle, and is given
+    /// as a percentage of the memory in use after the previous collection: the default of 200
+    /// waits until the amount of memory in use has doubled.
+    pub fn gc_set_pause(&self, pause: c_int) -> c_int {
+        unsafe { ffi::lua_gc(self.state, ffi::LUA_GCSETPAUSE, pause) }
+    }
+
+    /// Sets the 'step multiplier' value of the incremental collector and returns the previous
+    /// value.
+    ///
+    /// The step multiplier controls the speed of the collector relative to memory allocation, as a
+    /// percentage: larger values make each incremental step do more work, the default is 200.
+    pub fn gc_set_step_multiplier(&self, step_multiplier: c_int) -> c_int {
+        unsafe { ffi::lua_gc(self.state, ffi::LUA_GCSETSTEPMUL, step_multiplier) }
+    }
+
+    /// Sets both parameters of the incremental collector, see [`gc_set_pause`] and
+    /// [`gc_set_step_multiplier`].
+    ///
+    /// Later Lua versions can switch between an incremental and a generational collector.  The
+    /// collector of Lua 5.1 is always incremental and has no generational mode, so there is no
+    /// mode to select and this only sets the parameters.
+    ///
+    /// [`gc_set_pause`]: #method.gc_set_pause
+    /// [`gc_set_step_multiplier`]: #method.gc_set_step_multiplier
+    pub fn gc_set_incremental(&self, pause: c_int, step_multiplier: c_int) {
+        self.gc_set_pause(pause);
+        self.gc_set_step_multiplier(step_multiplier);
+    }
+
+    /// Sets a limit on the amount of memory (in bytes) the Lua state may allocate.
+    ///
+    /// Once the limit is reached, any further allocation fails and Lua raises a memory error, which
+    /// is returned as `Error::MemoryError` from the Rust code that invoked the Lua code.  Passing
+    /// `None` removes the limit, which is the default for a newly created `Lua` state.
+    ///
+    /// Setting a limit lower than the amount of memory already in use does not free anything, it
+    /// only causes all subsequent allocations to fail until enough memory has been collected.
+    ///
+    /// The limit is not strict: rlua's internal allocations, such as the error value and its
+    /// traceback or growing the Lua stacks to hold references and callback results, always
+    /// succeed, so that an error raised near the limit is returned intact and never leaves the Lua
+    /// state inconsistent.
+    pub fn set_memory_limit(&self, bytes: Option<usize>) {
+        unsafe {
+            (*memory_info(self.state)).memory_limit = bytes;
+        }
+    }
+
+    /// Sets a hook function that is called after every `count` instructions executed by the Lua
+    /// VM.
+    ///
+    /// This can be used to limit the execution time of untrusted scripts: if the callback returns
+    /// `Err`, the error is raised as a Lua error from the currently running Lua code, so it ends up
+    /// as the cause of an `Error::CallbackError` returned by the Rust code that invoked the Lua
+    /// code (unless it is caught by a `pcall` inside Lua).
+    ///
+    /// The hook applies to the current thread and to all coroutines created after the hook has
+    /// been set.  There can only be one hook at a time, setting a new hook replaces the previous
+    /// one.  `count` must be greater than zero.
+    pub fn set_hook_count<F>(&self, count: u32, callback: F)
+    where
+        F: 'static + Send + Fn(&Lua) -> Result<()>,
+    {
+        unsafe {
+            self.install_hook(HookMask::count(count), Rc::new(move |lua, _| callback(lua)));
+        }
+    }
+
+    /// Sets a hook function that is called on the events selected by `mask`.
+    ///
+    /// This is the general form of [`set_hook_count`]: besides counting instructions, the hook can
+    /// be called when a function is called or returns, and before each new line of Lua code is
+    /// run, which is what a step debugger needs.  The callback receives the event and the current
+    /// line, and can use [`debug_info`] to find out more about the running function.
+    ///
+    /// If the callback returns `Err`, the error is raised as a Lua error from the currently running
+    /// Lua code, so it aborts the running call just like an error from [`set_hook_count`]'s
+    /// callback.  Lua does not call hooks while a hook is running, so Lua code called by the
+    /// callback is not traced.
+    ///
+    /// There can only be one hook at a time, setting a new hook replaces the previous one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{HookEvent, HookMask, Lua, Result};
+    /// # use std::sync::{Arc, Mutex};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let lines = Arc::new(Mutex::new(Vec::new()));
+    /// let recorded = lines.clone();
+    /// lua.set_hook(HookMask::LINE, move |_, info| {
+    ///     assert_eq!(info.event, HookEvent::Line);
+    ///     recorded.lock().unwrap().extend(info.current_line);
+    ///     Ok(())
+    /// });
+    ///
+    /// lua.exec::<()>("local x = 1\nx = x + 1\n", None)?;
+    /// lua.remove_hook();
+    /// assert_eq!(*lines.lock().unwrap(), vec![1, 2]);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`set_hook_count`]: #method.set_hook_count
+    /// [`debug_info`]: #method.debug_info
+    pub fn set_hook<F>(&self, mask: HookMask, callback: F)
+    where
+        F: 'static + Send + FnMut(&Lua, HookInfo) -> Result<()>,
+    {
+        let callback = RefCell::new(callback);
+        unsafe {
+            self.install_hook(
+                mask,
+                Rc::new(move |lua, info| {
+                    let mut callback = callback
+                        .try_borrow_mut()
+                        .map_err(|_| Error::RecursiveMutCallback)?;
+                    (&mut *callback)(lua, info)
+                }),
+            );
+        }
+    }
+
+    /// Removes the hook previously set with [`set_hook_count`] or [`set_hook`].
+    ///
+    /// [`set_hook_count`]: #method.set_hook_count
+    /// [`set_hook`]: #method.set_hook
+    pub fn remove_hook(&self) {
+        unsafe {
+            (*extra_data(self.state)).hook_callback = None;
+            (*extra_data(self.state)).scoped_hook_callback = None;
+            set_hook_mask(self.state, HookMask::NONE);
+        }
+    }
+
+    /// Returns a handle which can interrupt Lua code running in this state from another thread.
+    ///
+    /// Calling [`InterruptHandle::cancel`] makes the running Lua code raise `Error::Interrupted`,
+    /// which is returned as is (not wrapped in an `Error::CallbackError`) from the Rust code that
+    /// invoked the Lua code, such as `Function::call`.  The flag is checked by a hook every 1000
+    /// instructions, or whenever the hook set with [`set_hook`] or [`set_hook_count`] runs, so the
+    /// hook applies to the current thread and to coroutines created afterwards.  Hooks set later
+    /// keep this check: they still see only the events they asked for, but a count hook of more
+    /// than 1000 instructions is then called after every multiple of 1000 instructions that adds
+    /// up to its count.  A single slow call to a library function or to a Rust callback is not
+    /// interrupted.
+    ///
+    /// Like a panic, the interrupt cannot be caught with `pcall` or `xpcall`.  The flag stays set
+    /// until [`InterruptHandle::reset`] is called, so any Lua code run before the reset is aborted
+    /// as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Error, Lua, Result};
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let handle = lua.interrupt_handle();
+    ///
+    /// let canceller = handle.clone();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_millis(10));
+    ///     canceller.cancel();
+    /// });
+    /// match lua.exec::<()>("while true do end", None) {
+    ///     Err(Error::Interrupted) => {}
+    ///     r => panic!("expected Interrupted, got {:?}", r),
+    /// }
+    ///
+    /// handle.reset();
+    /// assert_eq!(lua.eval::<i64>("1 + 1", None)?, 2);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`InterruptHandle::cancel`]: struct.InterruptHandle.html#method.cancel
+    /// [`InterruptHandle::reset`]: struct.InterruptHandle.html#method.reset
+    /// [`set_hook`]: #method.set_hook
+    /// [`set_hook_count`]: #method.set_hook_count
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        unsafe {
+            let extra = extra_data(self.state);
+            if let Some(ref interrupt) = (*extra).interrupt {
+                return InterruptHandle(interrupt.clone());
+            }
+
+            let interrupt = Arc::new(AtomicBool::new(false));
+            (*extra).interrupt = Some(interrupt.clone());
+            set_hook_mask(self.state, (*extra).hook_mask);
+            InterruptHandle(interrupt)
+        }
+    }
+
+    /// Routes warnings emitted by scripts to `handler` instead of stderr.
+    ///
+    /// Lua 5.4 has a builtin warning system, where `handler` would be installed with `lua_setwarnf`.
+    /// The bundled Lua 5.1 has no such system, so this instead sets a global `warn` function that
+    /// behaves like the one from Lua 5.4: `warn(msg1, ...)` concatenates all of its arguments
+    /// (which must be strings or numbers) and passes the resulting message to `handler`.
+    ///
+    /// A call with a single argument starting with `@` is a control message and is not passed to
+    /// `handler`.  `"@off"` stops passing warnings to `handler`, `"@on"` resumes it, and any other
+    /// control message is ignored.  Warnings are initially on.
+    pub fn set_warning_handler<F>(&self, handler: F) -> Result<()>
+    where
+        F: 'static + Send + Fn(&str),
+    {
+        let enabled = Cell::new(true);
+        let warn = self.create_function(move |_, messages: Variadic<StdString>| {
+            if messages.is_empty() {
+                return Err(Error::RuntimeError(
+                    "bad argument #1 to 'warn' (string expected, got no value)".to_owned(),
+                ));
+            }
+            if messages.len() == 1 && messages[0].starts_with('@') {
+                match messages[0].as_str() {
+                    "@on" => enabled.set(true),
+                    "@off" => enabled.set(false),
+                    _ => {}
+                }
+            } else if enabled.get() {
+                handler(&messages.concat());
+            }
+            Ok(())
+        })?;
+        self.globals().set("warn", warn)
+    }
+
+    /// Sets what happens to panics in Rust callbacks once they reach the Rust code that called
+    /// into Lua.
+    ///
+    /// By default, panics are resumed, which keeps panics (such as failed assertions in tests)
+    /// visible.  With `PanicPolicy::Convert`, the calling Rust code instead receives an
+    /// `Error::CallbackPanic`, so that a single misbehaving callback can be handled like any other
+    /// error.  The policy is shared by all `Lua` instances using the same main state.
+    pub fn set_panic_handler(&self, policy: PanicPolicy) {
+        unsafe {
+            (*extra_data(self.state)).panic_policy = policy;
+        }
+    }
+
+    /// Sets a function that decides which Lua value is raised when a Rust callback returns `Err`.
+    ///
+    /// By default the error itself is raised, which Lua code sees as a userdata that converts to
+    /// the error message with `tostring`.  The converter can instead turn the error into any Lua
+    /// value, such as a table with `code` and `message` fields that scripts can inspect after a
+    /// `pcall`.  If the converter itself returns `Err`, its error is discarded and the original
+    /// error is raised as if no converter was set.
+    ///
+    /// A converted value that is not caught inside Lua reaches the Rust code that called into Lua
+    /// like any other Lua error value, so the original `Error` is only preserved when the
+    /// converter is not used.  The converter is shared by all `Lua` instances using the same main
+    /// state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Error, Result, Value};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_error_converter(|lua, err| {
+    ///     let error = lua.create_table()?;
+    ///     error.set("code", match *err {
+    ///         Error::RuntimeError(_) => 1,
+    ///         _ => 2,
+    ///     })?;
+    ///     error.set("message", err.to_string())?;
+    ///     Ok(Value::Table(error))
+    /// });
+    ///
+    /// let fail = lua.create_function(|_, ()| -> Result<()> {
+    ///     Err(Error::RuntimeError("out of cheese".to_owned()))
+    /// })?;
+    /// lua.globals().set("fail", fail)?;
+    ///
+    /// let code: i64 = lua.eval(
+    ///     r#"
+    ///         local ok, err = pcall(fail)
+    ///         return err.code
+    ///     "#,
+    ///     None,
+    /// )?;
+    /// assert_eq!(code, 1);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn set_error_converter<F>(&self, converter: F)
+    where
+        F: 'static + Send + for<'lua> Fn(&'lua Lua, &Error) -> Result<Value<'lua>>,
+    {
+        unsafe {
+            (*extra_data(self.state)).error_converter = Some(Rc::new(converter));
+        }
+    }
+
+    /// Sets the maximum number of Rust callbacks that may be executing at the same time.
+    ///
+    /// Every call from Lua into a Rust function created with [`create_function`] (directly or
+    /// through intermediate Lua code called by another Rust function) increases the call depth by
+    /// one.  When the limit is reached, calling another Rust function fails with
+    /// `Error::CallDepthExceeded` instead of growing the native stack further.  Passing `None`
+    /// removes the limit, which is the default.
+    ///
+    /// Lua itself separately limits the depth of nested C calls, and raises a "C stack overflow"
+    /// error once that limit is reached.
+    ///
+    /// [`create_function`]: #method.create_function
+    pub fn set_max_call_depth(&self, depth: Option<usize>) {
+        unsafe {
+            (*extra_data(self.state)).max_call_depth = depth;
+        }
+    }
+
+    /// Returns the number of Rust callbacks that are currently executing.
+    ///
+    /// When called from inside a Rust callback, this includes that callback itself.
+    pub fn call_depth(&self) -> usize {
+        unsafe { (*extra_data(self.state)).call_depth }
+    }
+
+    /// Stores `data` in this Lua state, replacing and returning any value of the same type that was
+    /// stored before.
+    ///
+    /// Each type can be stored once, so a host can attach several pieces of context, and Rust
+    /// callbacks can get them back with [`app_data_ref`] or [`app_data_mut`] from the `Lua` they
+    /// receive, without capturing the context in every closure.  The data is dropped together with
+    /// the `Lua`.
+    ///
+    /// Borrows are checked at runtime for each type separately, like with a `RefCell`.  Replacing
+    /// data that is currently borrowed returns `Error::AppDataBorrowMutError`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// struct Config {
+    ///     greeting: String,
+    /// }
+    ///
+    /// let lua = Lua::new();
+    /// lua.set_app_data(Config {
+    ///     greeting: "hello".to_owned(),
+    /// })?;
+    ///
+    /// let greet = lua.create_function(|lua, name: String| {
+    ///     let config = lua.app_data_ref::<Config>()?.unwrap();
+    ///     Ok(format!("{}, {}", config.greeting, name))
+    /// })?;
+    /// assert_eq!(greet.call::<_, String>("world")?, "hello, world");
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`app_data_ref`]: #method.app_data_ref
+    /// [`app_data_mut`]: #method.app_data_mut
+    pub fn set_app_data<T: 'static + Send>(&self, data: T) -> Result<Option<T>> {
+        if let Some(cell) = self.app_data_cell::<T>() {
+            let mut old = cell
+                .try_borrow_mut()
+                .map_err(|_| Error::AppDataBorrowMutError)?;
+            let old = mem::replace(&mut *old, Box::new(data));
+            return Ok(old.downcast().ok().map(|old| *old));
+        }
+        let app_data = unsafe { &(*extra_data(self.state)).app_data };
+        app_data
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Box::new(RefCell::new(Box::new(data))));
+        Ok(None)
+    }
+
+    /// Borrows the data of type `T` stored with [`set_app_data`], or returns `None` if no such data
+    /// was stored.
+    ///
+    /// Returns `Error::AppDataBorrowError` if the data is currently borrowed mutably, for example
+    /// by a Rust callback further up the call stack.
+    ///
+    /// [`set_app_data`]: #method.set_app_data
+    pub fn app_data_ref<T: 'static + Send>(&self) -> Result<Option<Ref<T>>> {
+        match self.app_data_cell::<T>() {
+            Some(cell) => {
+                let data = cell.try_borrow().map_err(|_| Error::AppDataBorrowError)?;
+                Ok(Some(Ref::map(data, |data| data.downcast_ref().unwrap())))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Mutably borrows the data of type `T` stored with [`set_app_data`], or returns `None` if no
+    /// such data was stored.
     ///
-    /// You may call this function to manually remove a value placed in the registry with
-    /// [`create_registry_value`]. In addition to manual `RegistryKey` removal, you can also call
-    /// [`expire_registry_values`] to automatically remove values from the registry whose
-    /// `RegistryKey`s have been dropped.
+    /// Returns `Error::AppDataBorrowMutError` if the data is currently borrowed, for example by a
+    /// Rust callback further up the call stack.
     ///
-    /// [`create_registry_value`]: #method.create_registry_value
-    /// [`expire_registry_values`]: #method.expire_registry_values
-    pub fn remove_registry_value(&self, key: RegistryKey) -> Result<()> {
-        unsafe {
-            if !self.owns_registry_value(&key) {
-                return Err(Error::MismatchedRegistryKey);
+    /// [`set_app_data`]: #method.set_app_data
+    pub fn app_data_mut<T: 'static + Send>(&self) -> Result<Option<RefMut<T>>> {
+        match self.app_data_cell::<T>() {
+            Some(cell) => {
+                let data = cell
+                    .try_borrow_mut()
+                    .map_err(|_| Error::AppDataBorrowMutError)?;
+                Ok(Some(RefMut::map(data, |data| data.downcast_mut().unwrap())))
             }
+            None => Ok(None),
+        }
+    }
 
-            ffi::luaL_unref(self.state, ffi::LUA_REGISTRYINDEX, key.take());
-            Ok(())
+    /// Removes the data of type `T` stored with [`set_app_data`] and returns it, or returns `None`
+    /// if no such data was stored.
+    ///
+    /// Returns `Error::AppDataBorrowMutError` if the data is currently borrowed.
+    ///
+    /// [`set_app_data`]: #method.set_app_data
+    pub fn remove_app_data<T: 'static + Send>(&self) -> Result<Option<T>> {
+        match self.app_data_cell::<T>() {
+            Some(cell) => {
+                cell.try_borrow_mut()
+                    .map_err(|_| Error::AppDataBorrowMutError)?;
+            }
+            None => return Ok(None),
         }
+        let app_data = unsafe { &(*extra_data(self.state)).app_data };
+        let data = app_data.borrow_mut().remove(&TypeId::of::<T>()).unwrap();
+        Ok(data.into_inner().downcast().ok().map(|data| *data))
     }
 
-    /// Returns true if the given `RegistryKey` was created by a `Lua` which shares the underlying
-    /// main state with this `Lua` instance.
+    /// Returns information about the function running at the given level of the call stack, or
+    /// `None` if the stack has fewer levels.
     ///
-    /// Other than this, methods that accept a `RegistryKey` will return
-    /// `Error::MismatchedRegistryKey` if passed a `RegistryKey` that was not created with a
-    /// matching `Lua` state.
-    pub fn owns_registry_value(&self, key: &RegistryKey) -> bool {
+    /// Level 0 is the currently running function, which inside a Rust callback is the callback
+    /// itself, level 1 is the function that called it, and so on.  Outside of any callback there
+    /// is no running function, so this always returns `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let caller_line = lua.create_function(|lua, ()| {
+    ///     Ok(lua.debug_info(1).and_then(|info| info.current_line))
+    /// })?;
+    /// lua.globals().set("caller_line", caller_line)?;
+    ///
+    /// let line: u32 = lua.eval("\n\ncaller_line()", None)?;
+    /// assert_eq!(line, 3);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn debug_info(&self, level: u32) -> Option<DebugInfo> {
         unsafe {
-            Arc::ptr_eq(
-                &key.unref_list,
-                &(*extra_data(self.state)).registry_unref_list,
-            )
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            if ffi::lua_getstack(self.state, level as c_int, &mut ar) == 0 {
+                return None;
+            }
+            rlua_assert!(
+                ffi::lua_getinfo(self.state, cstr!("nSl"), &mut ar) != 0,
+                "lua_getinfo failed with valid options"
+            );
+            Some(DebugInfo::from_lua_debug(&ar))
         }
     }
 
     /// Remove any registry values whose `RegistryKey`s have all been dropped.
     ///
-    /// Unlike normal handle values, `RegistryKey`s do not automatically remove themselves on Drop,
-    /// but you can call this method to remove any unreachable registry values not manually removed
-    /// by `Lua::remove_registry_value`.
+    /// Unlike normal handle values, `RegistryKey`s do not remove their value on Drop, since they
+    /// may be dropped at any time, from any thread, and without access to the `Lua` state.
+    /// Instead, the slot of a dropped key is queued and its value is removed the next time this
+    /// method runs, after which Lua can collect the value.
+    ///
+    /// This happens automatically whenever `create_registry_value` or `gc_collect` is called, so
+    /// dropped values never accumulate for a program that keeps creating registry values.  Call
+    /// this method to remove them at any other time, such as before an incremental collection.
     pub fn expire_registry_values(&self) {
         unsafe {
             let unref_list = mem::replace(
@@ -708,6 +2627,63 @@ impl Lua {
         }
     }
 
+    // Returns the `package` table, or an error if the `package` library is not loaded.
+    fn package_table(&self) -> Result<Table> {
+        match self.globals().get::<_, Value>("package")? {
+            Value::Table(package) => Ok(package),
+            _ => Err(Error::RuntimeError("the package library is not loaded".to_owned())),
+        }
+    }
+
+    // Compiles a chunk for `exec` (or `eval`, which first tries the source as an expression),
+    // going through the compiled chunk cache if it is enabled.
+    fn compile_cached(&self, source: &str, name: Option<&str>, eval: bool) -> Result<Function> {
+        let compile = || {
+            if eval {
+                // First, try interpreting the lua as an expression by adding
+                // "return", then as a statement.  This is the same thing the
+                // actual lua repl does.
+                self.load(
+                    format!("return {}", source).as_bytes(),
+                    name,
+                    ChunkMode::Text,
+                ).or_else(|_| self.load(source.as_bytes(), name, ChunkMode::Text))
+            } else {
+                self.load(source.as_bytes(), name, ChunkMode::Text)
+            }
+        };
+
+        unsafe {
+            let extra = extra_data(self.state);
+            if (*extra).compiled_cache.capacity == 0 {
+                return compile();
+            }
+
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 3);
+
+            let hash = CompiledCache::hash(source, name, eval);
+            if let Some(registry_id) = (*extra).compiled_cache.get(hash, source, name, eval) {
+                ffi::lua_rawgeti(self.state, ffi::LUA_REGISTRYINDEX, registry_id as Integer);
+                // A previous run may have changed the environment of the chunk with `setfenv`, so
+                // give it the globals table back, as a freshly compiled chunk would have.
+                ffi::lua_pushglobaltable(self.state);
+                ffi::lua_setfenv(self.state, -2);
+                return Ok(Function(self.pop_ref()));
+            }
+
+            let function = compile()?;
+            self.push_ref(&function.0);
+            let registry_id = protect_lua_closure(self.state, 1, 0, |state| {
+                ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+            })?;
+            (*extra)
+                .compiled_cache
+                .insert(self.state, hash, source, name, eval, registry_id);
+            Ok(function)
+        }
+    }
+
     // Pushes a LuaRef value onto the stack, uses 1 stack space, does not call checkstack
     pub(crate) unsafe fn push_ref<'lua>(&'lua self, lref: &LuaRef<'lua>) {
 //        assert!(
@@ -735,6 +2711,27 @@ impl Lua {
         LuaRef { lua: self, index }
     }
 
+    // Returns true if both references refer to the same Lua object, without invoking `__eq`.
+    pub(crate) fn ref_equal(&self, a: &LuaRef, b: &LuaRef) -> bool {
+        unsafe {
+            let extra = extra_data(self.state);
+            if extra != extra_data(b.lua.state) {
+                return false;
+            }
+            ffi::lua_rawequal((*extra).ref_thread, a.index, b.index) != 0
+        }
+    }
+
+    // Returns the address of the referenced object.  It identifies the object for as long as the
+    // object is alive, but may be reused for another object once it has been collected.  This is
+    // null for strings, which are compared by value.
+    pub(crate) fn ref_pointer(&self, lref: &LuaRef) -> *const c_void {
+        unsafe {
+            let extra = extra_data(self.state);
+            ffi::lua_topointer((*extra).ref_thread, lref.index)
+        }
+    }
+
     pub(crate) fn clone_ref<'lua>(&'lua self, lref: &LuaRef<'lua>) -> LuaRef<'lua> {
         unsafe {
             let extra = extra_data(self.state);
@@ -753,6 +2750,71 @@ impl Lua {
         }
     }
 
+    // Creates a table with space preallocated for `narr` sequence elements and `nrec` other
+    // elements, and fills it with values from an iterator.
+    fn create_table_with_capacity_from<'lua, K, V, I>(
+        &'lua self,
+        narr: usize,
+        nrec: usize,
+        cont: I,
+    ) -> Result<Table<'lua>>
+    where
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let narr = cmp::min(narr, c_int::max_value() as usize) as c_int;
+        let nrec = cmp::min(nrec, c_int::max_value() as usize) as c_int;
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            // `Lua` instance assumes that on any callback, the Lua stack has at least LUA_MINSTACK
+            // slots available to avoid panics.
+            check_stack(self.state, 5 + ffi::LUA_MINSTACK)?;
+
+            protect_lua_closure(self.state, 0, 1, |state| {
+                ffi::lua_createtable(state, narr, nrec)
+            })?;
+
+            for (k, v) in cont {
+                self.push_value(k.to_lua(self)?);
+                self.push_value(v.to_lua(self)?);
+                unsafe extern "C" fn raw_set(state: *mut ffi::lua_State) -> c_int {
+                    ffi::lua_rawset(state, -3);
+                    1
+                }
+                protect_lua(self.state, 3, raw_set)?;
+            }
+            Ok(Table(self.pop_ref()))
+        }
+    }
+
+    // Returns the cell holding the app data of type `T`.  Each cell is boxed, so it stays in place
+    // when the map grows, and it is only removed by `remove_app_data`, which checks that it is not
+    // borrowed, or dropped with the `Lua`.  This is what allows handing out borrows of the cell
+    // that outlive the borrow of the map.
+    fn app_data_cell<T: 'static>(&self) -> Option<&RefCell<Box<Any + Send>>> {
+        unsafe {
+            let app_data = (*extra_data(self.state)).app_data.borrow();
+            app_data
+                .get(&TypeId::of::<T>())
+                .map(|cell| &*(&**cell as *const RefCell<Box<Any + Send>>))
+        }
+    }
+
+    // Sets `callback` as the count hook of the current thread.
+    pub(crate) unsafe fn install_hook(&self, mask: HookMask, callback: HookCallback) {
+        (*extra_data(self.state)).hook_callback = Some(callback);
+        (*extra_data(self.state)).scoped_hook_callback = None;
+        set_hook_mask(self.state, mask);
+    }
+
+    // Sets `callback` as the hook of the current thread, in place of any `HookCallback`.  The hook
+    // must be removed, usually by a `HookGuard`, before the closure of `callback` is dropped.
+    pub(crate) unsafe fn install_scoped_hook(&self, mask: HookMask, callback: ScopedHookCallback) {
+        (*extra_data(self.state)).scoped_hook_callback = Some(callback);
+        set_hook_mask(self.state, mask);
+    }
+
     // Creates a Function out of a Callback containing a 'static Fn.  This is safe ONLY because the
     // Fn is 'static, otherwise it could capture 'callback arguments improperly.  Without ATCs, we
     // cannot easily deal with the "correct" callback type of:
@@ -765,12 +2827,40 @@ impl Lua {
         &'lua self,
         func: Callback<'callback, 'static>,
     ) -> Result<Function<'lua>> {
+        self.create_callback_with_upvalues(func, MultiValue::new())
+    }
+
+    // Creates a function calling `func`, with `upvalues` stored as upvalues of the function after
+    // the callback itself and their count.  The upvalues are passed to `func` in front of the
+    // arguments.
+    pub(crate) fn create_callback_with_upvalues<'lua, 'callback>(
+        &'lua self,
+        func: Callback<'callback, 'static>,
+        upvalues: MultiValue<'lua>,
+    ) -> Result<Function<'lua>> {
+        // A C closure can have at most 255 upvalues, two of which are the callback and the number
+        // of upvalues.
+        const MAX_UPVALUES: usize = 253;
+
+        // Returned by the protected part of `call_callback` when the error of the callback has been
+        // converted to a Lua value, which has been pushed and should be raised.
+        const RAISE_CONVERTED_ERROR: c_int = -1;
+
         unsafe extern "C" fn call_callback(state: *mut ffi::lua_State) -> c_int {
-            callback_error(state, || {
+            let nresults = callback_error(state, || {
                 if ffi::lua_type(state, ffi::lua_upvalueindex(1)) == ffi::LUA_TNIL {
                     return Err(Error::CallbackDestructed);
                 }
 
+                let extra = extra_data(state);
+                if let Some(max_call_depth) = (*extra).max_call_depth {
+                    if (*extra).call_depth >= max_call_depth {
+                        return Err(Error::CallDepthExceeded);
+                    }
+                }
+                (*extra).call_depth += 1;
+                let _depth_guard = CallDepthGuard(extra);
+
                 let nargs = ffi::lua_gettop(state);
                 if nargs < ffi::LUA_MINSTACK {
                     check_stack(state, ffi::LUA_MINSTACK - nargs)?;
@@ -783,15 +2873,37 @@ impl Lua {
                     _phantom: PhantomData,
                 };
 
+                let nupvalues = ffi::lua_tointeger(state, ffi::lua_upvalueindex(2)) as c_int;
+
                 let mut args = MultiValue::new();
-                args.reserve(nargs as usize);
+                args.reserve((nargs + nupvalues) as usize);
                 for _ in 0..nargs {
                     args.push_front(lua.pop_value());
                 }
+                for i in (0..nupvalues).rev() {
+                    ffi::lua_pushvalue(state, ffi::lua_upvalueindex(i + 3));
+                    args.push_front(lua.pop_value());
+                }
 
                 let func = get_userdata::<Callback>(state, ffi::lua_upvalueindex(1));
 
-                let results = (*func)(&lua, args)?;
+                let results = match (*func)(&lua, args) {
+                    Ok(results) => results,
+                    Err(err) => {
+                        let converter = match (*extra).error_converter {
+                            Some(ref converter) => converter.clone(),
+                            None => return Err(err),
+                        };
+                        // If the conversion fails, the original error is raised instead.
+                        return match converter(&lua, &err) {
+                            Ok(value) if check_stack(state, 1).is_ok() => {
+                                lua.push_value(value);
+                                Ok(RAISE_CONVERTED_ERROR)
+                            }
+                            _ => Err(err),
+                        };
+                    }
+                };
                 let nresults = results.len() as c_int;
 
                 check_stack(state, nresults)?;
@@ -800,12 +2912,26 @@ impl Lua {
                 }
 
                 Ok(nresults)
-            })
+            });
+
+            if nresults == RAISE_CONVERTED_ERROR {
+                ffi::lua_error(state)
+            } else {
+                nresults
+            }
+        }
+
+        if upvalues.len() > MAX_UPVALUES {
+            return Err(Error::RuntimeError(format!(
+                "too many upvalues, a function can have at most {}",
+                MAX_UPVALUES
+            )));
         }
+        let nupvalues = upvalues.len() as c_int;
 
         unsafe {
             let _sg = StackGuard::new(self.state);
-            assert_stack(self.state, 4);
+            check_stack(self.state, nupvalues + 5)?;
 
             push_userdata::<Callback>(self.state, func)?;
 
@@ -816,8 +2942,13 @@ impl Lua {
             ffi::lua_rawget(self.state, ffi::LUA_REGISTRYINDEX);
             ffi::lua_setmetatable(self.state, -2);
 
-            protect_lua_closure(self.state, 1, 1, |state| {
-                ffi::lua_pushcclosure(state, call_callback, 1);
+            ffi::lua_pushinteger(self.state, nupvalues as ffi::lua_Integer);
+            for upvalue in upvalues {
+                self.push_value(upvalue);
+            }
+
+            protect_lua_closure(self.state, nupvalues + 2, 1, |state| {
+                ffi::lua_pushcclosure(state, call_callback, nupvalues + 2);
             })?;
 
             Ok(Function(self.pop_ref()))
@@ -828,56 +2959,401 @@ impl Lua {
 // Data associated with the main lua_State via lua_getextraspace.
 struct ExtraData {
     registry_unref_list: Arc<Mutex<Option<Vec<c_int>>>>,
+    mem_info: *mut MemoryInfo,
+    hook_callback: Option<HookCallback>,
+    // Takes precedence over `hook_callback` while set, see `Lua::install_scoped_hook`.
+    scoped_hook_callback: Option<ScopedHookCallback>,
+    // The events the hook callback asked for, and the instructions run since its last count
+    // event, see `set_hook_mask`.
+    hook_mask: HookMask,
+    hook_elapsed: c_int,
+    error_converter: Option<ErrorConverter>,
+    call_depth: usize,
+    max_call_depth: Option<usize>,
+    panic_policy: PanicPolicy,
 
     ref_thread: *mut ffi::lua_State,
     ref_stack_size: c_int,
     ref_stack_max: c_int,
     ref_free: Vec<c_int>,
+
+    compiled_cache: CompiledCache,
+    // Strings created by `Lua::intern`, keyed by their contents.
+    interned_strings: HashMap<Vec<u8>, c_int>,
+    // Data stored with `Lua::set_app_data`, keyed by its type.
+    app_data: RefCell<HashMap<TypeId, Box<RefCell<Box<Any + Send>>>>>,
+    // The flag shared by the handles returned by `Lua::interrupt_handle`.
+    interrupt: Option<Arc<AtomicBool>>,
+    // The thread of the `AsyncThread` currently being polled, and the waker it was polled with.
+    #[cfg(feature = "async")]
+    async_waker: Option<(*mut ffi::lua_State, Waker)>,
+}
+
+// Chunks compiled by `exec` and `eval`, stored in the registry and keyed by a hash of their source
+// and name.  The source and name are kept to rule out hash collisions.
+struct CompiledCache {
+    capacity: usize,
+    entries: HashMap<u64, CompiledChunk>,
+    clock: u64,
+}
+
+// The 64-bit FNV-1a hash, which is much cheaper than the default SipHash for hashing the whole
+// source of a chunk on every `exec` and `eval`.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+struct CompiledChunk {
+    source: StdString,
+    name: Option<StdString>,
+    eval: bool,
+    registry_id: c_int,
+    last_used: u64,
+}
+
+impl CompiledCache {
+    fn hash(source: &str, name: Option<&str>, eval: bool) -> u64 {
+        let mut hasher = FnvHasher::default();
+        (source, name, eval).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get(&mut self, hash: u64, source: &str, name: Option<&str>, eval: bool) -> Option<c_int> {
+        self.clock += 1;
+        let clock = self.clock;
+        match self.entries.get_mut(&hash) {
+            Some(chunk)
+                if chunk.source == source && chunk.name.as_ref().map(|n| n.as_str()) == name
+                    && chunk.eval == eval =>
+            {
+                chunk.last_used = clock;
+                Some(chunk.registry_id)
+            }
+            _ => None,
+        }
+    }
+
+    unsafe fn insert(
+        &mut self,
+        state: *mut ffi::lua_State,
+        hash: u64,
+        source: &str,
+        name: Option<&str>,
+        eval: bool,
+        registry_id: c_int,
+    ) {
+        if let Some(old) = self.entries.remove(&hash) {
+            ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, old.registry_id);
+        }
+        while self.entries.len() >= self.capacity {
+            self.evict(state);
+        }
+        self.entries.insert(
+            hash,
+            CompiledChunk {
+                source: source.to_owned(),
+                name: name.map(|n| n.to_owned()),
+                eval,
+                registry_id,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    // Removes the least recently used chunk.  Caches are expected to be small, so a linear search
+    // is fine here.
+    unsafe fn evict(&mut self, state: *mut ffi::lua_State) {
+        let lru = self
+            .entries
+            .iter()
+            .min_by_key(|&(_, chunk)| chunk.last_used)
+            .map(|(&hash, _)| hash);
+        if let Some(chunk) = lru.and_then(|hash| self.entries.remove(&hash)) {
+            ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, chunk.registry_id);
+        }
+    }
+}
+
+// Registry names used by the Lua standard library, which must not be overwritten.
+const RESERVED_REGISTRY_NAMES: &[&str] = &["_LOADED", "_PRELOAD", "FILE*"];
+
+// Size of the blocks in which `Lua::load_from_reader` reads its source.
+const READER_BUFFER_SIZE: usize = 8192;
+
+fn check_registry_name(name: &str) -> Result<()> {
+    if RESERVED_REGISTRY_NAMES.contains(&name) {
+        Err(Error::RuntimeError(format!(
+            "registry name '{}' is reserved by the Lua standard library",
+            name
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+// Converts a chunk name given to `Lua::load` to the name passed to Lua.
+fn chunk_name(name: &str) -> Result<CString> {
+    let name = if name.starts_with('@') || name.starts_with('=') {
+        name.to_owned()
+    } else {
+        format!("={}", name)
+    };
+    CString::new(name).map_err(|e| Error::ToLuaConversionError {
+        from: "&str",
+        to: "string",
+        message: Some(e.to_string()),
+    })
 }
 
 unsafe fn extra_data(state: *mut ffi::lua_State) -> *mut ExtraData {
     *(ffi::lua_getextraspace(state) as *mut *mut ExtraData)
 }
 
-unsafe fn create_lua(load_debug: bool) -> Lua {
+// Sets the thread being polled by an `AsyncThread` and its waker, returning the previous ones.
+#[cfg(feature = "async")]
+pub(crate) unsafe fn set_async_waker(
+    state: *mut ffi::lua_State,
+    waker: Option<(*mut ffi::lua_State, Waker)>,
+) -> Option<(*mut ffi::lua_State, Waker)> {
+    mem::replace(&mut (*extra_data(state)).async_waker, waker)
+}
+
+// Returns the waker to pass to futures polled by async functions called from `state`, which is
+// only set if `state` is the thread being polled by an `AsyncThread`.  Coroutines resumed by that
+// thread must not poll futures, since they would yield to their resumer instead of to the
+// `AsyncThread`.
+#[cfg(feature = "async")]
+unsafe fn async_waker(state: *mut ffi::lua_State) -> Option<Waker> {
+    match (*extra_data(state)).async_waker {
+        Some((thread, ref waker)) if thread == state => Some(waker.clone()),
+        _ => None,
+    }
+}
+
+// The value yielded by a coroutine waiting for the future of an async function.
+#[cfg(feature = "async")]
+pub(crate) fn async_pending() -> *mut c_void {
+    &ASYNC_PENDING as *const u8 as *mut c_void
+}
+
+pub(crate) unsafe fn panic_policy(state: *mut ffi::lua_State) -> PanicPolicy {
+    (*extra_data(state)).panic_policy
+}
+
+unsafe extern "C" fn hook_proc(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
+    callback_error(state, || {
+        if let Some(ref interrupt) = (*extra_data(state)).interrupt {
+            if interrupt.load(Ordering::SeqCst) {
+                return Err(Error::Interrupted);
+            }
+        }
+
+        // The count events of the interrupt check are only passed on if the hook asked for them,
+        // and only once they add up to its own count.
+        let extra = extra_data(state);
+        if (*ar).event == ffi::LUA_HOOKCOUNT {
+            let mask = (*extra).hook_mask;
+            if !mask.contains(HookMask::count(1)) {
+                return Ok(());
+            }
+            let count = mask.lua_count();
+            let elapsed = (*extra).hook_elapsed + ffi::lua_gethookcount(state);
+            if elapsed < count {
+                (*extra).hook_elapsed = elapsed;
+                return Ok(());
+            }
+            (*extra).hook_elapsed = elapsed - count;
+        }
+
+        let lua = Lua {
+            state: state,
+            ephemeral: true,
+            _phantom: PhantomData,
+        };
+        if let Some(callback) = (*extra).scoped_hook_callback {
+            return callback.call(&lua, HookInfo::from_lua_debug(state, ar));
+        }
+
+        let callback = match (*extra).hook_callback {
+            Some(ref callback) => callback.clone(),
+            None => return Ok(()),
+        };
+        callback(&lua, HookInfo::from_lua_debug(state, ar))
+    })
+}
+
+// Sets the events the hook callback is called for.  Once an `InterruptHandle` exists, a count hook
+// of at most `INTERRUPT_CHECK_COUNT` instructions is always added to check the interrupt flag.
+pub(crate) unsafe fn set_hook_mask(state: *mut ffi::lua_State, mask: HookMask) {
+    const INTERRUPT_CHECK_COUNT: c_int = 1000;
+
+    let extra = extra_data(state);
+    (*extra).hook_mask = mask;
+    (*extra).hook_elapsed = 0;
+
+    let mut lua_mask = mask.lua_mask();
+    let mut count = mask.lua_count();
+    if (*extra).interrupt.is_some() {
+        lua_mask |= ffi::LUA_MASKCOUNT;
+        count = if count == 0 {
+            INTERRUPT_CHECK_COUNT
+        } else {
+            cmp::min(count, INTERRUPT_CHECK_COUNT)
+        };
+    }
+    if lua_mask == 0 {
+        ffi::lua_sethook(state, None, 0, 0);
+    } else {
+        ffi::lua_sethook(state, Some(hook_proc), lua_mask, count);
+    }
+}
+
+// Saves the hook of a thread, and restores it when dropped.
+pub(crate) struct HookGuard {
+    state: *mut ffi::lua_State,
+    callback: Option<HookCallback>,
+    scoped_callback: Option<ScopedHookCallback>,
+    hook_mask: HookMask,
+    hook_elapsed: c_int,
+    hook: Option<ffi::lua_Hook>,
+    mask: c_int,
+    count: c_int,
+}
+
+impl HookGuard {
+    pub(crate) unsafe fn new(state: *mut ffi::lua_State) -> HookGuard {
+        HookGuard {
+            state,
+            callback: (*extra_data(state)).hook_callback.clone(),
+            scoped_callback: (*extra_data(state)).scoped_hook_callback,
+            hook_mask: (*extra_data(state)).hook_mask,
+            hook_elapsed: (*extra_data(state)).hook_elapsed,
+            hook: ffi::lua_gethook(state),
+            mask: ffi::lua_gethookmask(state),
+            count: ffi::lua_gethookcount(state),
+        }
+    }
+}
+
+impl Drop for HookGuard {
+    fn drop(&mut self) {
+        unsafe {
+            (*extra_data(self.state)).hook_callback = self.callback.take();
+            (*extra_data(self.state)).scoped_hook_callback = self.scoped_callback;
+            (*extra_data(self.state)).hook_mask = self.hook_mask;
+            (*extra_data(self.state)).hook_elapsed = self.hook_elapsed;
+            ffi::lua_sethook(self.state, self.hook, self.mask, self.count);
+        }
+    }
+}
+
+// Decrements the callback call depth when a callback finishes, even if it panics.
+struct CallDepthGuard(*mut ExtraData);
+
+impl Drop for CallDepthGuard {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.0).call_depth -= 1;
+        }
+    }
+}
+
+// Memory accounting for a Lua state, passed as the userdata pointer of the allocator function.
+struct MemoryInfo {
+    used_memory: usize,
+    memory_limit: Option<usize>,
+    // The number of nested `ignore_memory_limit` calls, while which the limit is not enforced.
+    limit_ignored: usize,
+}
+
+// Runs `f` while allocations may exceed the memory limit.  `f` should never panic or longjmp,
+// because this could inadvertently disable the limit.
+pub(crate) unsafe fn ignore_memory_limit<R, F: FnOnce() -> R>(
+    state: *mut ffi::lua_State,
+    f: F,
+) -> R {
+    let mem_info = memory_info(state);
+    (*mem_info).limit_ignored += 1;
+    let r = f();
+    (*mem_info).limit_ignored -= 1;
+    r
+}
+
+// Works with any thread of a Lua state, as all threads share the same allocator.
+unsafe fn memory_info(state: *mut ffi::lua_State) -> *mut MemoryInfo {
+    let mut ud = ptr::null_mut();
+    ffi::lua_getallocf(state, &mut ud);
+    ud as *mut MemoryInfo
+}
+
+unsafe fn create_lua(libs: StdLib, load_debug: bool) -> Lua {
     unsafe extern "C" fn allocator(
-        _: *mut c_void,
+        ud: *mut c_void,
         ptr: *mut c_void,
-        _: usize,
+        osize: usize,
         nsize: usize,
     ) -> *mut c_void {
+        let mem_info = ud as *mut MemoryInfo;
+        // Lua 5.1 always passes an `osize` of zero when `ptr` is null.
         if nsize == 0 {
             libc::free(ptr as *mut libc::c_void);
+            (*mem_info).used_memory -= osize;
             ptr::null_mut()
         } else {
+            let used_memory = (*mem_info).used_memory - osize + nsize;
+            if let Some(limit) = (*mem_info).memory_limit {
+                // Only refuse allocations that grow the memory usage, so that shrinking a block
+                // can never fail.  Inside of `ignore_memory_limit` (and so inside of `gc_guard`)
+                // the limit may be exceeded.
+                if nsize > osize && used_memory > limit && (*mem_info).limit_ignored == 0 {
+                    return ptr::null_mut();
+                }
+            }
+
             let p = libc::realloc(ptr as *mut libc::c_void, nsize);
             if p.is_null() {
                 // We require that OOM results in an abort, and that the lua allocator function
-                // never errors.  Since this is what rust itself normally does on OOM, this is
-                // not really a huge loss.  Importantly, this allows us to turn off the gc, and
-                // then know that calling Lua API functions marked as 'm' will not result in a
-                // 'longjmp' error while the gc is off.
+                // only errors when the memory limit set with `Lua::set_memory_limit` is reached.
+                // Since this is what rust itself normally does on OOM, this is not really a huge
+                // loss.  Importantly, since the limit is not enforced inside of `gc_guard`, this
+                // allows us to call Lua API functions marked as 'm' there and know that they will
+                // not result in a 'longjmp' error.
                 abort!("out of memory in Lua allocation, aborting!");
             } else {
+                (*mem_info).used_memory = used_memory;
                 p as *mut c_void
             }
         }
     }
 
-    let state = ffi::lua_newstate(allocator, ptr::null_mut());
+    let mem_info = Box::into_raw(Box::new(MemoryInfo {
+        used_memory: 0,
+        memory_limit: None,
+        limit_ignored: 0,
+    }));
+    let state = ffi::lua_newstate(allocator, mem_info as *mut c_void);
 
     // Ignores or `unwrap()`s 'm' errors, because we are making the assumption that nothing in
     // the lua standard library will have a `__gc` metamethod error.
 
     // Do not open the debug library, it can be used to cause unsafety.
-    ffi::luaL_requiref(state, cstr!("_G"), ffi::luaopen_base, 1);
-    ffi::luaL_requiref(state, cstr!("table"), ffi::luaopen_table, 1);
-    ffi::luaL_requiref(state, cstr!("io"), ffi::luaopen_io, 1);
-    ffi::luaL_requiref(state, cstr!("os"), ffi::luaopen_os, 1);
-    ffi::luaL_requiref(state, cstr!("string"), ffi::luaopen_string, 1);
-    ffi::luaL_requiref(state, cstr!("math"), ffi::luaopen_math, 1);
-    ffi::luaL_requiref(state, cstr!("package"), ffi::luaopen_package, 1);
-    ffi::lua_pop(state, 7);
+    load_std_libs(state, libs);
 
     init_error_metatables(state);
 
@@ -905,20 +3381,6 @@ unsafe fn create_lua(load_debug: bool) -> Lua {
 
     ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX);
 
-    // Override pcall and xpcall with versions that cannot be used to catch rust panics.
-
-    ffi::lua_pushglobaltable(state);
-
-    push_string(state, "pcall").unwrap();
-    ffi::lua_pushcfunction(state, safe_pcall);
-    ffi::lua_rawset(state, -3);
-
-    push_string(state, "xpcall").unwrap();
-    ffi::lua_pushcfunction(state, safe_xpcall);
-    ffi::lua_rawset(state, -3);
-
-    ffi::lua_pop(state, 1);
-
     // Create ref stack thread and place it in the registry to prevent it from being garbage
     // collected.
 
@@ -929,11 +3391,30 @@ unsafe fn create_lua(load_debug: bool) -> Lua {
 
     let extra = Box::into_raw(Box::new(ExtraData {
         registry_unref_list: Arc::new(Mutex::new(Some(Vec::new()))),
+        mem_info,
+        hook_callback: None,
+        scoped_hook_callback: None,
+        hook_mask: HookMask::NONE,
+        hook_elapsed: 0,
+        error_converter: None,
+        call_depth: 0,
+        max_call_depth: None,
+        panic_policy: PanicPolicy::Resume,
         ref_thread,
         // We need 1 extra stack space to move values in and out of the ref stack.
         ref_stack_size: ffi::LUA_MINSTACK - 1,
         ref_stack_max: 0,
         ref_free: Vec::new(),
+        compiled_cache: CompiledCache {
+            capacity: 0,
+            entries: HashMap::new(),
+            clock: 0,
+        },
+        interned_strings: HashMap::new(),
+        app_data: RefCell::new(HashMap::new()),
+        interrupt: None,
+        #[cfg(feature = "async")]
+        async_waker: None,
     }));
     *(ffi::lua_getextraspace(state) as *mut *mut ExtraData) = extra;
 
@@ -948,6 +3429,39 @@ unsafe fn create_lua(load_debug: bool) -> Lua {
     }
 }
 
+unsafe fn load_std_libs(state: *mut ffi::lua_State, libs: StdLib) {
+    let std_libs: [(StdLib, *const c_char, ffi::lua_CFunction); 7] = [
+        (StdLib::BASE, cstr!("_G"), ffi::luaopen_base),
+        (StdLib::TABLE, cstr!("table"), ffi::luaopen_table),
+        (StdLib::IO, cstr!("io"), ffi::luaopen_io),
+        (StdLib::OS, cstr!("os"), ffi::luaopen_os),
+        (StdLib::STRING, cstr!("string"), ffi::luaopen_string),
+        (StdLib::MATH, cstr!("math"), ffi::luaopen_math),
+        (StdLib::PACKAGE, cstr!("package"), ffi::luaopen_package),
+    ];
+    for &(lib, name, open) in &std_libs {
+        if libs.contains(lib) {
+            ffi::luaL_requiref(state, name, open, 1);
+            ffi::lua_pop(state, 1);
+        }
+    }
+
+    // Override pcall and xpcall with versions that cannot be used to catch rust panics.
+    if libs.contains(StdLib::BASE) {
+        ffi::lua_pushglobaltable(state);
+
+        ffi::lua_pushstring(state, cstr!("pcall"));
+        ffi::lua_pushcfunction(state, safe_pcall);
+        ffi::lua_rawset(state, -3);
+
+        ffi::lua_pushstring(state, cstr!("xpcall"));
+        ffi::lua_pushcfunction(state, safe_xpcall);
+        ffi::lua_rawset(state, -3);
+
+        ffi::lua_pop(state, 1);
+    }
+}
+
 unsafe fn ref_stack_pop(extra: *mut ExtraData) -> c_int {
     if let Some(free) = (*extra).ref_free.pop() {
         ffi::lua_replace((*extra).ref_thread, free);
@@ -956,7 +3470,7 @@ unsafe fn ref_stack_pop(extra: *mut ExtraData) -> c_int {
         if (*extra).ref_stack_max >= (*extra).ref_stack_size {
             // It is a user error to create enough references to exhaust the Lua max stack size for
             // the ref thread.
-            if ffi::lua_checkstack((*extra).ref_thread, (*extra).ref_stack_size) == 0 {
+            if !grow_stack((*extra).ref_thread, (*extra).ref_stack_size) {
                 panic!("cannot create a Lua reference, out of auxillary stack space");
             }
             (*extra).ref_stack_size *= 2;
@@ -967,4 +3481,6 @@ unsafe fn ref_stack_pop(extra: *mut ExtraData) -> c_int {
 }
 
 static FUNCTION_METATABLE_REGISTRY_KEY: u8 = 0;
+#[cfg(feature = "async")]
+static ASYNC_PENDING: u8 = 0;
 
@@ -1,8 +1,9 @@
 use std::iter::FromIterator;
 use std::ops::{Deref, DerefMut};
 use std::result::Result as StdResult;
+use std::sync::Arc;
 
-use error::Result;
+use error::{Error, Result};
 use lua::Lua;
 use value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti};
 
@@ -57,6 +58,11 @@ impl<'lua> FromLuaMulti<'lua> for MultiValue<'lua> {
 /// using [`FromLua`]. `Variadic<T>` can also be returned from a callback, returning a variable
 /// number of values to Lua.
 ///
+/// Since it consumes all of the remaining values, a `Variadic` can only be used as the last element
+/// of a tuple.  If one of the values cannot be converted, the conversion fails with
+/// `Error::BadArgument` holding the position of that value among all of the values being converted.
+/// This is not the case for a single type that is not a tuple, see `Error::BadArgument`.
+///
 /// The [`MultiValue`] type is equivalent to `Variadic<Value>`.
 ///
 /// # Examples
@@ -130,12 +136,32 @@ impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for Variadic<T> {
     fn from_lua_multi(values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
         values
             .into_iter()
-            .map(|e| T::from_lua(e, lua))
+            .enumerate()
+            .map(|(i, e)| T::from_lua(e, lua).map_err(|err| bad_argument(i + 1, err)))
             .collect::<Result<Vec<T>>>()
             .map(Variadic)
     }
 }
 
+// Wraps the error from converting the value at `position` in `Error::BadArgument`.  If `err` is
+// already a `BadArgument`, its position is relative to `position` (as with the trailing values of
+// a tuple), so it is only shifted.
+fn bad_argument(position: usize, err: Error) -> Error {
+    match err {
+        Error::BadArgument {
+            position: inner,
+            cause,
+        } => Error::BadArgument {
+            position: position + inner - 1,
+            cause,
+        },
+        cause => Error::BadArgument {
+            position,
+            cause: Arc::new(cause),
+        },
+    }
+}
+
 macro_rules! impl_tuple {
     () => (
         impl<'lua> ToLuaMulti<'lua> for () {
@@ -174,9 +200,15 @@ macro_rules! impl_tuple {
             #[allow(unused_mut)]
             #[allow(non_snake_case)]
             fn from_lua_multi(mut values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
-                $(let $name = values.pop_front().unwrap_or(Nil);)*
-                let $last = FromLuaMulti::from_lua_multi(values, lua)?;
-                Ok(($(FromLua::from_lua($name, lua)?,)* $last,))
+                let mut position = 0;
+                $(
+                    position += 1;
+                    let $name = FromLua::from_lua(values.pop_front().unwrap_or(Nil), lua)
+                        .map_err(|err| bad_argument(position, err))?;
+                )*
+                let $last = FromLuaMulti::from_lua_multi(values, lua)
+                    .map_err(|err| bad_argument(position + 1, err))?;
+                Ok(($($name,)* $last,))
             }
         }
     );
@@ -1,12 +1,19 @@
 //! Re-exports most types with an extra `Lua*` prefix to prevent name clashes.
 
 pub use {
-    Error as LuaError, ExternalError as LuaExternalError,
-    ExternalResult as LuaExternalResult, FromLua, FromLuaMulti, Function as LuaFunction,
-    Integer as LuaInteger, LightUserData as LuaLightUserData, Lua,
-    MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber, RegistryKey as LuaRegistryKey,
-    Result as LuaResult, Scope as LuaScope, String as LuaString, Table as LuaTable,
+    ChunkMode as LuaChunkMode, DebugInfo as LuaDebugInfo, Entry as LuaEntry, Error as LuaError,
+    ExternalError as LuaExternalError, ExternalResult as LuaExternalResult, FromLua, FromLuaMulti,
+    Function as LuaFunction, FunctionInfo as LuaFunctionInfo, GlobalsSnapshot as LuaGlobalsSnapshot,
+    HookEvent as LuaHookEvent, HookInfo as LuaHookInfo, HookMask as LuaHookMask,
+    Integer as LuaInteger, InterruptHandle as LuaInterruptHandle, LightUserData as LuaLightUserData,
+    Limits as LuaLimits, Lua, LuaBuilder, MultiValue as LuaMultiValue, Nil as LuaNil,
+    Number as LuaNumber, OccupiedEntry as LuaOccupiedEntry, PanicPolicy as LuaPanicPolicy,
+    RegistryKey as LuaRegistryKey, ReplResult as LuaReplResult, Result as LuaResult,
+    Scope as LuaScope, StdLib as LuaStdLib, String as LuaString, Table as LuaTable,
     TablePairs as LuaTablePairs, TableSequence as LuaTableSequence, Thread as LuaThread,
-    ThreadStatus as LuaThreadStatus, ToLua, ToLuaMulti,
-    Value as LuaValue,
+    ThreadIter as LuaThreadIter, ThreadStatus as LuaThreadStatus, ToLua, ToLuaMulti,
+    VacantEntry as LuaVacantEntry, Value as LuaValue, WeakMode as LuaWeakMode,
 };
+
+#[cfg(feature = "async")]
+pub use AsyncThread as LuaAsyncThread;
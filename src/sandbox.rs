@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use stdlib::StdLib;
+
+/// The restrictions under which [`Lua::run_untrusted`] runs a script.
+///
+/// The default only loads the `base`, `table`, `string` and `math` libraries and puts no bound on
+/// memory, instructions or time, so at least one of the bounds should be set, for example with
+/// `Limits { max_instructions: Some(1_000_000), ..Limits::default() }`.
+///
+/// [`Lua::run_untrusted`]: struct.Lua.html#method.run_untrusted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum amount of memory (in bytes) the Lua state may allocate, including the memory
+    /// used by the state itself.
+    pub max_memory: Option<usize>,
+    /// The maximum number of Lua VM instructions the script may execute.
+    ///
+    /// Instructions are counted in batches of up to 1000, so the script may execute up to 999
+    /// instructions more than this.
+    pub max_instructions: Option<u64>,
+    /// The maximum time the script may run for.
+    ///
+    /// The time is checked at the same points as the instruction count, so a single slow call to
+    /// a library function, such as matching a pathological pattern, is not interrupted.
+    pub timeout: Option<Duration>,
+    /// The standard libraries the script may use.
+    ///
+    /// Each library is loaded as it is, so allowing `io`, `os` or `package` gives the script
+    /// access to the system.
+    pub libs: StdLib,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_memory: None,
+            max_instructions: None,
+            timeout: None,
+            libs: StdLib::BASE | StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        }
+    }
+}
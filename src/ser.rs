@@ -0,0 +1,373 @@
+use std::result::Result as StdResult;
+
+use serde::ser::{self, Serialize};
+
+use error::{Error, Result, SerdeError};
+use lua::Lua;
+use table::Table;
+use types::Integer;
+use value::Value;
+
+/// Converts a Rust value implementing `Serialize` into a Lua value.
+///
+/// Structs and maps are converted into tables with the field names / map keys as keys, sequences
+/// and tuples are converted into tables with consecutive integer keys starting at 1.  `None` and
+/// `()` become `nil`, and enum variants are represented the same way `serde_json` represents
+/// them: a unit variant is the string of its name, any other variant is a table with the variant
+/// name as the single key.
+///
+/// Since Lua tables cannot store `nil`, a `None` inside of a sequence leaves a hole in the
+/// resulting table.
+pub fn to_value<'lua, T: ?Sized + Serialize>(lua: &'lua Lua, value: &T) -> Result<Value<'lua>> {
+    value.serialize(Serializer { lua }).map_err(|e| e.0)
+}
+
+struct Serializer<'lua> {
+    lua: &'lua Lua,
+}
+
+impl<'lua> ser::Serializer for Serializer<'lua> {
+    type Ok = Value<'lua>;
+    type Error = SerdeError;
+
+    type SerializeSeq = SerializeSeq<'lua>;
+    type SerializeTuple = SerializeSeq<'lua>;
+    type SerializeTupleStruct = SerializeSeq<'lua>;
+    type SerializeTupleVariant = SerializeVariant<'lua, SerializeSeq<'lua>>;
+    type SerializeMap = SerializeMap<'lua>;
+    type SerializeStruct = SerializeMap<'lua>;
+    type SerializeStructVariant = SerializeVariant<'lua, SerializeMap<'lua>>;
+
+    fn serialize_bool(self, v: bool) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> StdResult<Value<'lua>, SerdeError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> StdResult<Value<'lua>, SerdeError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> StdResult<Value<'lua>, SerdeError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Integer(v as Integer))
+    }
+
+    fn serialize_u8(self, v: u8) -> StdResult<Value<'lua>, SerdeError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> StdResult<Value<'lua>, SerdeError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> StdResult<Value<'lua>, SerdeError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> StdResult<Value<'lua>, SerdeError> {
+        if v <= Integer::max_value() as u64 {
+            Ok(Value::Integer(v as Integer))
+        } else {
+            Ok(Value::Number(v as f64))
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> StdResult<Value<'lua>, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Number(v))
+    }
+
+    fn serialize_char(self, v: char) -> StdResult<Value<'lua>, SerdeError> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_str(self, v: &str) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::String(self.lua.create_string(v)?))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Table(
+            self.lua.create_sequence_from(v.iter().cloned())?,
+        ))
+    }
+
+    fn serialize_none(self) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(
+        self,
+        value: &T,
+    ) -> StdResult<Value<'lua>, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> StdResult<Value<'lua>, SerdeError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> StdResult<Value<'lua>, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> StdResult<Value<'lua>, SerdeError> {
+        let table = self.lua.create_table()?;
+        table.raw_set(variant, value.serialize(self)?)?;
+        Ok(Value::Table(table))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> StdResult<SerializeSeq<'lua>, SerdeError> {
+        Ok(SerializeSeq {
+            lua: self.lua,
+            table: self.lua.create_table()?,
+            index: 1,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> StdResult<SerializeSeq<'lua>, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> StdResult<SerializeSeq<'lua>, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> StdResult<SerializeVariant<'lua, SerializeSeq<'lua>>, SerdeError> {
+        Ok(SerializeVariant {
+            lua: self.lua,
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> StdResult<SerializeMap<'lua>, SerdeError> {
+        Ok(SerializeMap {
+            lua: self.lua,
+            table: self.lua.create_table()?,
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> StdResult<SerializeMap<'lua>, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> StdResult<SerializeVariant<'lua, SerializeMap<'lua>>, SerdeError> {
+        Ok(SerializeVariant {
+            lua: self.lua,
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+struct SerializeSeq<'lua> {
+    lua: &'lua Lua,
+    table: Table<'lua>,
+    index: Integer,
+}
+
+impl<'lua> ser::SerializeSeq for SerializeSeq<'lua> {
+    type Ok = Value<'lua>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> StdResult<(), SerdeError> {
+        let value = value.serialize(Serializer { lua: self.lua })?;
+        self.table.raw_set(self.index, value)?;
+        self.index += 1;
+        Ok(())
+    }
+
+    fn end(self) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+impl<'lua> ser::SerializeTuple for SerializeSeq<'lua> {
+    type Ok = Value<'lua>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> StdResult<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> StdResult<Value<'lua>, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl<'lua> ser::SerializeTupleStruct for SerializeSeq<'lua> {
+    type Ok = Value<'lua>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> StdResult<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> StdResult<Value<'lua>, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct SerializeMap<'lua> {
+    lua: &'lua Lua,
+    table: Table<'lua>,
+    key: Option<Value<'lua>>,
+}
+
+impl<'lua> ser::SerializeMap for SerializeMap<'lua> {
+    type Ok = Value<'lua>;
+    type Error = SerdeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> StdResult<(), SerdeError> {
+        self.key = Some(key.serialize(Serializer { lua: self.lua })?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> StdResult<(), SerdeError> {
+        let key = match self.key.take() {
+            Some(Value::Nil) => {
+                return Err(SerdeError(Error::SerializeError(
+                    "map key serialized to nil".to_owned(),
+                )))
+            }
+            Some(key) => key,
+            None => {
+                return Err(SerdeError(Error::SerializeError(
+                    "map value serialized without a key".to_owned(),
+                )))
+            }
+        };
+        let value = value.serialize(Serializer { lua: self.lua })?;
+        self.table.raw_set(key, value)?;
+        Ok(())
+    }
+
+    fn end(self) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+impl<'lua> ser::SerializeStruct for SerializeMap<'lua> {
+    type Ok = Value<'lua>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> StdResult<(), SerdeError> {
+        let value = value.serialize(Serializer { lua: self.lua })?;
+        self.table.raw_set(key, value)?;
+        Ok(())
+    }
+
+    fn end(self) -> StdResult<Value<'lua>, SerdeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+// Wraps the contents of a tuple or struct variant in a table with the variant name as its only
+// key.
+struct SerializeVariant<'lua, S> {
+    lua: &'lua Lua,
+    variant: &'static str,
+    inner: S,
+}
+
+impl<'lua, S> SerializeVariant<'lua, S> {
+    fn wrap(
+        lua: &'lua Lua,
+        variant: &'static str,
+        value: Value<'lua>,
+    ) -> StdResult<Value<'lua>, SerdeError> {
+        let table = lua.create_table()?;
+        table.raw_set(variant, value)?;
+        Ok(Value::Table(table))
+    }
+}
+
+impl<'lua> ser::SerializeTupleVariant for SerializeVariant<'lua, SerializeSeq<'lua>> {
+    type Ok = Value<'lua>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> StdResult<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> StdResult<Value<'lua>, SerdeError> {
+        let value = ser::SerializeSeq::end(self.inner)?;
+        Self::wrap(self.lua, self.variant, value)
+    }
+}
+
+impl<'lua> ser::SerializeStructVariant for SerializeVariant<'lua, SerializeMap<'lua>> {
+    type Ok = Value<'lua>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> StdResult<(), SerdeError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> StdResult<Value<'lua>, SerdeError> {
+        let value = ser::SerializeStruct::end(self.inner)?;
+        Self::wrap(self.lua, self.variant, value)
+    }
+}
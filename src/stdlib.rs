@@ -0,0 +1,77 @@
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
+
+/// A set of Lua standard libraries, used to select which libraries are loaded into a new Lua
+/// state.
+///
+/// The sets can be combined with the `|`, `&` and `^` operators.  The `debug` library is not part
+/// of any set, since it can be used to break the guarantees of rlua; it can only be loaded with the
+/// unsafe [`LuaBuilder::load_debug`].
+///
+/// [`LuaBuilder::load_debug`]: struct.LuaBuilder.html#method.load_debug
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StdLib(u8);
+
+impl StdLib {
+    /// The base library, which in Lua 5.1 also contains the `coroutine` library.
+    pub const BASE: StdLib = StdLib(0x1);
+    /// The `table` library.
+    pub const TABLE: StdLib = StdLib(0x2);
+    /// The `io` library.
+    pub const IO: StdLib = StdLib(0x4);
+    /// The `os` library.
+    pub const OS: StdLib = StdLib(0x8);
+    /// The `string` library.
+    pub const STRING: StdLib = StdLib(0x10);
+    /// The `math` library.
+    pub const MATH: StdLib = StdLib(0x20);
+    /// The `package` library.
+    pub const PACKAGE: StdLib = StdLib(0x40);
+    /// All of the libraries above, which is what `Lua::new` loads.
+    pub const ALL: StdLib = StdLib(0x7f);
+    /// No libraries.
+    pub const NONE: StdLib = StdLib(0);
+
+    /// Returns true if all of the libraries in `libs` are contained in this set.
+    pub fn contains(self, libs: StdLib) -> bool {
+        self & libs == libs
+    }
+}
+
+impl BitAnd for StdLib {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self::Output {
+        StdLib(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for StdLib {
+    fn bitand_assign(&mut self, rhs: Self) {
+        *self = StdLib(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for StdLib {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        StdLib(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for StdLib {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = StdLib(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for StdLib {
+    type Output = Self;
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        StdLib(self.0 ^ rhs.0)
+    }
+}
+
+impl BitXorAssign for StdLib {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        *self = StdLib(self.0 ^ rhs.0)
+    }
+}
@@ -1,3 +1,6 @@
+use std::borrow::Cow;
+use std::hash::{Hash, Hasher};
+use std::string::String as StdString;
 use std::{slice, str};
 
 use error::{Error, Result};
@@ -42,6 +45,34 @@ impl<'lua> String<'lua> {
         })
     }
 
+    /// Converts this string to a `Cow<str>`, replacing any invalid UTF-8 sequences with
+    /// `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// This never fails and does not copy the string if it is valid UTF-8, which makes it useful
+    /// for best-effort rendering such as logging.  Use [`as_bytes`] to access the exact bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, String, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    ///
+    /// let non_utf8: String = lua.eval(r#"  "test\255"  "#, None)?;
+    /// assert_eq!(non_utf8.to_str_lossy(), "test\u{fffd}");
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`as_bytes`]: #method.as_bytes
+    pub fn to_str_lossy(&self) -> Cow<str> {
+        StdString::from_utf8_lossy(self.as_bytes())
+    }
+
     /// Get the bytes that make up this string.
     ///
     /// The returned slice will not contain the terminating nul byte, but will contain any nul
@@ -86,6 +117,32 @@ impl<'lua> String<'lua> {
             slice::from_raw_parts(data as *const u8, size + 1)
         }
     }
+
+    /// Returns the length of this string in bytes, not counting the trailing nul byte.
+    ///
+    /// Lua strings may contain nul bytes, and they are counted like any other byte.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, String};
+    /// # fn main() {
+    /// let lua = Lua::new();
+    ///
+    /// let s: String = lua.eval(r#" "a\0b" "#, None).unwrap();
+    /// assert_eq!(s.len(), 3);
+    /// assert_eq!(s.as_bytes_with_nul(), b"a\0b\0");
+    /// # }
+    /// ```
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    /// Returns true if this string has a length of zero bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 impl<'lua> AsRef<[u8]> for String<'lua> {
@@ -110,3 +167,12 @@ where
         self.as_bytes() == other.as_ref()
     }
 }
+
+impl<'lua> Eq for String<'lua> {}
+
+// Consistent with `PartialEq`, strings hash their contents, like `[u8]` does.
+impl<'lua> Hash for String<'lua> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
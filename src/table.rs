@@ -1,14 +1,44 @@
+use std::cmp;
 use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::c_int;
 
-use error::Result;
+use error::{Error, Result};
 use ffi;
+use lua::Lua;
 use types::{Integer, LuaRef};
-use util::{assert_stack, protect_lua, protect_lua_closure, StackGuard};
+use util::{assert_stack, check_stack, protect_lua, protect_lua_closure, StackGuard};
 use value::{FromLua, Nil, ToLua, Value};
 
+/// Which references of a table created with [`Lua::create_weak_table`] are weak.
+///
+/// [`Lua::create_weak_table`]: struct.Lua.html#method.create_weak_table
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WeakMode {
+    /// The keys are weak, `__mode = "k"`.
+    Keys,
+    /// The values are weak, `__mode = "v"`.
+    Values,
+    /// Both the keys and the values are weak, `__mode = "kv"`.
+    Both,
+}
+
+impl WeakMode {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            WeakMode::Keys => "k",
+            WeakMode::Values => "v",
+            WeakMode::Both => "kv",
+        }
+    }
+}
+
 /// Handle to an internal Lua table.
-#[derive(Clone, Debug)]
+///
+/// Handles compare equal, and hash the same, if they refer to the same Lua table.  The hash is
+/// based on the address of the table, so it is only meaningful within one `Lua` state and while the
+/// table is alive.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Table<'lua>(pub(crate) LuaRef<'lua>);
 
 impl<'lua> Table<'lua> {
@@ -69,6 +99,45 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Sets all key-value pairs from an iterator in the table.
+    ///
+    /// This is equivalent to calling [`set`] for every pair, but is faster for a large number of
+    /// pairs.  If converting or setting one of the pairs fails, the pairs that were already set
+    /// remain in the table and the error is returned.
+    ///
+    /// This might invoke the `__newindex` metamethod.
+    ///
+    /// [`set`]: #method.set
+    pub fn set_from<K, V, I>(&self, iter: I) -> Result<()>
+    where
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            // Conversions may call back into the `Lua` instance, which assumes that at least
+            // LUA_MINSTACK slots are available.
+            check_stack(lua.state, 5 + ffi::LUA_MINSTACK)?;
+
+            lua.push_ref(&self.0);
+            for (k, v) in iter {
+                let k = k.to_lua(lua)?;
+                let v = v.to_lua(lua)?;
+                lua.push_value(k);
+                lua.push_value(v);
+
+                unsafe extern "C" fn set_table(state: *mut ffi::lua_State) -> c_int {
+                    ffi::lua_settable(state, -3);
+                    1
+                }
+                protect_lua(lua.state, 3, set_table)?;
+            }
+            Ok(())
+        }
+    }
+
     /// Gets the value associated to `key` from the table.
     ///
     /// If no value is associated to `key`, returns the `nil` value.
@@ -118,6 +187,13 @@ impl<'lua> Table<'lua> {
     }
 
     /// Checks whether the table contains a non-nil value for `key`.
+    ///
+    /// This is cheaper than comparing the result of [`get`] to `Nil`, since the value is only
+    /// checked on the Lua stack and never converted.  Like `get`, this might invoke the `__index`
+    /// metamethod. Use the [`raw_contains_key`] method if that is not desired.
+    ///
+    /// [`get`]: #method.get
+    /// [`raw_contains_key`]: #method.raw_contains_key
     pub fn contains_key<K: ToLua<'lua>>(&self, key: K) -> Result<bool> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
@@ -140,7 +216,111 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Gets the value associated to `key`, or inserts and returns the value produced by `f` if
+    /// there is none.
+    ///
+    /// `f` is only called if the current value is `nil`, values like `false` or `0` are returned
+    /// as-is.  Like [`get`] and [`set`], this might invoke the `__index` and `__newindex`
+    /// metamethods.
+    ///
+    /// # Examples
+    ///
+    /// Get a subtable, creating it if it does not exist yet:
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result, Value};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let globals = lua.globals();
+    ///
+    /// let config = globals.get_or_insert_with("config", |lua| {
+    ///     lua.create_table().map(Value::Table)
+    /// })?;
+    /// # let _ = config;    // used
+    /// assert!(globals.contains_key("config")?);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    /// [`set`]: #method.set
+    pub fn get_or_insert_with<K, F>(&self, key: K, f: F) -> Result<Value<'lua>>
+    where
+        K: ToLua<'lua>,
+        F: FnOnce(&'lua Lua) -> Result<Value<'lua>>,
+    {
+        let lua = self.0.lua;
+        let key = key.to_lua(lua)?;
+        match self.get(key.clone())? {
+            Nil => {
+                let value = f(lua)?;
+                self.set(key, value.clone())?;
+                Ok(value)
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Gets the entry for `key`, for reading and updating its value in place.
+    ///
+    /// The current value is read once with [`get`], and the entry writes any new value back with
+    /// [`set`], so both might invoke metamethods.  A `nil` value makes the entry vacant.
+    ///
+    /// The entry does not lock the table: if Lua code changes the value of `key` while the entry
+    /// is held, for example in a callback or a metamethod, the entry still holds the old value and
+    /// the change is overwritten by the next write through the entry.
+    ///
+    /// # Examples
+    ///
+    /// Count words:
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let counts = lua.create_table()?;
+    ///
+    /// for word in "the cat and the hat".split(' ') {
+    ///     counts.entry(word)?.and_modify(|n: i64| Ok(n + 1))?.or_insert(1)?;
+    /// }
+    /// assert_eq!(counts.get::<_, i64>("the")?, 2);
+    /// assert_eq!(counts.get::<_, i64>("cat")?, 1);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    /// [`set`]: #method.set
+    pub fn entry<K: ToLua<'lua>>(&self, key: K) -> Result<Entry<'lua>> {
+        let key = key.to_lua(self.0.lua)?;
+        Ok(match self.get(key.clone())? {
+            Nil => Entry::Vacant(VacantEntry {
+                table: self.clone(),
+                key,
+            }),
+            value => Entry::Occupied(OccupiedEntry {
+                table: self.clone(),
+                key,
+                value,
+            }),
+        })
+    }
+
     /// Sets a key-value pair without invoking metamethods.
+    ///
+    /// Unlike [`set`], this never calls `__newindex`, even if `key` is not present in the table.
+    /// This is what a `__newindex` metamethod should use to store values in its own table, since
+    /// calling `set` there would invoke the metamethod again.
+    ///
+    /// [`set`]: #method.set
     pub fn raw_set<K: ToLua<'lua>, V: ToLua<'lua>>(&self, key: K, value: V) -> Result<()> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
@@ -165,6 +345,11 @@ impl<'lua> Table<'lua> {
     }
 
     /// Gets the value associated to `key` without invoking metamethods.
+    ///
+    /// Unlike [`get`], this never calls `__index`, so a key that is not present in the table
+    /// always results in `nil`.
+    ///
+    /// [`get`]: #method.get
     pub fn raw_get<K: ToLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
@@ -180,9 +365,33 @@ impl<'lua> Table<'lua> {
         V::from_lua(value, lua)
     }
 
+    /// Checks whether the table contains a non-nil value for `key`, without invoking metamethods.
+    ///
+    /// This is to [`contains_key`] what [`raw_get`] is to [`get`].
+    ///
+    /// [`contains_key`]: #method.contains_key
+    /// [`raw_get`]: #method.raw_get
+    /// [`get`]: #method.get
+    pub fn raw_contains_key<K: ToLua<'lua>>(&self, key: K) -> Result<bool> {
+        let lua = self.0.lua;
+        let key = key.to_lua(lua)?;
+
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 3);
+
+            lua.push_ref(&self.0);
+            lua.push_value(key);
+            ffi::lua_rawget(lua.state, -2);
+            Ok(ffi::lua_isnil(lua.state, -1) == 0)
+        }
+    }
+
     /// Returns the result of the Lua `#` operator.
     ///
-    /// This might invoke the `__len` metamethod. Use the [`raw_len`] method if that is not desired.
+    /// This might invoke the `__len` metamethod, which is how scripts give custom sequence types a
+    /// length, so this fails if the metamethod raises an error or does not return an integer.  Use
+    /// the [`raw_len`] method if that is not desired.
     ///
     /// [`raw_len`]: #method.raw_len
     pub fn len(&self) -> Result<Integer> {
@@ -196,6 +405,11 @@ impl<'lua> Table<'lua> {
     }
 
     /// Returns the result of the Lua `#` operator, without invoking the `__len` metamethod.
+    ///
+    /// The result is a *border* of the table: an index `n` such that `t[n]` is not `nil` and
+    /// `t[n + 1]` is `nil`, or 0 if `t[1]` is `nil`.  For a proper sequence this is its length, but
+    /// if the table has holes any border may be returned, so the result says little about such a
+    /// table.
     pub fn raw_len(&self) -> Integer {
         let lua = self.0.lua;
         unsafe {
@@ -207,6 +421,131 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Compares this table with `other` using the Lua `==` operator.
+    ///
+    /// This might invoke the `__eq` metamethod, which Lua only does when both tables are distinct
+    /// and their metatables have the same `__eq` metamethod.  An error raised by the metamethod is
+    /// returned as an `Error`.  Use the [`raw_equal`] method to only compare identity.
+    ///
+    /// [`raw_equal`]: #method.raw_equal
+    pub fn equals(&self, other: &Table<'lua>) -> Result<bool> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 5);
+            lua.push_ref(&self.0);
+            lua.push_ref(&other.0);
+            protect_lua_closure(lua.state, 2, 0, |state| {
+                ffi::lua_compare(state, -2, -1, ffi::LUA_OPEQ) != 0
+            })
+        }
+    }
+
+    /// Returns true if this table and `other` are the same table, without invoking the `__eq`
+    /// metamethod.
+    ///
+    /// This is the same comparison as `==` on `Table` handles.
+    pub fn raw_equal(&self, other: &Table<'lua>) -> bool {
+        self.0 == other.0
+    }
+
+    /// Inserts `value` at position `index`, shifting up the elements at `index` and above.
+    ///
+    /// This works like the Lua `table.insert` function: `index` must be between 1 and
+    /// [`len`]` + 1`, otherwise an error is returned and the table is left unchanged.  The elements
+    /// are moved using [`get`] and [`set`], so this might invoke the `__len`, `__index` and
+    /// `__newindex` metamethods.
+    ///
+    /// [`len`]: #method.len
+    /// [`get`]: #method.get
+    /// [`set`]: #method.set
+    pub fn insert<V: ToLua<'lua>>(&self, index: Integer, value: V) -> Result<()> {
+        let value = value.to_lua(self.0.lua)?;
+        let len = self.len()?;
+        if index < 1 || index > len + 1 {
+            return Err(out_of_bounds(index, len));
+        }
+        for i in (index..len + 1).rev() {
+            let v: Value = self.get(i)?;
+            self.set(i + 1, v)?;
+        }
+        self.set(index, value)
+    }
+
+    /// Appends `value` to the end of the sequence, at position [`len`]` + 1`.
+    ///
+    /// This is equivalent to the Lua `table.insert(t, value)`, and might invoke the `__len` and
+    /// `__newindex` metamethods.
+    ///
+    /// [`len`]: #method.len
+    pub fn push<V: ToLua<'lua>>(&self, value: V) -> Result<()> {
+        let len = self.len()?;
+        self.set(len + 1, value)
+    }
+
+    /// Removes and returns the element at position `index`, shifting down the elements above it.
+    ///
+    /// This works like the Lua `table.remove` function: `index` must be between 1 and
+    /// [`len`]` + 1`, or equal to [`len`] (which allows an index of 0 for an empty sequence),
+    /// otherwise an error is returned and the table is left unchanged.  Like [`insert`], this might
+    /// invoke the `__len`, `__index` and `__newindex` metamethods.
+    ///
+    /// [`len`]: #method.len
+    /// [`insert`]: #method.insert
+    pub fn remove(&self, index: Integer) -> Result<Value<'lua>> {
+        let len = self.len()?;
+        if index != len && (index < 1 || index > len + 1) {
+            return Err(out_of_bounds(index, len));
+        }
+        let value = self.get(index)?;
+        for i in index..len {
+            let v: Value = self.get(i + 1)?;
+            self.set(i, v)?;
+        }
+        self.set(cmp::max(index, len), Nil)?;
+        Ok(value)
+    }
+
+    /// Inserts `value` at position `index` like [`insert`], but without invoking metamethods.
+    ///
+    /// The length of the sequence is determined with [`raw_len`] and the elements are moved with
+    /// [`raw_get`] and [`raw_set`].
+    ///
+    /// [`insert`]: #method.insert
+    /// [`raw_len`]: #method.raw_len
+    /// [`raw_get`]: #method.raw_get
+    /// [`raw_set`]: #method.raw_set
+    pub fn raw_insert<V: ToLua<'lua>>(&self, index: Integer, value: V) -> Result<()> {
+        let value = value.to_lua(self.0.lua)?;
+        let len = self.raw_len();
+        if index < 1 || index > len + 1 {
+            return Err(out_of_bounds(index, len));
+        }
+        for i in (index..len + 1).rev() {
+            let v: Value = self.raw_get(i)?;
+            self.raw_set(i + 1, v)?;
+        }
+        self.raw_set(index, value)
+    }
+
+    /// Removes and returns the element at position `index` like [`remove`], but without invoking
+    /// metamethods.
+    ///
+    /// [`remove`]: #method.remove
+    pub fn raw_remove(&self, index: Integer) -> Result<Value<'lua>> {
+        let len = self.raw_len();
+        if index != len && (index < 1 || index > len + 1) {
+            return Err(out_of_bounds(index, len));
+        }
+        let value = self.raw_get(index)?;
+        for i in index..len {
+            let v: Value = self.raw_get(i + 1)?;
+            self.raw_set(i, v)?;
+        }
+        self.raw_set(cmp::max(index, len), Nil)?;
+        Ok(value)
+    }
+
     /// Returns a reference to the metatable of this table, or `None` if no metatable is set.
     ///
     /// Unlike the `getmetatable` Lua function, this method ignores the `__metatable` field.
@@ -225,15 +564,17 @@ impl<'lua> Table<'lua> {
         }
     }
 
-    /// Sets or removes the metatable of this table.
+    /// Sets or removes the metatable of this table, returning the previous metatable.
     ///
     /// If `metatable` is `None`, the metatable is removed (if no metatable is set, this does
-    /// nothing).
-    pub fn set_metatable(&self, metatable: Option<Table<'lua>>) {
+    /// nothing).  Unlike the `setmetatable` Lua function, this method ignores the `__metatable`
+    /// field, so a protected metatable can still be replaced.
+    pub fn set_metatable(&self, metatable: Option<Table<'lua>>) -> Option<Table<'lua>> {
         let lua = self.0.lua;
+        let previous = self.get_metatable();
         unsafe {
             let _sg = StackGuard::new(lua.state);
-            assert_stack(lua.state, 1);
+            assert_stack(lua.state, 2);
             lua.push_ref(&self.0);
             if let Some(metatable) = metatable {
                 lua.push_ref(&metatable.0);
@@ -242,6 +583,178 @@ impl<'lua> Table<'lua> {
             }
             ffi::lua_setmetatable(lua.state, -2);
         }
+        previous
+    }
+
+    /// Returns a new table with the same keys and values as this table.
+    ///
+    /// Only the top level is copied: nested tables, like every other value, are shared between
+    /// this table and the copy.  The copy has the same metatable as this table.  The contents are
+    /// copied with raw accesses, so no metamethods are invoked.
+    pub fn clone_shallow(&self) -> Result<Table<'lua>> {
+        let copy = self.0.lua.create_table()?;
+        for pair in self.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            copy.raw_set(key, value)?;
+        }
+        copy.set_metatable(self.get_metatable());
+        Ok(copy)
+    }
+
+    /// Returns a new table with the same contents as this table, where every table stored as a
+    /// value is itself copied recursively.
+    ///
+    /// A table that is reachable several times (including through cycles, such as a table that
+    /// contains itself) is copied only once, and the copies reference each other in the same way
+    /// as the originals.  Keys, metatables and all values other than tables are shared with the
+    /// original, as with [`clone_shallow`].
+    ///
+    /// [`clone_shallow`]: #method.clone_shallow
+    pub fn clone_deep(&self) -> Result<Table<'lua>> {
+        let lua = self.0.lua;
+        let copies = lua.create_table()?;
+        let root = lua.create_table()?;
+        copies.raw_set(self.clone(), root.clone())?;
+
+        // Tables are copied from a worklist rather than recursively, so that deeply nested tables
+        // cannot overflow the Rust stack.
+        let mut pending = vec![(self.clone(), root.clone())];
+        while let Some((table, copy)) = pending.pop() {
+            for pair in table.clone().pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                let value = match value {
+                    Value::Table(nested) => {
+                        match copies.raw_get::<_, Option<Table>>(nested.clone())? {
+                            Some(nested_copy) => Value::Table(nested_copy),
+                            None => {
+                                let nested_copy = lua.create_table()?;
+                                copies.raw_set(nested.clone(), nested_copy.clone())?;
+                                pending.push((nested, nested_copy.clone()));
+                                Value::Table(nested_copy)
+                            }
+                        }
+                    }
+                    value => value,
+                };
+                copy.raw_set(key, value)?;
+            }
+            copy.set_metatable(table.get_metatable());
+        }
+        Ok(root)
+    }
+
+    /// Returns a read-only view of this table.
+    ///
+    /// The returned table is an empty proxy whose metatable has `__index` set to this table, so
+    /// reading a field is a plain table lookup without calling any metamethod.  Assigning to any
+    /// field of the proxy raises an "attempt to modify a read-only table" error, and the metatable
+    /// is protected with a `__metatable` field so Lua code cannot remove it.  The proxy also has a
+    /// `__len` metamethod returning the length of this table, which is used by [`len`].
+    ///
+    /// Since the proxy itself is empty, raw accesses (`rawget`, `rawset`, [`raw_get`]) and
+    /// iteration with `next` or `pairs` do not see the contents of this table, so sandboxed code
+    /// should not be given access to the raw functions.  Lua 5.1 never calls `__len` for tables,
+    /// and its `ipairs` uses raw accesses, so in Lua code `#` is 0 for the proxy and `ipairs`
+    /// yields nothing; scripts have to index the proxy directly instead.  This table is not
+    /// modified, and can still be changed through other references to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let config = lua.create_table()?;
+    /// config.set("debug", true)?;
+    /// lua.globals().set("config", config.into_readonly()?)?;
+    ///
+    /// assert_eq!(lua.eval::<bool>("config.debug", None)?, true);
+    /// assert!(lua.exec::<()>("config.debug = false", None).is_err());
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`len`]: #method.len
+    /// [`raw_get`]: #method.raw_get
+    pub fn into_readonly(self) -> Result<Table<'lua>> {
+        unsafe extern "C" fn readonly_newindex(state: *mut ffi::lua_State) -> c_int {
+            ffi::luaL_where(state, 1);
+            ffi::lua_pushstring(state, cstr!("attempt to modify a read-only table"));
+            ffi::lua_concat(state, 2);
+            ffi::lua_error(state)
+        }
+
+        unsafe extern "C" fn readonly_len(state: *mut ffi::lua_State) -> c_int {
+            let len = ffi::luaL_len(state, ffi::lua_upvalueindex(1));
+            ffi::lua_pushinteger(state, len);
+            1
+        }
+
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 4);
+
+            lua.push_ref(&self.0);
+            protect_lua_closure(lua.state, 1, 1, |state| {
+                ffi::lua_createtable(state, 0, 0);
+                ffi::lua_createtable(state, 0, 4);
+                ffi::lua_pushvalue(state, -3);
+                ffi::lua_setfield(state, -2, cstr!("__index"));
+                ffi::lua_pushcfunction(state, readonly_newindex);
+                ffi::lua_setfield(state, -2, cstr!("__newindex"));
+                ffi::lua_pushvalue(state, -3);
+                ffi::lua_pushcclosure(state, readonly_len, 1);
+                ffi::lua_setfield(state, -2, cstr!("__len"));
+                ffi::lua_pushboolean(state, 0);
+                ffi::lua_setfield(state, -2, cstr!("__metatable"));
+                ffi::lua_setmetatable(state, -2);
+            })?;
+            Ok(Table(lua.pop_ref()))
+        }
+    }
+
+    /// Returns a read-only view of this table and of all the tables reachable from it.
+    ///
+    /// This works like [`into_readonly`], but first replaces every table stored as a value in this
+    /// table (recursively) with its read-only view, so that nested tables cannot be modified
+    /// either.  Unlike [`into_readonly`], this modifies this table and the nested tables.  A table
+    /// that is reachable several times (including through cycles) is replaced by the same view
+    /// everywhere.
+    ///
+    /// [`into_readonly`]: #method.into_readonly
+    pub fn into_readonly_recursive(self) -> Result<Table<'lua>> {
+        let views = self.0.lua.create_table()?;
+        let root = self.clone().into_readonly()?;
+        views.raw_set(self.clone(), root.clone())?;
+
+        // As in `clone_deep`, nested tables are handled from a worklist rather than recursively.
+        let mut pending = vec![self];
+        while let Some(table) = pending.pop() {
+            let mut nested = Vec::new();
+            for pair in table.clone().pairs::<Value, Value>() {
+                if let (key, Value::Table(nested_table)) = pair? {
+                    nested.push((key, nested_table));
+                }
+            }
+            for (key, nested_table) in nested {
+                let view = match views.raw_get::<_, Option<Table>>(nested_table.clone())? {
+                    Some(view) => view,
+                    None => {
+                        let view = nested_table.clone().into_readonly()?;
+                        views.raw_set(nested_table.clone(), view.clone())?;
+                        pending.push(nested_table);
+                        view
+                    }
+                };
+                table.raw_set(key, view)?;
+            }
+        }
+        Ok(root)
     }
 
     /// Consume this table and return an iterator over the pairs of the table.
@@ -289,6 +802,92 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Calls `f` for every key-value pair of the table, stopping at the first error.
+    ///
+    /// This visits the same pairs, in the same order, as [`pairs`], but drives the iteration from a
+    /// single loop that keeps the table and the current key on the Lua stack, which avoids the
+    /// overhead of resuming an iterator for every pair.  If converting a pair to `K` and `V` fails,
+    /// or `f` returns an error, the iteration stops and the error is returned.
+    ///
+    /// As with Lua's `next`, `f` must not add new keys to the table while it is being traversed,
+    /// it may only modify or clear existing fields.  Adding keys makes the order of the traversal
+    /// undefined, and may result in an "invalid key to 'next'" error being returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result, Table};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let prices: Table = lua.eval("{ apple = 3, pear = 4, plum = 5 }", None)?;
+    ///
+    /// let mut total = 0;
+    /// prices.for_each(|_: String, price: u32| {
+    ///     total += price;
+    ///     Ok(())
+    /// })?;
+    /// assert_eq!(total, 12);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`pairs`]: #method.pairs
+    pub fn for_each<K, V, F>(&self, mut f: F) -> Result<()>
+    where
+        K: FromLua<'lua>,
+        V: FromLua<'lua>,
+        F: FnMut(K, V) -> Result<()>,
+    {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 6);
+
+            lua.push_ref(&self.0);
+            ffi::lua_pushnil(lua.state);
+            // With the table and the previous key on top of the stack, `lua_next` leaves the table
+            // followed by the next key and value, or only the table once the traversal is done.
+            while protect_lua_closure(lua.state, 2, ffi::LUA_MULTRET, |state| {
+                ffi::lua_next(state, -2) != 0
+            })? {
+                ffi::lua_pushvalue(lua.state, -2);
+                let key = lua.pop_value();
+                let value = lua.pop_value();
+                f(K::from_lua(key, lua)?, V::from_lua(value, lua)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns all key-value pairs of the table, sorted by key.
+    ///
+    /// Lua does not define the order in which `next` (and so [`pairs`]) visits the pairs of a
+    /// table, so this is useful wherever the order needs to be reproducible, such as when dumping a
+    /// configuration.  Every key must be convertible to the single type `K`: to iterate a table
+    /// with both string and integer keys, for example, convert the keys to `String`.
+    ///
+    /// This reads the whole table into a `Vec` before sorting it, so it allocates memory
+    /// proportional to the size of the table.
+    ///
+    /// [`pairs`]: #method.pairs
+    pub fn pairs_sorted<K, V>(&self) -> Result<Vec<(K, V)>>
+    where
+        K: FromLua<'lua> + Ord,
+        V: FromLua<'lua>,
+    {
+        let mut pairs = Vec::new();
+        self.for_each(|k: K, v: V| {
+            pairs.push((k, v));
+            Ok(())
+        })?;
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(pairs)
+    }
+
     /// Consume this table and return an iterator over all values in the sequence part of the table.
     ///
     /// The iterator will yield all values `t[1]`, `t[2]`, and so on, until a `nil` value is
@@ -336,6 +935,123 @@ impl<'lua> Table<'lua> {
     }
 }
 
+/// An entry of a table, which is either vacant or occupied.
+///
+/// This enum is created by the [`Table::entry`] method.
+///
+/// [`Table::entry`]: struct.Table.html#method.entry
+#[derive(Debug)]
+pub enum Entry<'lua> {
+    /// The key has a non-nil value.
+    Occupied(OccupiedEntry<'lua>),
+    /// The key has no value.
+    Vacant(VacantEntry<'lua>),
+}
+
+impl<'lua> Entry<'lua> {
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &Value<'lua> {
+        match *self {
+            Entry::Occupied(ref entry) => entry.key(),
+            Entry::Vacant(ref entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value of an occupied entry, or sets a vacant entry to `default` and returns
+    /// that.
+    pub fn or_insert<V: ToLua<'lua>>(self, default: V) -> Result<Value<'lua>> {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.value),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Replaces the value of an occupied entry with the result of `f`, leaving a vacant entry as
+    /// it is.
+    ///
+    /// The current value is converted to `T` before calling `f`.
+    pub fn and_modify<T, V, F>(self, f: F) -> Result<Entry<'lua>>
+    where
+        T: FromLua<'lua>,
+        V: ToLua<'lua>,
+        F: FnOnce(T) -> Result<V>,
+    {
+        match self {
+            Entry::Occupied(mut entry) => {
+                let lua = entry.table.0.lua;
+                let value = f(T::from_lua(entry.value.clone(), lua)?)?;
+                entry.insert(value)?;
+                Ok(Entry::Occupied(entry))
+            }
+            Entry::Vacant(entry) => Ok(Entry::Vacant(entry)),
+        }
+    }
+}
+
+/// An entry of a table whose key has a non-nil value.
+///
+/// This is part of the [`Entry`] enum.
+///
+/// [`Entry`]: enum.Entry.html
+#[derive(Debug)]
+pub struct OccupiedEntry<'lua> {
+    table: Table<'lua>,
+    key: Value<'lua>,
+    value: Value<'lua>,
+}
+
+impl<'lua> OccupiedEntry<'lua> {
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &Value<'lua> {
+        &self.key
+    }
+
+    /// Returns the value of the entry, as it was read or last written through the entry.
+    pub fn get(&self) -> &Value<'lua> {
+        &self.value
+    }
+
+    /// Sets the value of the entry, returning the previous value.
+    ///
+    /// Setting the value to `nil` removes the key from the table, but the entry stays occupied.
+    pub fn insert<V: ToLua<'lua>>(&mut self, value: V) -> Result<Value<'lua>> {
+        let value = value.to_lua(self.table.0.lua)?;
+        self.table.set(self.key.clone(), value.clone())?;
+        Ok(mem::replace(&mut self.value, value))
+    }
+
+    /// Removes the key from the table, returning its value.
+    pub fn remove(self) -> Result<Value<'lua>> {
+        self.table.set(self.key, Nil)?;
+        Ok(self.value)
+    }
+}
+
+/// An entry of a table whose key has no value.
+///
+/// This is part of the [`Entry`] enum.
+///
+/// [`Entry`]: enum.Entry.html
+#[derive(Debug)]
+pub struct VacantEntry<'lua> {
+    table: Table<'lua>,
+    key: Value<'lua>,
+}
+
+impl<'lua> VacantEntry<'lua> {
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &Value<'lua> {
+        &self.key
+    }
+
+    /// Sets the value of the entry, returning it.
+    pub fn insert<V: ToLua<'lua>>(self, value: V) -> Result<Value<'lua>> {
+        let value = value.to_lua(self.table.0.lua)?;
+        self.table.set(self.key, value.clone())?;
+        Ok(value)
+    }
+}
+
 /// An iterator over the pairs of a Lua table.
 ///
 /// This struct is created by the [`Table::pairs`] method.
@@ -446,3 +1162,10 @@ where
         }
     }
 }
+
+fn out_of_bounds(index: Integer, len: Integer) -> Error {
+    Error::RuntimeError(format!(
+        "position {} out of bounds for a sequence of length {}",
+        index, len
+    ))
+}
@@ -1,10 +1,24 @@
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::c_int;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
 
 use error::{Error, Result};
 use ffi;
+#[cfg(feature = "async")]
+use lua::{async_pending, set_async_waker};
+#[cfg(feature = "async")]
+use types::LightUserData;
 use types::LuaRef;
 use util::{assert_stack, check_stack, error_traceback, pop_error, StackGuard};
-use value::{FromLuaMulti, MultiValue, ToLuaMulti};
+#[cfg(feature = "async")]
+use value::Value;
+use value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLuaMulti};
 
 /// Status of a Lua thread (or coroutine).
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -15,14 +29,22 @@ pub enum ThreadStatus {
     ///
     /// [`Thread::resume`]: struct.Thread.html#method.resume
     Resumable,
-    /// Either the thread has finished executing, or the thread is currently running.
-    Unresumable,
+    /// The thread is currently running, or has resumed another coroutine and is waiting for it.
+    Running,
+    /// The thread has finished executing its main function.
+    Dead,
     /// The thread has raised a Lua error during execution.
+    ///
+    /// Like a `Dead` thread, a thread in this state can never be resumed again.
     Error,
 }
 
 /// Handle to an internal Lua thread (or coroutine).
-#[derive(Clone, Debug)]
+///
+/// Handles compare equal, and hash the same, if they refer to the same Lua thread.  The hash is
+/// based on the address of the thread, so it is only meaningful within one `Lua` state and while
+/// the thread is alive.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Thread<'lua>(pub(crate) LuaRef<'lua>);
 
 impl<'lua> Thread<'lua> {
@@ -32,14 +54,18 @@ impl<'lua> Thread<'lua> {
     ///
     /// Passes `args` as arguments to the thread. If the coroutine has called `coroutine.yield`, it
     /// will return these arguments. Otherwise, the coroutine wasn't yet started, so the arguments
-    /// are passed to its main function.
+    /// are passed to its main function.  This means that the arguments of the first `resume` become
+    /// the parameters of the main function, and the arguments of every later `resume` become the
+    /// results of the `coroutine.yield` call the thread is suspended in.
     ///
-    /// If the thread is no longer in `Active` state (meaning it has finished execution or
+    /// If the thread is `Dead` or in the `Error` state (meaning it has finished execution or
     /// encountered an error), this will return `Err(CoroutineInactive)`, otherwise will return `Ok`
     /// as follows:
     ///
     /// If the thread calls `coroutine.yield`, returns the values passed to `yield`. If the thread
-    /// `return`s values from its main function, returns those.
+    /// `return`s values from its main function, returns those.  Both are converted to `R` in the
+    /// same way, so use [`status`] afterwards to tell them apart: the thread is still `Resumable`
+    /// after a yield, and `Dead` once its main function has returned.
     ///
     /// # Examples
     ///
@@ -71,12 +97,24 @@ impl<'lua> Thread<'lua> {
     /// #     try_main().unwrap();
     /// # }
     /// ```
+    ///
+    /// [`status`]: #method.status
     pub fn resume<A, R>(&self, args: A) -> Result<R>
     where
         A: ToLuaMulti<'lua>,
         R: FromLuaMulti<'lua>,
     {
         let lua = self.0.lua;
+        match self.status() {
+            ThreadStatus::Resumable => {}
+            ThreadStatus::Running => {
+                return Err(Error::RuntimeError(
+                    "cannot resume a running coroutine".to_owned(),
+                ))
+            }
+            ThreadStatus::Dead | ThreadStatus::Error => return Err(Error::CoroutineInactive),
+        }
+
         let args = args.to_lua_multi(lua)?;
         let results = unsafe {
             let _sg = StackGuard::new(lua.state);
@@ -84,12 +122,6 @@ impl<'lua> Thread<'lua> {
 
             lua.push_ref(&self.0);
             let thread_state = ffi::lua_tothread(lua.state, -1);
-
-            let status = ffi::lua_status(thread_state);
-            if status != ffi::LUA_YIELD && ffi::lua_gettop(thread_state) == 0 {
-                return Err(Error::CoroutineInactive);
-            }
-
             ffi::lua_pop(lua.state, 1);
 
             let nargs = args.len() as c_int;
@@ -120,7 +152,75 @@ impl<'lua> Thread<'lua> {
         R::from_lua_multi(results, lua)
     }
 
+    /// Consumes this thread and returns an iterator over the values it yields, treating it as a
+    /// generator.
+    ///
+    /// Each call to `next` resumes the thread without arguments and converts the first value
+    /// passed to `coroutine.yield` to `R`.  Iteration ends once the thread is dead; if its main
+    /// function returns any values, the first of them is produced as the last item.
+    ///
+    /// A value that cannot be converted to `R` produces an `Err` item, and the thread can still be
+    /// iterated further.  If the thread raises an error, that error is the last item.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rlua;
+    /// # use rlua::{Lua, Result, Thread};
+    /// # fn try_main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let squares: Thread = lua.eval(r#"
+    ///     coroutine.create(function()
+    ///         for i = 1, 3 do
+    ///             coroutine.yield(i * i)
+    ///         end
+    ///     end)
+    /// "#, None)?;
+    ///
+    /// let squares = squares.into_iter::<u32>().collect::<Result<Vec<_>>>()?;
+    /// assert_eq!(squares, vec![1, 4, 9]);
+    /// # Ok(())
+    /// # }
+    /// # fn main() {
+    /// #     try_main().unwrap();
+    /// # }
+    /// ```
+    pub fn into_iter<R: FromLua<'lua>>(self) -> ThreadIter<'lua, R> {
+        ThreadIter {
+            thread: self,
+            finished: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Consumes this thread and returns a future which drives it, resuming it with `args`.
+    ///
+    /// Requires the `async` feature.  Polling the future resumes the thread.  While an async
+    /// function called by the thread waits for its future, the thread stays suspended and the
+    /// future returns `Poll::Pending`.  Otherwise the future completes with the values the thread
+    /// returns, or with the values it passes to `coroutine.yield`, converted to `R`.
+    ///
+    /// See [`Lua::create_async_function`].
+    ///
+    /// [`Lua::create_async_function`]: struct.Lua.html#method.create_async_function
+    #[cfg(feature = "async")]
+    pub fn into_async<A, R>(self, args: A) -> Result<AsyncThread<'lua, R>>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        let args = args.to_lua_multi(self.0.lua)?;
+        Ok(AsyncThread {
+            thread: self,
+            args: Some(args),
+            _phantom: PhantomData,
+        })
+    }
+
     /// Gets the status of the thread.
+    ///
+    /// This follows the same rules as the Lua `coroutine.status` function, except that a thread
+    /// which has raised an error is reported as `Error` rather than `Dead`.
     pub fn status(&self) -> ThreadStatus {
         let lua = self.0.lua;
         unsafe {
@@ -131,14 +231,118 @@ impl<'lua> Thread<'lua> {
             let thread_state = ffi::lua_tothread(lua.state, -1);
             ffi::lua_pop(lua.state, 1);
 
-            let status = ffi::lua_status(thread_state);
-            if status != ffi::LUA_OK && status != ffi::LUA_YIELD {
-                ThreadStatus::Error
-            } else if status == ffi::LUA_YIELD || ffi::lua_gettop(thread_state) > 0 {
-                ThreadStatus::Resumable
-            } else {
-                ThreadStatus::Unresumable
+            if thread_state == lua.state {
+                return ThreadStatus::Running;
+            }
+
+            match ffi::lua_status(thread_state) {
+                ffi::LUA_YIELD => ThreadStatus::Resumable,
+                ffi::LUA_OK => {
+                    let mut ar: ffi::lua_Debug = mem::zeroed();
+                    if ffi::lua_getstack(thread_state, 0, &mut ar) > 0 {
+                        // A thread with active call frames that is not suspended has resumed
+                        // another coroutine.
+                        ThreadStatus::Running
+                    } else if ffi::lua_gettop(thread_state) == 0 {
+                        ThreadStatus::Dead
+                    } else {
+                        // The main function has not been started yet
+                        ThreadStatus::Resumable
+                    }
+                }
+                _ => ThreadStatus::Error,
+            }
+        }
+    }
+}
+
+/// An iterator over the values yielded by a Lua thread.
+///
+/// This struct is created by the [`Thread::into_iter`] method.
+///
+/// [`Thread::into_iter`]: struct.Thread.html#method.into_iter
+pub struct ThreadIter<'lua, R> {
+    thread: Thread<'lua>,
+    finished: bool,
+    _phantom: PhantomData<R>,
+}
+
+impl<'lua, R> Iterator for ThreadIter<'lua, R>
+where
+    R: FromLua<'lua>,
+{
+    type Item = Result<R>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished || self.thread.status() != ThreadStatus::Resumable {
+            return None;
+        }
+
+        match self.thread.resume::<_, MultiValue>(()) {
+            Ok(values) => {
+                if values.is_empty() && self.thread.status() == ThreadStatus::Dead {
+                    self.finished = true;
+                    return None;
+                }
+                let value = values.into_iter().next().unwrap_or(Nil);
+                Some(R::from_lua(value, self.thread.0.lua))
+            }
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// A future driving a Lua thread that calls async functions.
+///
+/// This struct is created by the [`Thread::into_async`] method.
+///
+/// [`Thread::into_async`]: struct.Thread.html#method.into_async
+#[cfg(feature = "async")]
+pub struct AsyncThread<'lua, R> {
+    thread: Thread<'lua>,
+    args: Option<MultiValue<'lua>>,
+    _phantom: PhantomData<fn() -> R>,
+}
+
+#[cfg(feature = "async")]
+impl<'lua, R> Future for AsyncThread<'lua, R>
+where
+    R: FromLuaMulti<'lua>,
+{
+    type Output = Result<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let lua = this.thread.0.lua;
+        let args = this.args.take().unwrap_or_else(MultiValue::new);
+
+        let results = unsafe {
+            let thread_state = {
+                let _sg = StackGuard::new(lua.state);
+                assert_stack(lua.state, 1);
+                lua.push_ref(&this.thread.0);
+                ffi::lua_tothread(lua.state, -1)
+            };
+            let previous = set_async_waker(lua.state, Some((thread_state, cx.waker().clone())));
+            let results = this.thread.resume::<_, MultiValue>(args);
+            set_async_waker(lua.state, previous);
+            results
+        };
+
+        let results = match results {
+            Ok(results) => results,
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+        if results.len() == 1 {
+            if let Some(&Value::LightUserData(LightUserData(ptr))) = results.iter().next() {
+                if ptr == async_pending() {
+                    return Poll::Pending;
+                }
             }
         }
+        Poll::Ready(R::from_lua_multi(results, lua))
     }
 }
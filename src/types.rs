@@ -1,11 +1,14 @@
 use std::os::raw::{c_int, c_void};
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::hash::{Hash, Hasher};
 use std::{fmt, mem, ptr};
 
-use error::Result;
+use debug::HookInfo;
+use error::{Error, Result};
 use ffi;
 use lua::Lua;
-use value::MultiValue;
+use value::{MultiValue, Value};
 
 /// Type of Lua integer numbers.
 pub type Integer = ffi::lua_Integer;
@@ -19,14 +22,51 @@ pub struct LightUserData(pub *mut c_void);
 pub(crate) type Callback<'lua, 'a> =
     Box<Fn(&'lua Lua, MultiValue<'lua>) -> Result<MultiValue<'lua>> + 'a>;
 
+pub(crate) type HookCallback = Rc<Fn(&Lua, HookInfo) -> Result<()> + Send>;
+
+// A hook callback borrowed for the duration of a call, such as the closure passed to
+// `Function::call_with_hook`.  It is kept as a pointer to the closure and a function calling it, so
+// that it neither needs to be `'static` nor `Send`; whoever installs it must remove it before the
+// closure goes out of scope.
+#[derive(Copy, Clone)]
+pub(crate) struct ScopedHookCallback {
+    data: *const c_void,
+    call: unsafe fn(*const c_void, &Lua, HookInfo) -> Result<()>,
+}
+
+impl ScopedHookCallback {
+    pub(crate) fn new<F: Fn(&Lua, HookInfo) -> Result<()>>(callback: &F) -> ScopedHookCallback {
+        unsafe fn call<F: Fn(&Lua, HookInfo) -> Result<()>>(
+            data: *const c_void,
+            lua: &Lua,
+            info: HookInfo,
+        ) -> Result<()> {
+            (*(data as *const F))(lua, info)
+        }
+
+        ScopedHookCallback {
+            data: callback as *const F as *const c_void,
+            call: call::<F>,
+        }
+    }
+
+    // The closure this was created from must still be alive.
+    pub(crate) unsafe fn call(&self, lua: &Lua, info: HookInfo) -> Result<()> {
+        (self.call)(self.data, lua, info)
+    }
+}
+
+pub(crate) type ErrorConverter = Rc<for<'lua> Fn(&'lua Lua, &Error) -> Result<Value<'lua>> + Send>;
+
 /// An auto generated key into the Lua registry.
 ///
 /// This is a handle to a value stored inside the Lua registry.  It is not directly usable like the
 /// `Table` or `Function` handle types, but since it doesn't hold a reference to a parent Lua and is
 /// Send + Sync + 'static, it is much more flexible and can be used in many situations where it is
-/// impossible to directly store a normal handle type.  It is not automatically garbage collected on
-/// Drop, but it can be removed with [`Lua::remove_registry_value`], and instances not manually
-/// removed can be garbage collected with [`Lua::expire_registry_values`].
+/// impossible to directly store a normal handle type.  It can be removed with
+/// [`Lua::remove_registry_value`], and the value of a key that is simply dropped is removed the
+/// next time registry values are expired: on the next call to `Lua::create_registry_value` or
+/// `Lua::gc_collect`, or explicitly with [`Lua::expire_registry_values`].
 ///
 /// Be warned, If you place this into Lua via a `UserData` type or a rust callback, it is *very
 /// easy* to accidentally cause reference cycles that the Lua garbage collector cannot resolve.
@@ -81,6 +121,21 @@ impl<'lua> fmt::Debug for LuaRef<'lua> {
     }
 }
 
+// References are equal if they refer to the same Lua object, and hash the address of that object.
+impl<'lua> PartialEq for LuaRef<'lua> {
+    fn eq(&self, other: &Self) -> bool {
+        self.lua.ref_equal(self, other)
+    }
+}
+
+impl<'lua> Eq for LuaRef<'lua> {}
+
+impl<'lua> Hash for LuaRef<'lua> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lua.ref_pointer(self).hash(state)
+    }
+}
+
 impl<'lua> Clone for LuaRef<'lua> {
     fn clone(&self) -> Self {
         self.lua.clone_ref(self)
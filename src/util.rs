@@ -7,6 +7,7 @@ use std::{mem, ptr};
 
 use error::{Error, Result};
 use ffi;
+use lua::{ignore_memory_limit, panic_policy, PanicPolicy};
 
 // Checks that Lua has enough free stack space for future stack operations.  On failure, this will
 // panic with an internal error message.
@@ -14,21 +15,26 @@ pub unsafe fn assert_stack(state: *mut ffi::lua_State, amount: c_int) {
     // TODO: This should only be triggered when there is a logic error in `rlua`.  In the future,
     // when there is a way to be confident about stack safety and test it, this could be enabled
     // only when `cfg!(debug_assertions)` is true.
-    rlua_assert!(
-        ffi::lua_checkstack(state, amount) != 0,
-        "out of stack space"
-    );
+    rlua_assert!(grow_stack(state, amount), "out of stack space");
 }
 
 // Similar to `assert_stack`, but returns `Error::StackError` on failure.
 pub unsafe fn check_stack(state: *mut ffi::lua_State, amount: c_int) -> Result<()> {
-    if ffi::lua_checkstack(state, amount) == 0 {
-        Err(Error::StackError)
-    } else {
+    if grow_stack(state, amount) {
         Ok(())
+    } else {
+        Err(Error::StackError)
     }
 }
 
+// Calls lua_checkstack with the memory limit lifted.  Growing a stack allocates, and an allocation
+// refused because of the memory limit would otherwise raise a memory error, which longjmps even
+// where no error handler is in place or over Rust frames.  This way the only failure is the stack
+// reaching its maximum size, which is reported by returning false.
+pub unsafe fn grow_stack(state: *mut ffi::lua_State, amount: c_int) -> bool {
+    ignore_memory_limit(state, || ffi::lua_checkstack(state, amount) != 0)
+}
+
 pub struct StackGuard {
     state: *mut ffi::lua_State,
     top: c_int,
@@ -155,7 +161,8 @@ where
 
 // Pops an error off of the stack and returns it.  The specific behavior depends on the type of the
 // error at the top of the stack:
-//   1) If the error is actually a WrappedPanic, this will continue the panic.
+//   1) If the error is actually a WrappedPanic, this will continue the panic, or return it as an
+//      `Error::CallbackPanic` if the panic policy says so.
 //   2) If the error on the top of the stack is actually a WrappedError, just returns it.
 //   3) Otherwise, interprets the error as the appropriate lua error.
 // Uses 2 stack spaces, does not call lua_checkstack.
@@ -170,10 +177,13 @@ pub unsafe fn pop_error(state: *mut ffi::lua_State, err_code: c_int) -> Error {
         err.clone()
     } else if is_wrapped_panic(state, -1) {
         let panic = get_userdata::<WrappedPanic>(state, -1);
-        if let Some(p) = (*panic).0.take() {
-            resume_unwind(p);
-        } else {
-            rlua_panic!("panic was resumed twice")
+        match (*panic).payload.take() {
+            Some(p) if panic_policy(state) == PanicPolicy::Resume => resume_unwind(p),
+            _ => {
+                let message = (*panic).message.clone();
+                ffi::lua_pop(state, 1);
+                Error::CallbackPanic(message)
+            }
         }
     } else {
         let err_string = gc_guard(state, || {
@@ -190,8 +200,10 @@ pub unsafe fn pop_error(state: *mut ffi::lua_State, err_code: c_int) -> Error {
             ffi::LUA_ERRSYNTAX => {
                 Error::SyntaxError {
                     // This seems terrible, but as far as I can tell, this is exactly what the
-                    // stock Lua REPL does.
-                    incomplete_input: err_string.ends_with("<eof>"),
+                    // stock Lua REPL does.  Lua 5.1 quotes the token, later versions do not.
+                    incomplete_input: err_string.ends_with("'<eof>'")
+                        || err_string.ends_with("<eof>"),
+                    line: syntax_error_line(&err_string),
                     message: err_string,
                 }
             }
@@ -203,9 +215,9 @@ pub unsafe fn pop_error(state: *mut ffi::lua_State, err_code: c_int) -> Error {
                 Error::RuntimeError(err_string)
             }
             ffi::LUA_ERRMEM => {
-                // This should be impossible, as we set the lua allocator to one that aborts
-                // instead of failing.
-                rlua_abort!("impossible Lua allocation error")
+                // The lua allocator only fails instead of aborting when the state has reached its
+                // memory limit.
+                Error::MemoryError(err_string)
             }
             ffi::LUA_ERRGCMM => Error::GarbageCollectorError(err_string),
             _ => rlua_panic!("unrecognized lua error code"),
@@ -213,8 +225,26 @@ pub unsafe fn pop_error(state: *mut ffi::lua_State, err_code: c_int) -> Error {
     }
 }
 
+// Lua formats syntax errors as "<chunk name>:<line>: <message>", so the line is the first number
+// enclosed in colons.  The chunk name of a string chunk contains the start of its source, so this
+// can be fooled by a source starting with such a pattern, but that is very unlikely in practice.
+fn syntax_error_line(message: &str) -> Option<usize> {
+    let parts: Vec<&str> = message.split(':').collect();
+    if parts.len() < 3 {
+        return None;
+    }
+    parts[1..parts.len() - 1]
+        .iter()
+        .find(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|part| part.parse().ok())
+}
+
 // Internally uses 4 stack spaces, does not call checkstack
-pub unsafe fn push_string(state: *mut ffi::lua_State, s: &str) -> Result<()> {
+pub unsafe fn push_string<S>(state: *mut ffi::lua_State, s: &S) -> Result<()>
+where
+    S: ?Sized + AsRef<[u8]>,
+{
+    let s = s.as_ref();
     protect_lua_closure(state, 0, 1, |state| {
         ffi::lua_pushlstring(state, s.as_ptr() as *const c_char, s.len());
     })
@@ -275,7 +305,7 @@ where
         }
         Err(p) => {
             ffi::lua_settop(state, 0);
-            if ffi::lua_checkstack(state, 2) == 0 {
+            if !grow_stack(state, 2) {
                 rlua_abort!("not enough stack space to propagate panic");
             }
             push_wrapped_panic(state, p);
@@ -292,10 +322,12 @@ pub unsafe extern "C" fn error_traceback(state: *mut ffi::lua_State) -> c_int {
     // I believe luaL_traceback requires this much free stack to not error.
     const LUA_TRACEBACK_STACK: c_int = 11;
 
-    if ffi::lua_checkstack(state, 2) == 0 {
+    if !grow_stack(state, 2) {
         // If we don't have enough stack space to even check the error type, do nothing
+    } else if let Some(&Error::Interrupted) = get_wrapped_error(state, 1).as_ref() {
+        // An interrupt is returned as is from every call, however deeply it is nested.
     } else if let Some(error) = get_wrapped_error(state, 1).as_ref() {
-        let traceback = if ffi::lua_checkstack(state, LUA_TRACEBACK_STACK) != 0 {
+        let traceback = if grow_stack(state, LUA_TRACEBACK_STACK) {
             gc_guard(state, || {
                 ffi::luaL_traceback(state, state, ptr::null(), 0);
             });
@@ -319,7 +351,7 @@ pub unsafe extern "C" fn error_traceback(state: *mut ffi::lua_State) -> c_int {
             },
         );
     } else if !is_wrapped_panic(state, 1) {
-        if ffi::lua_checkstack(state, LUA_TRACEBACK_STACK) != 0 {
+        if grow_stack(state, LUA_TRACEBACK_STACK) {
             gc_guard(state, || {
                 let s = ffi::lua_tostring(state, 1);
                 let s = if s.is_null() {
@@ -335,7 +367,8 @@ pub unsafe extern "C" fn error_traceback(state: *mut ffi::lua_State) -> c_int {
     1
 }
 
-// A variant of pcall that does not allow lua to catch panic errors from callback_error
+// A variant of pcall that does not allow lua to catch panic errors from callback_error, or
+// interrupts
 pub unsafe extern "C" fn safe_pcall(state: *mut ffi::lua_State) -> c_int {
     ffi::luaL_checkstack(state, 2, ptr::null());
 
@@ -344,7 +377,7 @@ pub unsafe extern "C" fn safe_pcall(state: *mut ffi::lua_State) -> c_int {
         ffi::lua_pushstring(state, cstr!("not enough arguments to pcall"));
         ffi::lua_error(state);
     } else if ffi::lua_pcall(state, top - 1, ffi::LUA_MULTRET, 0) != ffi::LUA_OK {
-        if is_wrapped_panic(state, -1) {
+        if is_uncatchable_error(state, -1) {
             ffi::lua_error(state);
         }
         ffi::lua_pushboolean(state, 0);
@@ -357,12 +390,13 @@ pub unsafe extern "C" fn safe_pcall(state: *mut ffi::lua_State) -> c_int {
     }
 }
 
-// A variant of xpcall that does not allow lua to catch panic errors from callback_error
+// A variant of xpcall that does not allow lua to catch panic errors from callback_error, or
+// interrupts
 pub unsafe extern "C" fn safe_xpcall(state: *mut ffi::lua_State) -> c_int {
     unsafe extern "C" fn xpcall_msgh(state: *mut ffi::lua_State) -> c_int {
         ffi::luaL_checkstack(state, 2, ptr::null());
 
-        if is_wrapped_panic(state, -1) {
+        if is_uncatchable_error(state, -1) {
             1
         } else {
             ffi::lua_pushvalue(state, ffi::lua_upvalueindex(1));
@@ -387,7 +421,7 @@ pub unsafe extern "C" fn safe_xpcall(state: *mut ffi::lua_State) -> c_int {
 
     let res = ffi::lua_pcall(state, ffi::lua_gettop(state) - 2, ffi::LUA_MULTRET, 1);
     if res != ffi::LUA_OK {
-        if is_wrapped_panic(state, -1) {
+        if is_uncatchable_error(state, -1) {
             ffi::lua_error(state);
         }
         ffi::lua_pushboolean(state, 0);
@@ -443,21 +477,24 @@ pub unsafe fn get_wrapped_error(state: *mut ffi::lua_State, index: c_int) -> *co
     }
 }
 
-// Runs the given function with the Lua garbage collector disabled.  `rlua` assumes that all
-// allocation failures are aborts, so when the garbage collector is disabled, 'm' functions that can
-// cause either an allocation error or a a `__gc` metamethod error are prevented from causing errors
-// at all.  The given function should never panic or longjmp, because this could inadverntently
-// disable the gc.  This is useful when error handling must allocate, and `__gc` errors at that time
-// would shadow more important errors, or be extremely difficult to handle safely.
+// Runs the given function with the Lua garbage collector disabled and the memory limit lifted.
+// `rlua` assumes that all allocation failures other than those caused by the memory limit are
+// aborts, so in the given function, 'm' functions that can cause either an allocation error or a
+// `__gc` metamethod error are prevented from causing errors at all.  The given function should
+// never panic or longjmp, because this could inadverntently disable the gc.  This is useful when
+// error handling must allocate, and `__gc` errors at that time would shadow more important
+// errors, or be extremely difficult to handle safely.
 pub unsafe fn gc_guard<R, F: FnOnce() -> R>(state: *mut ffi::lua_State, f: F) -> R {
-    if ffi::lua_gc(state, ffi::LUA_GCISRUNNING, 0) != 0 {
-        ffi::lua_gc(state, ffi::LUA_GCSTOP, 0);
-        let r = f();
-        ffi::lua_gc(state, ffi::LUA_GCRESTART, 0);
-        r
-    } else {
-        f()
-    }
+    ignore_memory_limit(state, || {
+        if ffi::lua_gc(state, ffi::LUA_GCISRUNNING, 0) != 0 {
+            ffi::lua_gc(state, ffi::LUA_GCSTOP, 0);
+            let r = f();
+            ffi::lua_gc(state, ffi::LUA_GCRESTART, 0);
+            r
+        } else {
+            f()
+        }
+    })
 }
 
 // Initialize the error, panic, and destructed userdata metatables.
@@ -510,6 +547,29 @@ pub unsafe fn init_error_metatables(state: *mut ffi::lua_State) {
 
     // Create panic metatable
 
+    unsafe extern "C" fn panic_tostring(state: *mut ffi::lua_State) -> c_int {
+        ffi::luaL_checkstack(state, 2, ptr::null());
+
+        callback_error(state, || {
+            if is_wrapped_panic(state, -1) {
+                let panic = get_userdata::<WrappedPanic>(state, -1);
+                let panic_str = Error::CallbackPanic((*panic).message.clone()).to_string();
+                gc_guard(state, || {
+                    ffi::lua_pushlstring(
+                        state,
+                        panic_str.as_ptr() as *const c_char,
+                        panic_str.len(),
+                    )
+                });
+                ffi::lua_remove(state, -2);
+
+                Ok(1)
+            } else {
+                panic!("userdata mismatch in panic metamethod");
+            }
+        })
+    }
+
     ffi::lua_pushlightuserdata(
         state,
         &PANIC_METATABLE_REGISTRY_KEY as *const u8 as *mut c_void,
@@ -520,6 +580,10 @@ pub unsafe fn init_error_metatables(state: *mut ffi::lua_State) {
     ffi::lua_pushcfunction(state, userdata_destructor::<WrappedPanic>);
     ffi::lua_rawset(state, -3);
 
+    ffi::lua_pushstring(state, cstr!("__tostring"));
+    ffi::lua_pushcfunction(state, panic_tostring);
+    ffi::lua_rawset(state, -3);
+
     ffi::lua_pushstring(state, cstr!("__metatable"));
     ffi::lua_pushboolean(state, 0);
     ffi::lua_rawset(state, -3);
@@ -576,23 +640,46 @@ pub unsafe fn init_error_metatables(state: *mut ffi::lua_State) {
 }
 
 struct WrappedError(pub Error);
-struct WrappedPanic(pub Option<Box<Any + Send>>);
+struct WrappedPanic {
+    // Taken when the panic is resumed.
+    payload: Option<Box<Any + Send>>,
+    message: String,
+}
 
 // Pushes a WrappedError::Panic to the top of the stack.  Uses two stack spaces and does not call
 // lua_checkstack.
 unsafe fn push_wrapped_panic(state: *mut ffi::lua_State, panic: Box<Any + Send>) {
+    let message = panic_message(&*panic);
     gc_guard(state, || {
         let ud = ffi::lua_newuserdata(state, mem::size_of::<WrappedPanic>()) as *mut WrappedPanic;
-        ptr::write(ud, WrappedPanic(Some(panic)))
+        ptr::write(
+            ud,
+            WrappedPanic {
+                payload: Some(panic),
+                message,
+            },
+        )
     });
 
     get_panic_metatable(state);
     ffi::lua_setmetatable(state, -2);
 }
 
+// Returns the message of a panic payload, which is either a `&str` or a `String` for panics raised
+// by `panic!`.
+fn panic_message(panic: &(Any + Send)) -> String {
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        (*msg).to_owned()
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "<non-string panic payload>".to_owned()
+    }
+}
+
 // Checks if the value at the given index is a WrappedPanic.  Uses 2 stack spaces and does not call
 // lua_checkstack.
-unsafe fn is_wrapped_panic(state: *mut ffi::lua_State, index: c_int) -> bool {
+pub unsafe fn is_wrapped_panic(state: *mut ffi::lua_State, index: c_int) -> bool {
     let userdata = ffi::lua_touserdata(state, index);
     if userdata.is_null() {
         return false;
@@ -608,6 +695,19 @@ unsafe fn is_wrapped_panic(state: *mut ffi::lua_State, index: c_int) -> bool {
     res
 }
 
+// Checks if the value at the given index is an error that Lua code must not catch: a WrappedPanic,
+// or an `Error::Interrupted`, so that interrupted code cannot keep running by catching the
+// interrupt.  Uses 2 stack spaces and does not call lua_checkstack.
+pub unsafe fn is_uncatchable_error(state: *mut ffi::lua_State, index: c_int) -> bool {
+    if is_wrapped_panic(state, index) {
+        return true;
+    }
+    match get_wrapped_error(state, index).as_ref() {
+        Some(&Error::Interrupted) => true,
+        _ => false,
+    }
+}
+
 unsafe fn get_error_metatable(state: *mut ffi::lua_State) {
     ffi::lua_pushlightuserdata(
         state,
@@ -1,5 +1,10 @@
+use std::ffi::CStr;
 use std::iter::{self, FromIterator};
-use std::{slice, str, vec};
+use std::os::raw::{c_char, c_void};
+use std::string::String as StdString;
+use std::{ptr, slice, str, vec};
+
+use libc;
 
 use error::{Error, Result};
 use function::Function;
@@ -26,6 +31,16 @@ pub enum Value<'lua> {
     /// Any Lua number convertible to a `Integer` will be represented as this variant.
     Integer(Integer),
     /// A floating point number.
+    ///
+    /// Numbers represent integers exactly only up to 2^53 in magnitude.  Lua 5.1 stores every
+    /// number, including an `Integer`, as a `Number`, so integers above 2^53 in magnitude are
+    /// rounded to the nearest representable number once they are passed to Lua, for example when
+    /// setting a global or a table field.  Rust integers that do not fit in an `Integer`, such as
+    /// large `u64` values, are converted to this variant and rounded right away.
+    ///
+    /// Converting a number to a Rust integer type fails with `Error::FromLuaConversionError` if it
+    /// has a fractional part or is out of range.  Converting a number to `f32` rounds it to the
+    /// nearest `f32`, and fails if it is finite but too large.
     Number(Number),
     /// An interned string, managed by Lua.
     ///
@@ -43,12 +58,16 @@ pub enum Value<'lua> {
 pub use self::Value::Nil;
 
 impl<'lua> Value<'lua> {
-    pub(crate) fn type_name(&self) -> &'static str {
+    /// Returns the name of the Lua type of this value, as returned by Lua's `type` function.
+    ///
+    /// Since these are Lua's own type names, both integers and floating point numbers are a
+    /// `"number"`, and light userdata and the builtin `Error` userdata are both a `"userdata"`.
+    pub fn type_name(&self) -> &'static str {
         match *self {
             Value::Nil => "nil",
             Value::Boolean(_) => "boolean",
-            Value::LightUserData(_) => "light userdata",
-            Value::Integer(_) => "integer",
+            Value::LightUserData(_) => "userdata",
+            Value::Integer(_) => "number",
             Value::Number(_) => "number",
             Value::String(_) => "string",
             Value::Table(_) => "table",
@@ -57,6 +76,318 @@ impl<'lua> Value<'lua> {
             Value::Error(_) => "userdata",
         }
     }
+
+    /// Returns true if this value is `nil`.
+    pub fn is_nil(&self) -> bool {
+        match *self {
+            Value::Nil => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is a boolean.
+    pub fn is_boolean(&self) -> bool {
+        match *self {
+            Value::Boolean(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is a light userdata.
+    pub fn is_light_userdata(&self) -> bool {
+        match *self {
+            Value::LightUserData(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is an `Integer`.
+    pub fn is_integer(&self) -> bool {
+        match *self {
+            Value::Integer(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is a number, either an `Integer` or a `Number`.
+    pub fn is_number(&self) -> bool {
+        match *self {
+            Value::Integer(_) | Value::Number(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is a string.
+    pub fn is_string(&self) -> bool {
+        match *self {
+            Value::String(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is a table.
+    pub fn is_table(&self) -> bool {
+        match *self {
+            Value::Table(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is a function.
+    pub fn is_function(&self) -> bool {
+        match *self {
+            Value::Function(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is a thread.
+    pub fn is_thread(&self) -> bool {
+        match *self {
+            Value::Thread(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this value is an `Error`.
+    pub fn is_error(&self) -> bool {
+        match *self {
+            Value::Error(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the boolean held by this value, or `None` if it is not a boolean.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the light userdata held by this value, or `None` if it is not a light userdata.
+    pub fn as_light_userdata(&self) -> Option<LightUserData> {
+        match *self {
+            Value::LightUserData(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer held by this value, or `None` if it is not an `Integer`.
+    pub fn as_integer(&self) -> Option<Integer> {
+        match *self {
+            Value::Integer(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the number held by this value, or `None` if it is not a number.
+    ///
+    /// Like [`is_number`], this accepts both `Integer` and `Number` values, and integers are
+    /// converted to a `Number`, which may lose precision for very large integers.  Unlike
+    /// `FromLua`, this does not coerce strings to numbers.
+    ///
+    /// [`is_number`]: #method.is_number
+    pub fn as_number(&self) -> Option<Number> {
+        match *self {
+            Value::Integer(i) => Some(i as Number),
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the string held by this value, or `None` if it is not a string.
+    pub fn as_string(&self) -> Option<&String<'lua>> {
+        match *self {
+            Value::String(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the table held by this value, or `None` if it is not a table.
+    pub fn as_table(&self) -> Option<&Table<'lua>> {
+        match *self {
+            Value::Table(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the function held by this value, or `None` if it is not a function.
+    pub fn as_function(&self) -> Option<&Function<'lua>> {
+        match *self {
+            Value::Function(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the thread held by this value, or `None` if it is not a thread.
+    pub fn as_thread(&self) -> Option<&Thread<'lua>> {
+        match *self {
+            Value::Thread(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the `Error` held by this value, or `None` if it is not an `Error`.
+    pub fn as_error(&self) -> Option<&Error> {
+        match *self {
+            Value::Error(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Converts this value into the string it holds, or returns `None` if it is not a string.
+    pub fn into_string(self) -> Option<String<'lua>> {
+        match self {
+            Value::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Converts this value into the table it holds, or returns `None` if it is not a table.
+    pub fn into_table(self) -> Option<Table<'lua>> {
+        match self {
+            Value::Table(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Converts this value into the function it holds, or returns `None` if it is not a function.
+    pub fn into_function(self) -> Option<Function<'lua>> {
+        match self {
+            Value::Function(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Converts this value into the thread it holds, or returns `None` if it is not a thread.
+    pub fn into_thread(self) -> Option<Thread<'lua>> {
+        match self {
+            Value::Thread(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Converts this value into the `Error` it holds, or returns `None` if it is not an `Error`.
+    pub fn into_error(self) -> Option<Error> {
+        match self {
+            Value::Error(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Converts this value to a number, following the coercion rules of Lua's `tonumber`.
+    ///
+    /// Numbers are returned as they are, and strings are converted if they contain a decimal or
+    /// hexadecimal number, optionally surrounded by whitespace, such as `"3.14"` or `" 0x10 "`.
+    /// Returns `None` for every other value.
+    pub fn to_number(&self) -> Option<Number> {
+        match *self {
+            Value::Integer(i) => Some(i as Number),
+            Value::Number(n) => Some(n),
+            Value::String(ref s) => s.0.lua.coerce_number(self.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Converts this value to an integer, following the same coercion rules as [`to_number`].
+    ///
+    /// Returns `None` if the value cannot be converted to a number, or if the number has a
+    /// fractional part or does not fit in an `Integer`.
+    ///
+    /// [`to_number`]: #method.to_number
+    pub fn to_integer(&self) -> Option<Integer> {
+        match *self {
+            Value::Integer(i) => Some(i),
+            Value::Number(n) => {
+                if n.fract() == 0.0
+                    && n >= Integer::min_value() as Number
+                    && n < -(Integer::min_value() as Number)
+                {
+                    Some(n as Integer)
+                } else {
+                    None
+                }
+            }
+            Value::String(ref s) => s.0.lua.coerce_integer(self.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Converts a string or number to a Rust string, the way Lua coerces values to strings for the
+    /// `..` operator.
+    ///
+    /// Numbers are formatted like Lua formats them, and strings that are not valid UTF-8 have
+    /// their invalid sequences replaced with `U+FFFD REPLACEMENT CHARACTER`.  No metamethods are
+    /// invoked, so this never fails, and returns `None` for every value other than a string or a
+    /// number.  Use [`tostring`] to convert any value.
+    ///
+    /// [`tostring`]: #method.tostring
+    pub fn to_string_lossy(&self) -> Option<StdString> {
+        match *self {
+            Value::Integer(i) => Some(number_to_string(i as Number)),
+            Value::Number(n) => Some(number_to_string(n)),
+            Value::String(ref s) => Some(s.to_str_lossy().into_owned()),
+            _ => None,
+        }
+    }
+
+    /// Converts any value to a Rust string, in the same way as Lua's `tostring` function.
+    ///
+    /// Values with a `__tostring` metamethod are converted by calling it, which can raise an
+    /// error, and tables, functions and threads without one are shown with their type and address.
+    /// Invalid UTF-8 in the result is replaced like in [`to_string_lossy`].  Use
+    /// [`Lua::coerce_to_string`] to keep the result as a Lua string.
+    ///
+    /// [`to_string_lossy`]: #method.to_string_lossy
+    /// [`Lua::coerce_to_string`]: struct.Lua.html#method.coerce_to_string
+    pub fn tostring(&self) -> Result<StdString> {
+        match *self {
+            Value::Nil => Ok("nil".to_owned()),
+            Value::Boolean(b) => Ok(b.to_string()),
+            Value::LightUserData(ud) => Ok(format!("userdata: {:p}", ud.0)),
+            Value::Integer(i) => Ok(number_to_string(i as Number)),
+            Value::Number(n) => Ok(number_to_string(n)),
+            Value::String(ref s) => lossy_tostring(s.0.lua, self),
+            Value::Table(ref t) => lossy_tostring(t.0.lua, self),
+            Value::Function(ref f) => lossy_tostring(f.0.lua, self),
+            Value::Thread(ref t) => lossy_tostring(t.0.lua, self),
+            Value::Error(ref e) => Ok(e.to_string()),
+        }
+    }
+
+    /// Returns the address Lua uses to identify this value, as shown by Lua's default `tostring`.
+    ///
+    /// Tables, functions and threads yield the address of the underlying Lua object, which
+    /// identifies it for as long as it is alive, and light userdata yields the pointer it holds.
+    /// All other values have no identity and yield a null pointer; this includes strings, which
+    /// Lua compares by value.  No metamethods are invoked.
+    ///
+    /// The pointer is only meant for identifying values, for example in logs or as a cache key,
+    /// and must never be dereferenced.
+    pub fn to_pointer(&self) -> *const c_void {
+        match *self {
+            Value::LightUserData(ud) => ud.0 as *const c_void,
+            Value::Table(ref t) => t.0.lua.ref_pointer(&t.0),
+            Value::Function(ref f) => f.0.lua.ref_pointer(&f.0),
+            Value::Thread(ref t) => t.0.lua.ref_pointer(&t.0),
+            _ => ptr::null(),
+        }
+    }
+}
+
+// Formats a number the way Lua does, using the default `LUA_NUMBER_FMT` of Lua 5.1.
+fn number_to_string(n: Number) -> StdString {
+    let mut buf = [0 as c_char; 32];
+    unsafe {
+        libc::snprintf(buf.as_mut_ptr(), buf.len(), cstr!("%.14g"), n);
+        CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+    }
+}
+
+// Converts a value with `Lua::coerce_to_string`, replacing invalid UTF-8 in the result.
+fn lossy_tostring<'lua>(lua: &'lua Lua, value: &Value<'lua>) -> Result<StdString> {
+    Ok(lua.coerce_to_string(value)?.to_str_lossy().into_owned())
 }
 
 /// Trait for types convertible to `Value`.
@@ -107,17 +438,35 @@ impl<'a, 'lua> IntoIterator for &'a MultiValue<'lua> {
 }
 
 impl<'lua> MultiValue<'lua> {
+    /// Creates a `MultiValue` from the values in `v`, in the same order.
+    ///
+    /// Like everywhere else in `MultiValue`, the first element of `v` is the first value, that
+    /// is the first argument of a call or the leftmost value returned by a function.
     pub fn from_vec(mut v: Vec<Value<'lua>>) -> MultiValue<'lua> {
         v.reverse();
         MultiValue(v)
     }
 
+    /// Converts this `MultiValue` into a `Vec` of its values, the first value at index 0.
     pub fn into_vec(self) -> Vec<Value<'lua>> {
         let mut v = self.0;
         v.reverse();
         v
     }
 
+    /// Returns the value at position `index`, where index 0 is the first value, or `None` if there
+    /// are not that many values.
+    ///
+    /// The values of a `MultiValue` are in Lua's left-to-right order, so for the results of a
+    /// function call `get(0)` is the first value returned.
+    pub fn get(&self, index: usize) -> Option<&Value<'lua>> {
+        if index < self.0.len() {
+            self.0.get(self.0.len() - index - 1)
+        } else {
+            None
+        }
+    }
+
     pub(crate) fn reserve(&mut self, size: usize) {
         self.0.reserve(size);
     }
@@ -130,10 +479,17 @@ impl<'lua> MultiValue<'lua> {
         self.0.pop()
     }
 
+    /// Returns the number of values.
     pub fn len(&self) -> usize {
         self.0.len()
     }
 
+    /// Returns true if there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an iterator over the values, starting with the first one.
     pub fn iter(&self) -> iter::Rev<slice::Iter<Value<'lua>>> {
         self.0.iter().rev()
     }
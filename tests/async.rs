@@ -0,0 +1,168 @@
+#![cfg(feature = "async")]
+
+extern crate rlua;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+
+use rlua::{Error, Function, Lua, Result, Thread};
+
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+// Completes with `value` after returning `Poll::Pending` `polls` times.
+struct Delay {
+    polls: u32,
+    value: i64,
+}
+
+impl Future for Delay {
+    type Output = Result<i64>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.polls == 0 {
+            Poll::Ready(Ok(self.value))
+        } else {
+            self.polls -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+struct Fail;
+
+impl Future for Fail {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, _: &mut Context) -> Poll<Self::Output> {
+        Poll::Ready(Err(Error::RuntimeError("failed".to_owned())))
+    }
+}
+
+#[test]
+fn test_async_function() {
+    let lua = Lua::new();
+    let add = lua
+        .create_async_function(|_, (a, b): (i64, i64)| Delay {
+            polls: 3,
+            value: a + b,
+        }).unwrap();
+    lua.globals().set("add", add).unwrap();
+
+    let thread: Thread = lua
+        .eval(
+            r#"
+            coroutine.create(function(n)
+                local sum = 0
+                for i = 1, n do
+                    sum = add(sum, i)
+                end
+                return sum
+            end)
+            "#,
+            None,
+        ).unwrap();
+    assert_eq!(block_on(thread.into_async::<_, i64>(4).unwrap()).unwrap(), 10);
+
+    let thread: Thread = lua
+        .eval(
+            r#"
+            coroutine.create(function()
+                coroutine.yield(add(1, 2))
+                return 0
+            end)
+            "#,
+            None,
+        ).unwrap();
+    assert_eq!(block_on(thread.into_async::<_, i64>(()).unwrap()).unwrap(), 3);
+}
+
+#[test]
+fn test_async_function_errors() {
+    let lua = Lua::new();
+    let fail = lua.create_async_function(|_, ()| Fail).unwrap();
+    let thread = lua.create_thread(fail).unwrap();
+    match block_on(thread.into_async::<_, ()>(()).unwrap()) {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "failed"),
+            ref err => panic!("expected RuntimeError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    let add = lua
+        .create_async_function(|_, (a, b): (i64, i64)| Delay {
+            polls: 1,
+            value: a + b,
+        }).unwrap();
+    match add.call::<_, i64>((1, 2)) {
+        Err(Error::CallbackError { .. }) => {}
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+    let thread = lua.create_thread(add).unwrap();
+    assert!(thread.resume::<_, i64>((1, 2)).is_err());
+}
+
+#[test]
+fn test_async_function_nested_coroutine() {
+    let lua = Lua::new();
+    let add = lua
+        .create_async_function(|_, (a, b): (i64, i64)| Delay {
+            polls: 1,
+            value: a + b,
+        }).unwrap();
+    lua.globals().set("add", add).unwrap();
+
+    // Only the polled thread itself may call async functions, a coroutine it resumes would yield
+    // back to it instead of to the `AsyncThread`.
+    let thread: Thread = lua
+        .eval(
+            r#"
+            coroutine.create(function()
+                local co = coroutine.create(function()
+                    return add(1, 2)
+                end)
+                local ok, err = coroutine.resume(co)
+                assert(not ok)
+                return tostring(err), add(3, 4)
+            end)
+            "#,
+            None,
+        ).unwrap();
+    let (err, sum) = block_on(thread.into_async::<_, (String, i64)>(()).unwrap()).unwrap();
+    assert!(err.contains("async function called outside of an AsyncThread"), "{}", err);
+    assert_eq!(sum, 7);
+}
+
+#[test]
+fn test_async_function_value() {
+    let lua = Lua::new();
+    let double: Function = lua
+        .create_async_function(|_, n: i64| Delay {
+            polls: 0,
+            value: n * 2,
+        }).unwrap();
+    let thread = lua.create_thread(double).unwrap();
+    assert_eq!(block_on(thread.into_async::<_, i64>(21).unwrap()).unwrap(), 42);
+}
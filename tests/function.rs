@@ -1,6 +1,13 @@
+extern crate failure;
 extern crate rlua;
 
-use rlua::{Function, Lua, String};
+use std::iter::FromIterator;
+use std::num::ParseIntError;
+use std::ops::ControlFlow;
+
+use rlua::{
+    ChunkMode, Error, Function, Lua, MultiValue, Nil, Result, String, Table, Value, Variadic,
+};
 
 #[test]
 fn test_function() {
@@ -19,6 +26,51 @@ fn test_function() {
     assert_eq!(concat.call::<_, String>(("foo", "bar")).unwrap(), "foobar");
 }
 
+#[test]
+fn test_call_catch() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals
+        .set(
+            "rust_error",
+            lua.create_function(|_, ()| -> Result<()> { Err(Error::RecursiveMutCallback) })
+                .unwrap(),
+        ).unwrap();
+    let f: Function = lua
+        .eval(
+            r#"
+                function(kind)
+                    if kind == "table" then
+                        error({code = 5})
+                    elseif kind == "string" then
+                        error("message", 0)
+                    elseif kind == "rust" then
+                        rust_error()
+                    end
+                    return 1, 2
+                end
+            "#,
+            None,
+        ).unwrap();
+
+    assert_eq!(
+        f.call_catch::<_, (i64, i64), Value>("none").unwrap().unwrap(),
+        (1, 2)
+    );
+    let err = f.call_catch::<_, (), Table>("table").unwrap().unwrap_err();
+    assert_eq!(err.get::<_, i64>("code").unwrap(), 5);
+    let err = f.call_catch::<_, (), String>("string").unwrap().unwrap_err();
+    assert_eq!(err, "message");
+    match f.call_catch::<_, (), Error>("rust").unwrap() {
+        Err(Error::RecursiveMutCallback) => {}
+        r => panic!("expected RecursiveMutCallback, got {:?}", r),
+    }
+
+    // Only a failing conversion is an `Err`.
+    assert!(f.call_catch::<_, (), Table>("string").is_err());
+    assert!(f.call_catch::<_, Table, Value>("none").is_err());
+}
+
 #[test]
 fn test_bind() {
     let lua = Lua::new();
@@ -46,6 +98,25 @@ fn test_bind() {
     );
 }
 
+#[test]
+fn test_bind_repeated() {
+    let lua = Lua::new();
+    let count: Function = lua.eval("function(...) return select('#', ...) end", None).unwrap();
+
+    // Repeated binds do not nest, so this does not run into the C call depth limit.
+    let mut bound = count.clone();
+    for i in 0..250 {
+        bound = bound.bind(i).unwrap();
+    }
+    assert_eq!(bound.call::<_, i64>(()).unwrap(), 250);
+    assert_eq!(bound.call::<_, i64>((1, 2, 3)).unwrap(), 253);
+    assert!(bound.bind((1, 2, 3, 4)).is_err());
+
+    let partial = count.bind((1, 2)).unwrap();
+    assert_eq!(partial.bind(3).unwrap().call::<_, i64>(4).unwrap(), 4);
+    assert_eq!(partial.call::<_, i64>(()).unwrap(), 2);
+}
+
 #[test]
 fn test_rust_function() {
     let lua = Lua::new();
@@ -68,3 +139,315 @@ fn test_rust_function() {
     globals.set("rust_function", rust_function).unwrap();
     assert_eq!(lua_function.call::<_, String>(()).unwrap(), "hello");
 }
+
+#[test]
+fn test_function_ext() {
+    let lua = Lua::new();
+
+    let parse = lua
+        .create_function_ext(|_, s: String| s.to_str().unwrap().parse::<i64>())
+        .unwrap();
+    assert_eq!(parse.call::<_, i64>("17").unwrap(), 17);
+    match parse.call::<_, i64>("x") {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::ExternalError(ref err) => assert!(err.downcast_ref::<ParseIntError>().is_some()),
+            ref err => panic!("expected ExternalError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    // rlua errors passed through `failure::Error` are not wrapped again.
+    let both = lua
+        .create_function_ext(|lua, s: String| -> ::std::result::Result<i64, failure::Error> {
+            let n = s.to_str()?.parse::<i64>()?;
+            Ok(n + lua.eval::<i64>("error('bad')", None)?)
+        }).unwrap();
+    match both.call::<_, i64>("1") {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert!(msg.contains("bad")),
+            ref err => panic!("expected RuntimeError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+    let err = both.call::<_, i64>("y").unwrap_err();
+    assert!(err.downcast_ref::<ParseIntError>().is_some());
+}
+
+#[test]
+fn test_function_with_upvalues() {
+    let lua = Lua::new();
+    let seen = lua.create_table().unwrap();
+
+    let record = lua
+        .create_function_with(
+            (seen.clone(), "prefix: "),
+            |lua, upvalues, (a, b): (i64, i64)| {
+                let (seen, prefix): (Table, String) = lua.unpack_multi(upvalues)?;
+                let sum = a + b;
+                seen.set(seen.raw_len() + 1, sum)?;
+                Ok(format!("{}{}", prefix.to_str()?, sum))
+            },
+        ).unwrap();
+    lua.globals().set("record", record).unwrap();
+
+    lua.exec::<()>(
+        r#"
+            assert(record(1, 2) == "prefix: 3")
+            assert(record(3, 4) == "prefix: 7")
+        "#,
+        None,
+    ).unwrap();
+    assert_eq!(seen.get::<_, i64>(1).unwrap(), 3);
+    assert_eq!(seen.get::<_, i64>(2).unwrap(), 7);
+
+    // No upvalues, and upvalues that are nil.
+    let count = lua
+        .create_function_with((), |_, upvalues, args: MultiValue| {
+            Ok((upvalues.len(), args.len()))
+        }).unwrap();
+    assert_eq!(count.call::<_, (usize, usize)>((1, 2)).unwrap(), (0, 2));
+    let count = lua
+        .create_function_with((Nil, Nil), |_, upvalues, args: MultiValue| {
+            Ok((upvalues.len(), args.len()))
+        }).unwrap();
+    assert_eq!(count.call::<_, (usize, usize)>(Nil).unwrap(), (2, 1));
+
+    let too_many = Variadic::from_iter(0..254);
+    assert!(lua.create_function_with(too_many, |_, _, ()| Ok(())).is_err());
+}
+
+#[test]
+fn test_large_tuples() {
+    let lua = Lua::new();
+
+    // Reverses twelve arguments, passing them through Lua and back.
+    let reverse = lua
+        .create_function(
+            |_, (a, b, c, d, e, f, g, h, i, j, k, l): (
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+            )| Ok((l, k, j, i, h, g, f, e, d, c, b, a)),
+        ).unwrap();
+    let result: (i64, i64, i64, i64, i64, i64, i64, i64, i64, i64, i64, i64) = reverse
+        .call((1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12))
+        .unwrap();
+    assert_eq!(result, (12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1));
+
+    let count: Function = lua.eval("function(...) return select('#', ...) end", None).unwrap();
+    assert_eq!(
+        count
+            .call::<_, i64>((1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16))
+            .unwrap(),
+        16
+    );
+}
+
+#[test]
+fn test_dump() {
+    let lua = Lua::new();
+    let sum: Function = lua
+        .eval(
+            r#"
+        function(a, b)
+            local function sum(x, y)
+                return x + y
+            end
+            return sum(a, b) .. "!"
+        end
+    "#,
+            Some("sum"),
+        ).unwrap();
+
+    let bytecode = sum.dump(false).unwrap();
+    let stripped = sum.dump(true).unwrap();
+    assert!(stripped.len() < bytecode.len());
+
+    let loaded = lua.load_bytecode(&bytecode, None).unwrap();
+    assert_eq!(loaded.call::<_, String>((1, 2)).unwrap(), "3!");
+    let loaded = lua.load_bytecode(&stripped, None).unwrap();
+    assert_eq!(loaded.call::<_, String>((1, 2)).unwrap(), "3!");
+
+    let rust_function = lua.create_function(|_, ()| Ok(())).unwrap();
+    assert!(rust_function.dump(false).is_err());
+}
+
+#[test]
+fn test_strip_debug() {
+    let lua = Lua::new();
+    let chunk = lua
+        .load(
+            b"local x = ...\nif x then return x * 2 end\nerror('no value')",
+            Some("chunk"),
+            ChunkMode::Text,
+        ).unwrap();
+
+    let stripped = chunk.strip_debug().unwrap();
+    assert_eq!(stripped.call::<_, i64>(21).unwrap(), 42);
+    assert!(stripped.dump(false).unwrap().len() < chunk.dump(false).unwrap().len());
+
+    // The original keeps its line numbers, the stripped copy does not.
+    match chunk.call::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("chunk:3:")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    match stripped.call::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("no value"));
+            assert!(!msg.contains(":3:"));
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    // The environment is shared with the original.
+    let env = lua.create_table().unwrap();
+    let setter = lua.load(b"y = 1", None, ChunkMode::Text).unwrap();
+    setter.set_environment(env.clone()).unwrap();
+    setter.strip_debug().unwrap().call::<_, ()>(()).unwrap();
+    assert_eq!(env.get::<_, i64>("y").unwrap(), 1);
+
+    let closure: Function = lua
+        .eval("(function() local x = 1; return function() return x end end)()", None)
+        .unwrap();
+    assert!(closure.strip_debug().is_err());
+    let rust_function = lua.create_function(|_, ()| Ok(())).unwrap();
+    assert!(rust_function.strip_debug().is_err());
+}
+
+#[test]
+fn test_call_with_hook() {
+    let lua = Lua::new();
+    let forever: Function = lua
+        .eval(
+            r#"
+                function()
+                    while true do
+                        pcall(function() while true do end end)
+                    end
+                end
+            "#,
+            None,
+        ).unwrap();
+
+    let mut calls = 0;
+    match forever.call_with_hook::<_, (), _>((), 100, |_| {
+        calls += 1;
+        if calls < 10 {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        }
+    }) {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "call cancelled by hook"),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    assert_eq!(calls, 10);
+
+    // The hook is removed once the call returns
+    let count: Function = lua
+        .eval("function() local n = 0 for i = 1, 10000 do n = n + 1 end return n end", None)
+        .unwrap();
+    assert_eq!(count.call::<_, i64>(()).unwrap(), 10000);
+    let mut hooks = 0;
+    assert_eq!(
+        count
+            .call_with_hook::<_, i64, _>((), 100, |_| {
+                hooks += 1;
+                ControlFlow::Continue(())
+            }).unwrap(),
+        10000
+    );
+    assert!(hooks > 0);
+}
+
+#[test]
+fn test_environment() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("value", "global").unwrap();
+
+    let chunk: Function = lua
+        .eval("function() value = value .. '!' return value end", None)
+        .unwrap();
+    assert_eq!(
+        chunk.get_environment().unwrap().get::<_, String>("value").unwrap(),
+        "global"
+    );
+
+    let env = lua.create_table().unwrap();
+    env.set("value", "sandboxed").unwrap();
+    chunk.set_environment(env.clone()).unwrap();
+    drop(env);
+    lua.gc_collect().unwrap();
+
+    assert_eq!(chunk.call::<_, String>(()).unwrap(), "sandboxed!");
+    assert_eq!(chunk.call::<_, String>(()).unwrap(), "sandboxed!!");
+    assert_eq!(globals.get::<_, String>("value").unwrap(), "global");
+    assert_eq!(
+        chunk.get_environment().unwrap().get::<_, String>("value").unwrap(),
+        "sandboxed!!"
+    );
+
+    let rust_function = lua.create_function(|_, ()| Ok(())).unwrap();
+    assert!(rust_function.get_environment().is_err());
+    assert!(
+        rust_function
+            .set_environment(lua.create_table().unwrap())
+            .is_err()
+    );
+}
+
+#[test]
+fn test_info() {
+    let lua = Lua::new();
+    lua.exec::<()>(
+        r#"
+            function fixed(a, b, c)
+                return a
+            end
+            local up = 1
+            function variadic(a, ...)
+                return up
+            end
+        "#,
+        Some("script"),
+    ).unwrap();
+    let globals = lua.globals();
+
+    let fixed = globals.get::<_, Function>("fixed").unwrap().info().unwrap();
+    assert!(!fixed.is_c);
+    assert_eq!(fixed.source.as_ref().map(|s| &s[..]), Some("=script"));
+    assert_eq!(fixed.short_source.as_ref().map(|s| &s[..]), Some("script"));
+    assert_eq!(fixed.line_defined, Some(2));
+    assert_eq!(fixed.last_line_defined, Some(4));
+    assert_eq!(fixed.num_params, 3);
+    assert!(!fixed.is_vararg);
+    assert_eq!(fixed.num_upvalues, 0);
+
+    let variadic = globals.get::<_, Function>("variadic").unwrap().info().unwrap();
+    assert_eq!(variadic.num_params, 1);
+    assert!(variadic.is_vararg);
+    assert_eq!(variadic.num_upvalues, 1);
+
+    let chunk = lua.load(b"return ...", None, ChunkMode::Text).unwrap().info().unwrap();
+    assert_eq!(chunk.line_defined, Some(0));
+    assert_eq!(chunk.num_params, 0);
+    assert!(chunk.is_vararg);
+
+    let rust = lua.create_function(|_, (_, _): (i32, i32)| Ok(())).unwrap().info().unwrap();
+    assert!(rust.is_c);
+    assert!(rust.source.is_none());
+    assert!(rust.line_defined.is_none());
+    assert_eq!(rust.num_params, 0);
+    assert!(rust.is_vararg);
+    assert!(globals.get::<_, Function>("print").unwrap().info().unwrap().is_c);
+}
@@ -3,7 +3,7 @@ extern crate rlua;
 use std::cell::Cell;
 use std::rc::Rc;
 
-use rlua::{Error, Function, Lua, String};
+use rlua::{Error, Function, Lua, Result, String};
 
 #[test]
 fn scope_func() {
@@ -70,3 +70,80 @@ fn outer_lua_access() {
     });
     assert_eq!(table.get::<_, String>("a").unwrap(), "b");
 }
+
+#[test]
+fn scope_return_value() {
+    let lua = Lua::new();
+
+    let rc = Rc::new(Cell::new(0));
+    let (count, name) = lua.scope(|scope| {
+        let r = rc.clone();
+        let f = scope
+            .create_function(move |_, n: i32| {
+                r.set(r.get() + n);
+                Ok(r.get())
+            })
+            .unwrap();
+        lua.globals().set("add", f).unwrap();
+        let count = lua.eval::<i32>("add(1) return add(2)", None).unwrap();
+        (count, "scoped".to_owned())
+    });
+    assert_eq!(count, 3);
+    assert_eq!(name, "scoped");
+    // The scoped function was dropped before the value was returned.
+    assert_eq!(Rc::strong_count(&rc), 1);
+}
+
+#[test]
+fn scope_try() {
+    struct PanicOnDrop;
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("drop panic");
+        }
+    }
+
+    let lua = Lua::new();
+
+    let n = lua
+        .try_scope(|scope| {
+            let f = scope.create_function(|_, n: i32| Ok(n * 2))?;
+            lua.globals().set("double", f)?;
+            lua.eval::<i32>("double(21)", None)
+        })
+        .unwrap();
+    assert_eq!(n, 42);
+
+    let res = lua.try_scope(|scope| {
+        let f = scope.create_function(|_, ()| -> Result<()> {
+            Err(Error::RuntimeError("scoped error".to_owned()))
+        })?;
+        lua.globals().set("fail", f)?;
+        lua.exec::<()>("fail()", None)?;
+        Ok(())
+    });
+    match res {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "scoped error"),
+            ref e => panic!("unexpected cause {:?}", e),
+        },
+        r => panic!("expected a callback error, got {:?}", r),
+    };
+    // The scoped function was invalidated on the error path as well.
+    assert!(lua.exec::<()>("fail()", None).is_err());
+
+    // A panic while dropping the scoped values does not mask the error.
+    let res = lua.try_scope(|scope| -> Result<()> {
+        let guard = PanicOnDrop;
+        scope.create_function(move |_, ()| {
+            let _ = &guard;
+            Ok(())
+        })?;
+        Err(Error::RuntimeError("original".to_owned()))
+    });
+    match res {
+        Err(Error::RuntimeError(ref msg)) => assert_eq!(msg, "original"),
+        r => panic!("expected the original error, got {:?}", r),
+    };
+}
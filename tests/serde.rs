@@ -0,0 +1,111 @@
+#![cfg(feature = "serde")]
+
+extern crate rlua;
+#[macro_use]
+extern crate serde_derive;
+
+use std::collections::HashMap;
+
+use rlua::{from_value, to_value, Error, Lua, Table, Value};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    port: u16,
+    ratio: f64,
+    tags: Vec<String>,
+    limits: HashMap<String, i32>,
+    parent: Option<Box<Config>>,
+    mode: Mode,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Mode {
+    Fast,
+    Limited(u32),
+    Custom { level: u8 },
+}
+
+#[test]
+fn test_serde_roundtrip() {
+    let lua = Lua::new();
+
+    let mut limits = HashMap::new();
+    limits.insert("cpu".to_owned(), 4);
+    let config = Config {
+        name: "server".to_owned(),
+        port: 8080,
+        ratio: 0.5,
+        tags: vec!["a".to_owned(), "b".to_owned()],
+        limits,
+        parent: Some(Box::new(Config {
+            name: "parent".to_owned(),
+            port: 80,
+            ratio: 1.0,
+            tags: Vec::new(),
+            limits: HashMap::new(),
+            parent: None,
+            mode: Mode::Custom { level: 3 },
+        })),
+        mode: Mode::Limited(10),
+    };
+
+    let value = to_value(&lua, &config).unwrap();
+    lua.globals().set("config", value.clone()).unwrap();
+    lua.exec::<()>(
+        r#"
+            assert(config.name == "server")
+            assert(config.port == 8080)
+            assert(#config.tags == 2 and config.tags[2] == "b")
+            assert(config.limits.cpu == 4)
+            assert(config.parent.parent == nil)
+            assert(config.parent.mode.Custom.level == 3)
+            assert(config.mode.Limited == 10)
+        "#,
+        None,
+    ).unwrap();
+
+    assert_eq!(from_value::<Config>(value).unwrap(), config);
+}
+
+#[test]
+fn test_serde_from_lua() {
+    let lua = Lua::new();
+
+    let value: Value = lua
+        .eval(
+            r#"
+                {
+                    name = "lua",
+                    port = 1,
+                    ratio = 2,
+                    tags = {},
+                    limits = {},
+                    mode = "Fast",
+                }
+            "#,
+            None,
+        ).unwrap();
+    let config: Config = from_value(value).unwrap();
+    assert_eq!(config.ratio, 2.0);
+    assert_eq!(config.parent, None);
+    assert_eq!(config.mode, Mode::Fast);
+
+    let sequence: Value = lua.eval("{1, 2, 3}", None).unwrap();
+    assert_eq!(from_value::<Vec<i64>>(sequence).unwrap(), vec![1, 2, 3]);
+
+    let sparse: Table = lua.eval("{[1] = 1, [3] = 3}", None).unwrap();
+    assert!(from_value::<Vec<i64>>(Value::Table(sparse.clone())).is_err());
+    let map: HashMap<i64, i64> = from_value(Value::Table(sparse)).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&3], 3);
+
+    let missing: Value = lua.eval("{name = 'lua', port = 1}", None).unwrap();
+    match from_value::<Config>(missing) {
+        Err(Error::DeserializeError(ref msg)) => assert!(msg.contains("ratio")),
+        r => panic!("expected a missing field error, got {:?}", r),
+    }
+
+    let function: Value = lua.eval("function() end", None).unwrap();
+    assert!(from_value::<i64>(function).is_err());
+}
@@ -61,3 +61,73 @@ fn compare() {
 //    assert_eq!(empty.as_bytes_with_nul(), &[0]);
 //    assert_eq!(empty.as_bytes(), &[]);
 //}
+
+#[test]
+fn to_str_lossy() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    lua.exec::<()>(
+        r#"
+            nul = "embedded \0 nul"
+            invalid = "lone \255 byte"
+        "#,
+        None,
+    ).unwrap();
+
+    let nul: String = globals.get("nul").unwrap();
+    let invalid: String = globals.get("invalid").unwrap();
+
+    match nul.to_str_lossy() {
+        Cow::Borrowed(s) => assert_eq!(s, "embedded \0 nul"),
+        Cow::Owned(_) => panic!("valid UTF-8 should not be copied"),
+    }
+    assert!(invalid.to_str().is_err());
+    assert_eq!(invalid.to_str_lossy(), "lone \u{fffd} byte");
+    assert_eq!(invalid.as_bytes(), &b"lone \xff byte"[..]);
+}
+
+#[test]
+fn len() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    lua.exec::<()>(
+        r#"
+        embedded = "a\0b"
+        empty = ""
+    "#,
+        None,
+    ).unwrap();
+
+    let embedded: String = globals.get("embedded").unwrap();
+    assert_eq!(embedded.len(), 3);
+    assert!(!embedded.is_empty());
+    assert_eq!(embedded.as_bytes(), b"a\0b");
+    assert_eq!(embedded.as_bytes_with_nul(), b"a\0b\0");
+
+    globals.set("copy", embedded).unwrap();
+    assert_eq!(lua.eval::<bool>("copy == embedded", None).unwrap(), true);
+
+    let empty: String = globals.get("empty").unwrap();
+    assert_eq!(empty.len(), 0);
+    assert!(empty.is_empty());
+    assert_eq!(empty.as_bytes_with_nul(), &[0]);
+}
+
+#[test]
+fn intern() {
+    let lua = Lua::new();
+    let table = lua.create_table().unwrap();
+
+    let x = lua.intern("x").unwrap();
+    table.set(&x, 1).unwrap();
+    lua.gc_collect().unwrap();
+    assert_eq!(table.get::<_, i64>("x").unwrap(), 1);
+    assert_eq!(table.get::<_, i64>(lua.intern("x").unwrap()).unwrap(), 1);
+    assert_eq!(lua.intern("x").unwrap(), x);
+    assert_eq!(x, "x");
+
+    let bytes = lua.intern(&b"\xff\0"[..]).unwrap();
+    assert_eq!(bytes.as_bytes(), b"\xff\0");
+    table.set(&bytes, 2).unwrap();
+    assert_eq!(table.get::<_, i64>(&bytes).unwrap(), 2);
+}
@@ -1,6 +1,8 @@
 extern crate rlua;
 
-use rlua::{Lua, Nil, Result, Table, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use rlua::{Entry, Error, Function, Lua, Nil, Result, Table, Value, WeakMode};
 
 #[test]
 fn test_set_get() {
@@ -134,17 +136,37 @@ fn test_metatable() {
             lua.create_function(|_, ()| Ok("index_value")).unwrap(),
         )
         .unwrap();
-    table.set_metatable(Some(metatable));
+    assert!(table.get_metatable().is_none());
+    assert!(table.set_metatable(Some(metatable)).is_none());
     assert_eq!(table.get::<_, String>("any_key").unwrap(), "index_value");
     match table.raw_get::<_, Value>("any_key").unwrap() {
         Nil => {}
         _ => panic!(),
     }
-    table.set_metatable(None);
+    let previous = table.set_metatable(None).unwrap();
+    assert_eq!(
+        previous.get::<_, Function>("__index").unwrap().call::<_, String>(()).unwrap(),
+        "index_value"
+    );
+    assert!(table.get_metatable().is_none());
     match table.get::<_, Value>("any_key").unwrap() {
         Nil => {}
         _ => panic!(),
     };
+
+    // The `__metatable` field only protects the metatable from Lua code
+    let protected: Table = lua
+        .eval("setmetatable({}, {__metatable = false})", None)
+        .unwrap();
+    lua.globals().set("protected", protected.clone()).unwrap();
+    assert!(lua
+        .exec::<()>("setmetatable(protected, {})", None)
+        .is_err());
+    let replaced = protected
+        .set_metatable(Some(lua.create_table().unwrap()))
+        .unwrap();
+    assert_eq!(replaced.get::<_, bool>("__metatable").unwrap(), false);
+    lua.exec::<()>("setmetatable(protected, nil)", None).unwrap();
 }
 
 #[test]
@@ -177,3 +199,671 @@ fn test_table_error() {
     assert!(bad_table.raw_get::<_, i32>(1).is_ok());
     assert_eq!(bad_table.raw_len(), 1);
 }
+
+#[test]
+fn test_len() {
+    let lua = Lua::new();
+    let sequence: Table = lua
+        .eval(
+            r#"
+                setmetatable({1, 2, 3}, {
+                    __len = function() return 30 end
+                })
+            "#,
+            None,
+        ).unwrap();
+    assert_eq!(sequence.len().unwrap(), 30);
+    assert_eq!(sequence.raw_len(), 3);
+
+    let table = lua.create_table().unwrap();
+    assert_eq!(table.len().unwrap(), 0);
+    assert_eq!(table.raw_len(), 0);
+    table.set(2, "hole at 1").unwrap();
+    assert!(table.raw_len() == 0 || table.raw_len() == 2);
+}
+
+#[test]
+fn test_insert_remove() {
+    let lua = Lua::new();
+
+    let table = lua.create_sequence_from(vec![1, 2, 3]).unwrap();
+    table.push(4).unwrap();
+    table.insert(1, 0).unwrap();
+    table.insert(3, 10).unwrap();
+    table.insert(7, 5).unwrap();
+    assert_eq!(
+        table.clone().sequence_values().collect::<Result<Vec<i64>>>().unwrap(),
+        vec![0, 1, 10, 2, 3, 4, 5]
+    );
+    assert!(table.insert(0, 0).is_err());
+    assert!(table.insert(9, 0).is_err());
+
+    assert_eq!(lua.unpack::<i64>(table.remove(3).unwrap()).unwrap(), 10);
+    assert_eq!(lua.unpack::<i64>(table.remove(1).unwrap()).unwrap(), 0);
+    assert_eq!(
+        table.clone().sequence_values().collect::<Result<Vec<i64>>>().unwrap(),
+        vec![1, 2, 3, 4, 5]
+    );
+    assert!(table.remove(0).is_err());
+    assert!(table.remove(7).is_err());
+    match table.remove(6).unwrap() {
+        Nil => {}
+        v => panic!("expected nil, got {:?}", v),
+    }
+    assert_eq!(table.len().unwrap(), 5);
+
+    let empty = lua.create_table().unwrap();
+    match (empty.remove(0).unwrap(), empty.remove(1).unwrap()) {
+        (Nil, Nil) => {}
+        v => panic!("expected nil, got {:?}", v),
+    }
+
+    // The sequence length is taken from `__len`, like the Lua `table` functions do.
+    let proxied: Table = lua
+        .eval(
+            r#"
+                setmetatable({}, {
+                    __len = function() return 2 end,
+                })
+            "#,
+            None,
+        ).unwrap();
+    proxied.push("c").unwrap();
+    assert_eq!(proxied.raw_get::<_, String>(3).unwrap(), "c");
+}
+
+#[test]
+fn test_raw_insert_remove() {
+    let lua = Lua::new();
+
+    // None of these metamethods may be triggered by the raw variants.
+    let table: Table = lua
+        .eval(
+            r#"
+                setmetatable({1, 2, 3}, {
+                    __len = function() return 0 end,
+                    __index = function() return "default" end,
+                    __newindex = function() error("no writes") end,
+                })
+            "#,
+            None,
+        ).unwrap();
+
+    table.raw_insert(1, 0).unwrap();
+    table.raw_insert(5, 4).unwrap();
+    assert!(table.raw_insert(7, 0).is_err());
+    assert_eq!(table.raw_len(), 5);
+    assert!(table.set(6, 0).is_err());
+
+    assert_eq!(lua.unpack::<i64>(table.raw_remove(2).unwrap()).unwrap(), 1);
+    assert_eq!(lua.unpack::<i64>(table.raw_remove(4).unwrap()).unwrap(), 4);
+    assert_eq!(table.raw_len(), 3);
+    let values = (1..=3)
+        .map(|i| table.raw_get(i))
+        .collect::<Result<Vec<i64>>>()
+        .unwrap();
+    assert_eq!(values, vec![0, 2, 3]);
+    assert!(table.raw_get::<_, Option<i64>>(4).unwrap().is_none());
+    assert_eq!(table.get::<_, String>(4).unwrap(), "default");
+}
+
+#[test]
+fn test_get_or_insert_with() {
+    let lua = Lua::new();
+    let table: Table = lua.eval("{falsey = false, zero = 0}", None).unwrap();
+
+    let inserted = table
+        .get_or_insert_with("sub", |lua| lua.create_table().map(Value::Table))
+        .unwrap();
+    let sub: Table = table.get("sub").unwrap();
+    match inserted {
+        Value::Table(t) => {
+            t.set("x", 1).unwrap();
+            assert_eq!(sub.get::<_, i64>("x").unwrap(), 1);
+        }
+        v => panic!("expected a table, got {:?}", v),
+    }
+
+    for key in &["falsey", "zero", "sub"] {
+        table
+            .get_or_insert_with(*key, |_| -> Result<Value> {
+                panic!("default must not be created for {}", key)
+            }).unwrap();
+    }
+    assert_eq!(table.get::<_, bool>("falsey").unwrap(), false);
+    assert_eq!(table.get::<_, i64>("zero").unwrap(), 0);
+
+    assert!(
+        table
+            .get_or_insert_with("missing", |_| Err(rlua::Error::BindError))
+            .is_err()
+    );
+    assert!(!table.contains_key("missing").unwrap());
+}
+
+#[test]
+fn test_contains_key() {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .eval(
+            r#"
+            setmetatable({ present = 1, falsey = false }, {
+                __index = function(t, k)
+                    if k == "inherited" then
+                        return true
+                    end
+                end,
+            })
+            "#,
+            None,
+        ).unwrap();
+
+    assert!(table.contains_key("present").unwrap());
+    assert!(table.contains_key("falsey").unwrap());
+    assert!(table.contains_key("inherited").unwrap());
+    assert!(!table.contains_key("missing").unwrap());
+
+    assert!(table.raw_contains_key("present").unwrap());
+    assert!(table.raw_contains_key("falsey").unwrap());
+    assert!(!table.raw_contains_key("inherited").unwrap());
+    assert!(!table.raw_contains_key("missing").unwrap());
+
+    let failing: Table = lua
+        .eval(
+            r#"setmetatable({}, { __index = function() error("no lookups") end })"#,
+            None,
+        ).unwrap();
+    assert!(failing.contains_key("key").is_err());
+    assert!(!failing.raw_contains_key("key").unwrap());
+}
+
+#[test]
+fn test_entry() {
+    let lua = Lua::new();
+    let counts = lua.create_table().unwrap();
+
+    for word in "a b a c a b".split(' ') {
+        counts
+            .entry(word)
+            .unwrap()
+            .and_modify(|n: i64| Ok(n + 1))
+            .unwrap()
+            .or_insert(1)
+            .unwrap();
+    }
+    assert_eq!(counts.get::<_, i64>("a").unwrap(), 3);
+    assert_eq!(counts.get::<_, i64>("b").unwrap(), 2);
+    assert_eq!(counts.get::<_, i64>("c").unwrap(), 1);
+
+    match counts.entry("a").unwrap() {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(lua.unpack::<String>(entry.key().clone()).unwrap(), "a");
+            assert_eq!(lua.unpack::<i64>(entry.get().clone()).unwrap(), 3);
+            assert_eq!(lua.unpack::<i64>(entry.insert(10).unwrap()).unwrap(), 3);
+            assert_eq!(lua.unpack::<i64>(entry.get().clone()).unwrap(), 10);
+            assert_eq!(lua.unpack::<i64>(entry.remove().unwrap()).unwrap(), 10);
+        }
+        Entry::Vacant(_) => panic!("expected an occupied entry"),
+    }
+    assert!(!counts.contains_key("a").unwrap());
+
+    match counts.entry("d").unwrap() {
+        Entry::Vacant(entry) => {
+            assert_eq!(lua.unpack::<String>(entry.key().clone()).unwrap(), "d");
+            assert_eq!(lua.unpack::<i64>(entry.insert(4).unwrap()).unwrap(), 4);
+        }
+        Entry::Occupied(_) => panic!("expected a vacant entry"),
+    }
+    assert_eq!(counts.get::<_, i64>("d").unwrap(), 4);
+
+    // A value that does not convert is left as it is.
+    counts.set("s", "text").unwrap();
+    assert!(
+        counts
+            .entry("s")
+            .unwrap()
+            .and_modify(|n: i64| Ok(n + 1))
+            .is_err()
+    );
+    assert_eq!(counts.get::<_, String>("s").unwrap(), "text");
+
+    // Entries go through metamethods.
+    let defaults: Table = lua
+        .eval(r#"setmetatable({}, { __index = function() return 0 end })"#, None)
+        .unwrap();
+    defaults
+        .entry("x")
+        .unwrap()
+        .and_modify(|n: i64| Ok(n + 5))
+        .unwrap();
+    assert_eq!(defaults.raw_get::<_, i64>("x").unwrap(), 5);
+}
+
+#[test]
+fn test_set_from() {
+    let lua = Lua::new();
+
+    let table = lua.create_table().unwrap();
+    table
+        .set_from((0..10000).map(|i| (format!("key{}", i), i)))
+        .unwrap();
+    assert_eq!(table.get::<_, i64>("key0").unwrap(), 0);
+    assert_eq!(table.get::<_, i64>("key9999").unwrap(), 9999);
+
+    let partial = lua.create_table().unwrap();
+    let pairs = vec![
+        (Value::Integer(1), Value::Boolean(true)),
+        (Nil, Value::Boolean(true)),
+        (Value::Integer(3), Value::Boolean(true)),
+    ];
+    assert!(partial.set_from(pairs).is_err());
+    assert_eq!(partial.get::<_, bool>(1).unwrap(), true);
+    assert!(!partial.contains_key(3).unwrap());
+}
+
+#[test]
+fn test_create_from_preallocates() {
+    let lua = Lua::new();
+    lua.gc_stop();
+
+    // Without a size hint, the array part grows in powers of two, so a preallocated table of 1000
+    // elements is smaller.
+    let before = lua.gc_count();
+    let hinted = lua.create_sequence_from(0..1000).unwrap();
+    let hinted_size = lua.gc_count() - before;
+    let before = lua.gc_count();
+    let unhinted = lua
+        .create_sequence_from((0..1000).filter(|_| true))
+        .unwrap();
+    let unhinted_size = lua.gc_count() - before;
+    assert!(hinted_size < unhinted_size);
+
+    assert_eq!(hinted.len().unwrap(), 1000);
+    assert_eq!(unhinted.len().unwrap(), 1000);
+    assert_eq!(hinted.get::<_, i64>(1000).unwrap(), 999);
+
+    let map = lua
+        .create_table_from((0..100).map(|i| (format!("key{}", i), i)))
+        .unwrap();
+    assert_eq!(map.get::<_, i64>("key42").unwrap(), 42);
+}
+
+#[test]
+fn test_map_conversions() {
+    let lua = Lua::new();
+
+    let table: Table = lua.eval("{1, 2, foo = 3, [10] = 4}", None).unwrap();
+    let map: HashMap<String, i64> = lua.unpack(Value::Table(table.clone())).unwrap();
+    assert_eq!(map.len(), 4);
+    assert_eq!(map["1"], 1);
+    assert_eq!(map["foo"], 3);
+    assert_eq!(map["10"], 4);
+    let map: BTreeMap<String, i64> = lua.unpack(Value::Table(table.clone())).unwrap();
+    assert_eq!(map.len(), 4);
+    assert_eq!(map["2"], 2);
+
+    // "foo" cannot be converted to an integer key
+    assert!(lua
+        .unpack::<HashMap<i64, i64>>(Value::Table(table.clone()))
+        .is_err());
+    assert!(lua.unpack::<BTreeMap<i64, i64>>(Value::Table(table)).is_err());
+
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), 1);
+    map.insert("b".to_owned(), 2);
+    let table: Table = lua.unpack(lua.pack(map).unwrap()).unwrap();
+    assert_eq!(table.get::<_, i64>("a").unwrap(), 1);
+    assert_eq!(table.get::<_, i64>("b").unwrap(), 2);
+
+    let mut map = BTreeMap::new();
+    map.insert(1, "x");
+    map.insert(3, "y");
+    let table: Table = lua.unpack(lua.pack(map).unwrap()).unwrap();
+    assert_eq!(table.get::<_, String>(1).unwrap(), "x");
+    assert!(table.get::<_, Option<String>>(2).unwrap().is_none());
+    assert_eq!(table.get::<_, String>(3).unwrap(), "y");
+}
+
+#[test]
+fn test_sequence_conversions() {
+    let lua = Lua::new();
+
+    let table: Table = lua.eval("{1, 2, 3}", None).unwrap();
+    let vec: Vec<i64> = lua.unpack(Value::Table(table.clone())).unwrap();
+    assert_eq!(vec, vec![1, 2, 3]);
+    let array: [i64; 3] = lua.unpack(Value::Table(table.clone())).unwrap();
+    assert_eq!(array, [1, 2, 3]);
+    assert!(lua.unpack::<[i64; 2]>(Value::Table(table)).is_err());
+
+    let holes: Table = lua.eval("{1, nil, 3}", None).unwrap();
+    let vec: Vec<Option<i64>> = lua.unpack(Value::Table(holes.clone())).unwrap();
+    assert_eq!(vec, vec![Some(1), None, Some(3)]);
+    assert!(lua.unpack::<Vec<i64>>(Value::Table(holes)).is_err());
+
+    match lua.unpack::<Vec<i64>>(Value::Integer(1)) {
+        Err(Error::FromLuaConversionError { from, to, .. }) => {
+            assert_eq!(from, "number");
+            assert_eq!(to, "Vec");
+        }
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    match lua.unpack::<[i64; 1]>(Value::Boolean(true)) {
+        Err(Error::FromLuaConversionError { to: "array", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    let table: Table = lua.unpack(lua.pack(["a", "b"]).unwrap()).unwrap();
+    assert_eq!(table.len().unwrap(), 2);
+    assert_eq!(table.get::<_, String>(2).unwrap(), "b");
+}
+
+#[test]
+fn test_readonly() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    let config: Table = lua
+        .eval("{1, 2, 3, name = 'test', nested = {value = 1}}", None)
+        .unwrap();
+    let readonly = config.clone().into_readonly().unwrap();
+    globals.set("config", readonly.clone()).unwrap();
+
+    assert_eq!(readonly.get::<_, String>("name").unwrap(), "test");
+    assert_eq!(readonly.len().unwrap(), 3);
+    assert_eq!(lua.eval::<i64>("config[2]", None).unwrap(), 2);
+    assert!(readonly.get_metatable().is_some());
+
+    // Lua 5.1 ignores `__len` for tables, and `ipairs` only sees the empty proxy.
+    assert_eq!(lua.eval::<i64>("#config", None).unwrap(), 0);
+    assert_eq!(
+        lua.eval::<i64>(
+            "local n = 0 for _ in ipairs(config) do n = n + 1 end return n",
+            None
+        ).unwrap(),
+        0
+    );
+
+    for code in &[
+        "config.name = 'other'",
+        "config.other = 1",
+        "config[1] = 0",
+        "setmetatable(config, nil)",
+    ] {
+        match lua.exec::<()>(code, None) {
+            Err(Error::RuntimeError(_)) => {}
+            r => panic!("expected RuntimeError, got {:?}", r),
+        }
+    }
+    match lua.exec::<()>("config.name = 'other'", None) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("attempt to modify a read-only table"))
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    assert_eq!(lua.eval::<bool>("getmetatable(config)", None).unwrap(), false);
+    assert_eq!(config.get::<_, String>("name").unwrap(), "test");
+
+    // Nested tables are only frozen by `into_readonly_recursive`
+    lua.exec::<()>("config.nested.value = 2", None).unwrap();
+    config.set("cycle", config.clone()).unwrap();
+    globals
+        .set("config", config.into_readonly_recursive().unwrap())
+        .unwrap();
+    assert_eq!(lua.eval::<i64>("config.nested.value", None).unwrap(), 2);
+    assert!(lua.exec::<()>("config.nested.value = 3", None).is_err());
+    assert!(lua.exec::<()>("config.cycle.name = 'other'", None).is_err());
+    assert!(lua.eval::<bool>("config.cycle == config", None).unwrap());
+
+    let nested: Table = lua
+        .eval(
+            "local t = {} for i = 1, 100000 do t = { child = t } end return t",
+            None,
+        ).unwrap();
+    globals
+        .set("nested", nested.into_readonly_recursive().unwrap())
+        .unwrap();
+    assert!(
+        lua.exec::<()>(
+            "local t = nested while t.child.child do t = t.child end t.child = 1",
+            None
+        ).is_err()
+    );
+}
+
+#[test]
+fn test_equals() {
+    let lua = Lua::new();
+    lua.exec::<()>(
+        r#"
+            local mt = { __eq = function(a, b) return a.id == b.id end }
+            a = setmetatable({ id = 1 }, mt)
+            b = setmetatable({ id = 1 }, mt)
+            c = setmetatable({ id = 2 }, mt)
+            plain = { id = 1 }
+            local err_mt = { __eq = function() error("no comparing") end }
+            e1 = setmetatable({}, err_mt)
+            e2 = setmetatable({}, err_mt)
+        "#,
+        None,
+    ).unwrap();
+    let globals = lua.globals();
+    let get = |name| globals.get::<_, Table>(name).unwrap();
+
+    assert!(get("a").equals(&get("b")).unwrap());
+    assert!(!get("a").equals(&get("c")).unwrap());
+    assert!(!get("a").equals(&get("plain")).unwrap());
+    assert!(get("a").equals(&get("a")).unwrap());
+
+    assert!(!get("a").raw_equal(&get("b")));
+    assert!(get("a").raw_equal(&get("a")));
+
+    match get("e1").equals(&get("e2")) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("no comparing"), "{}", msg),
+        r => panic!("expected a runtime error, got {:?}", r),
+    };
+}
+
+#[test]
+fn test_clone() {
+    let lua = Lua::new();
+    let template: Table = lua
+        .eval(
+            r#"
+                local shared = { 1, 2 }
+                local t = setmetatable({ name = "config", a = shared, b = shared }, {})
+                t.self = t
+                t.nested = { parent = t }
+                return t
+            "#,
+            None,
+        )
+        .unwrap();
+
+    let shallow = template.clone_shallow().unwrap();
+    assert!(!shallow.raw_equal(&template));
+    assert_eq!(shallow.get::<_, String>("name").unwrap(), "config");
+    assert_eq!(
+        shallow.get::<_, Table>("a").unwrap(),
+        template.get::<_, Table>("a").unwrap()
+    );
+    assert_eq!(shallow.get::<_, Table>("self").unwrap(), template);
+    assert_eq!(shallow.get_metatable(), template.get_metatable());
+
+    let deep = template.clone_deep().unwrap();
+    let a: Table = deep.get("a").unwrap();
+    assert_ne!(a, template.get::<_, Table>("a").unwrap());
+    assert_eq!(a, deep.get::<_, Table>("b").unwrap());
+    assert_eq!(deep.get::<_, Table>("self").unwrap(), deep);
+    let nested: Table = deep.get("nested").unwrap();
+    assert_eq!(nested.get::<_, Table>("parent").unwrap(), deep);
+    assert_eq!(deep.get_metatable(), template.get_metatable());
+
+    a.set(1, 10).unwrap();
+    deep.set("name", "copy").unwrap();
+    assert_eq!(template.get::<_, String>("name").unwrap(), "config");
+    assert_eq!(template.get::<_, Table>("a").unwrap().get::<_, i64>(1).unwrap(), 1);
+
+    // Deeply nested tables are copied without recursion.
+    let nested: Table = lua
+        .eval(
+            r#"
+                local t = {}
+                for i = 1, 100000 do
+                    t = { child = t }
+                end
+                return t
+            "#,
+            None,
+        )
+        .unwrap();
+    lua.globals().set("copy", nested.clone_deep().unwrap()).unwrap();
+    lua.globals().set("original", nested).unwrap();
+    assert_eq!(
+        lua.eval::<i64>(
+            r#"
+                local depth = 0
+                local t, u = copy, original
+                while t.child do
+                    assert(t ~= u)
+                    t, u = t.child, u.child
+                    depth = depth + 1
+                end
+                return depth
+            "#,
+            None,
+        )
+        .unwrap(),
+        100000
+    );
+}
+
+#[test]
+fn test_for_each() {
+    let lua = Lua::new();
+    let table: Table = lua.eval("{ 1, 2, 3, a = 4, b = 5, [6.5] = 6 }", None).unwrap();
+
+    let mut pairs = Vec::new();
+    table
+        .for_each(|k: Value, v: i64| {
+            pairs.push((k, v));
+            Ok(())
+        })
+        .unwrap();
+    let expected = table
+        .clone()
+        .pairs::<Value, i64>()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    let rawequal: Function = lua.globals().get("rawequal").unwrap();
+    assert_eq!(pairs.len(), 6);
+    for ((k1, v1), (k2, v2)) in pairs.into_iter().zip(expected) {
+        assert!(rawequal.call::<_, bool>((k1, k2)).unwrap());
+        assert_eq!(v1, v2);
+    }
+
+    // Clearing fields while traversing is allowed.
+    table.for_each(|k: Value, _: Value| table.set(k, Nil)).unwrap();
+    assert_eq!(table.clone().pairs::<Value, Value>().count(), 0);
+
+    // The first error stops the traversal.
+    let table: Table = lua.eval("{ 1, 2, 'three', 4 }", None).unwrap();
+    let mut visited = 0;
+    let res = table.for_each(|_: i64, v: i64| {
+        visited += 1;
+        if v == 2 {
+            Err(Error::RuntimeError("stop".to_owned()))
+        } else {
+            Ok(())
+        }
+    });
+    match res {
+        Err(Error::RuntimeError(ref msg)) if msg == "stop" => {}
+        r => panic!("expected the error returned by the callback, got {:?}", r),
+    }
+    assert_eq!(visited, 2);
+    match table.for_each(|_: i64, _: i64| Ok(())) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected a conversion error, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_pairs_sorted() {
+    let lua = Lua::new();
+    let table: Table = lua
+        .eval("{ pear = 2, apple = 1, zucchini = 4, plum = 3 }", None)
+        .unwrap();
+    let pairs = table.pairs_sorted::<String, i64>().unwrap();
+    assert_eq!(
+        pairs,
+        vec![
+            ("apple".to_owned(), 1),
+            ("pear".to_owned(), 2),
+            ("plum".to_owned(), 3),
+            ("zucchini".to_owned(), 4),
+        ]
+    );
+
+    let table: Table = lua.eval("{ [10] = 'c', [-1] = 'a', [3] = 'b' }", None).unwrap();
+    let keys: Vec<i64> = table
+        .pairs_sorted::<i64, String>()
+        .unwrap()
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(keys, vec![-1, 3, 10]);
+
+    // Integer keys are converted to strings, and compared as such.
+    let table: Table = lua.eval("{ 'x', [10] = 'y', b = 'z' }", None).unwrap();
+    let keys: Vec<String> = table
+        .pairs_sorted::<String, String>()
+        .unwrap()
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(keys, vec!["1", "10", "b"]);
+
+    let table: Table = lua.eval("{ 1, a = 2 }", None).unwrap();
+    assert!(table.pairs_sorted::<i64, i64>().is_err());
+}
+
+#[test]
+fn test_weak_table() {
+    let lua = Lua::new();
+
+    let weak_values = lua.create_weak_table(WeakMode::Values).unwrap();
+    let kept = lua.create_table().unwrap();
+    weak_values.set("kept", kept.clone()).unwrap();
+    weak_values.set("dropped", lua.create_table().unwrap()).unwrap();
+    weak_values.set("string", "never collected").unwrap();
+
+    let weak_keys = lua.create_weak_table(WeakMode::Keys).unwrap();
+    weak_keys.set(kept.clone(), 1).unwrap();
+    weak_keys.set(lua.create_table().unwrap(), 2).unwrap();
+
+    let weak_both = lua.create_weak_table(WeakMode::Both).unwrap();
+    weak_both.set(1, lua.create_table().unwrap()).unwrap();
+    weak_both.set(lua.create_table().unwrap(), 2).unwrap();
+    assert_eq!(
+        weak_both
+            .get_metatable()
+            .unwrap()
+            .get::<_, String>("__mode")
+            .unwrap(),
+        "kv"
+    );
+
+    lua.gc_collect().unwrap();
+    lua.gc_collect().unwrap();
+
+    assert_eq!(weak_values.get::<_, Table>("kept").unwrap(), kept);
+    assert!(weak_values.get::<_, Option<Table>>("dropped").unwrap().is_none());
+    assert_eq!(
+        weak_values.get::<_, String>("string").unwrap(),
+        "never collected"
+    );
+    assert_eq!(weak_keys.clone().pairs::<Table, i64>().count(), 1);
+    assert_eq!(weak_keys.get::<_, i64>(kept).unwrap(), 1);
+    assert_eq!(weak_both.pairs::<Value, Value>().count(), 0);
+}
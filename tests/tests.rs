@@ -1,21 +1,189 @@
 extern crate failure;
 extern crate rlua;
 
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read};
 use std::iter::FromIterator;
-use std::panic::catch_unwind;
-use std::{error, fmt};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{cmp, error, fmt};
 
 use failure::err_msg;
-use rlua::{Error, ExternalError, Function, Lua, Nil, Result, String, Table, Value, Variadic};
+use rlua::{
+    ChunkMode, Error, ExternalError, Function, HookEvent, HookMask, Limits, Lua, MultiValue, Nil,
+    PanicPolicy, ReplResult, Result, StdLib, String, Table, Value, Variadic,
+};
 
 #[test]
 fn test_load() {
     let lua = Lua::new();
-    let func = lua.load("return 1+2", None).unwrap();
+    let func = lua.load(b"return 1+2", None, ChunkMode::Text).unwrap();
     let result: i32 = func.call(()).unwrap();
     assert_eq!(result, 3);
 
-    assert!(lua.load("§$%§&$%&", None).is_err());
+    assert!(lua.load("§$%§&$%&".as_bytes(), None, ChunkMode::Text).is_err());
+
+    let bytecode = lua
+        .eval::<String>("string.dump(function() return 42 end)", None)
+        .unwrap();
+    let bytecode = bytecode.as_bytes();
+    match lua.load(bytecode, None, ChunkMode::Text) {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("binary chunk was not rejected, got {:?}", r),
+    }
+    match lua.load(b"return 42", None, ChunkMode::Binary) {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("text chunk was not rejected, got {:?}", r),
+    }
+    for &source in &[&b"return 42"[..], bytecode] {
+        let func = lua.load(source, None, ChunkMode::Both).unwrap();
+        assert_eq!(func.call::<_, i32>(()).unwrap(), 42);
+    }
+
+    for &(name, prefix) in &[
+        ("chunk", "chunk:1:"),
+        ("=chunk", "chunk:1:"),
+        ("@chunk.lua", "chunk.lua:1:"),
+    ] {
+        let func = lua
+            .load(b"error('boom')", Some(name), ChunkMode::Text)
+            .unwrap();
+        match func.call::<_, ()>(()) {
+            Err(Error::RuntimeError(msg)) => assert!(msg.starts_with(prefix), "{}", msg),
+            r => panic!("expected RuntimeError, got {:?}", r),
+        }
+    }
+    assert!(lua.load(b"", Some("a\0b"), ChunkMode::Text).is_err());
+}
+
+#[test]
+fn test_load_bytecode() {
+    let lua = Lua::new();
+    let bytecode = lua
+        .eval::<String>("string.dump(function(a) return a * 2 end)", None)
+        .unwrap();
+    let bytecode = bytecode.as_bytes();
+
+    let func = lua.load_bytecode(bytecode, Some("chunk")).unwrap();
+    assert_eq!(func.call::<_, i32>(21).unwrap(), 42);
+
+    match lua.load_bytecode(b"return 1 + 2", None) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("text chunk was not rejected, got {:?}", r),
+    }
+    match lua.load_bytecode(b"", None) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("empty chunk was not rejected, got {:?}", r),
+    }
+    assert!(lua.load_bytecode(b"\x1bLu", None).is_err());
+    assert!(lua.load_bytecode(&bytecode[..8], None).is_err());
+
+    // Flip the endianness flag in the header
+    let mut swapped = bytecode.to_vec();
+    swapped[6] ^= 1;
+    assert!(lua.load_bytecode(&swapped, None).is_err());
+
+    // Chunk names are formatted like those of `load`.
+    let truncated = &bytecode[..bytecode.len() / 2];
+    let expected = match lua.load(truncated, Some("chunk"), ChunkMode::Binary) {
+        Err(err) => err.to_string(),
+        Ok(_) => panic!("truncated chunk was loaded"),
+    };
+    match lua.load_bytecode(truncated, Some("chunk")) {
+        Err(err) => {
+            assert!(err.to_string().contains("chunk:"));
+            assert_eq!(err.to_string(), expected);
+        }
+        Ok(_) => panic!("truncated chunk was loaded"),
+    };
+}
+
+#[test]
+fn test_load_from_reader() {
+    // Hands out the source a few bytes at a time and then fails if `fail` is set.
+    struct ChunkedReader {
+        source: Vec<u8>,
+        pos: usize,
+        fail: bool,
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = cmp::min(cmp::min(buf.len(), 3), self.source.len() - self.pos);
+            if n == 0 && self.fail {
+                return Err(io::Error::new(io::ErrorKind::Other, "read failed"));
+            }
+            buf[..n].copy_from_slice(&self.source[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    let lua = Lua::new();
+
+    let reader = ChunkedReader {
+        source: b"local a = 20\nreturn a + 22".to_vec(),
+        pos: 0,
+        fail: false,
+    };
+    let func = lua
+        .load_from_reader(reader, Some("chunk"), ChunkMode::Text)
+        .unwrap();
+    assert_eq!(func.call::<_, i32>(()).unwrap(), 42);
+
+    // Larger than the internal buffer
+    let source = format!("return '{}'", "x".repeat(20000));
+    let func = lua
+        .load_from_reader(source.as_bytes(), None, ChunkMode::Text)
+        .unwrap();
+    assert_eq!(func.call::<_, String>(()).unwrap().as_bytes().len(), 20000);
+
+    let bytecode = lua
+        .eval::<String>("string.dump(function() return 7 end)", None)
+        .unwrap();
+    let func = lua
+        .load_from_reader(bytecode.as_bytes(), None, ChunkMode::Binary)
+        .unwrap();
+    assert_eq!(func.call::<_, i32>(()).unwrap(), 7);
+    let func = lua
+        .load_from_reader(bytecode.as_bytes(), None, ChunkMode::Both)
+        .unwrap();
+    assert_eq!(func.call::<_, i32>(()).unwrap(), 7);
+
+    // Bytecode is only accepted when the mode allows it, and the other way around.
+    match lua.load_from_reader(bytecode.as_bytes(), None, ChunkMode::Text) {
+        Err(Error::SyntaxError { ref message, .. }) => {
+            assert_eq!(message, "attempt to load a binary chunk (mode is 't')")
+        }
+        r => panic!("syntax error expected, got {:?}", r),
+    }
+    for source in &[&b"return 1"[..], &b""[..]] {
+        match lua.load_from_reader(*source, None, ChunkMode::Binary) {
+            Err(Error::SyntaxError { ref message, .. }) => {
+                assert_eq!(message, "attempt to load a text chunk (mode is 'b')")
+            }
+            r => panic!("syntax error expected, got {:?}", r),
+        }
+    }
+
+    match lua.load_from_reader(&b"return +"[..], Some("chunk"), ChunkMode::Text) {
+        Err(Error::SyntaxError { ref message, .. }) => assert!(message.starts_with("chunk:1:")),
+        r => panic!("syntax error expected, got {:?}", r),
+    }
+
+    let reader = ChunkedReader {
+        source: b"return 1".to_vec(),
+        pos: 0,
+        fail: true,
+    };
+    match lua.load_from_reader(reader, None, ChunkMode::Text) {
+        Err(Error::ExternalError(ref err)) => assert_eq!(err.to_string(), "read failed"),
+        r => panic!("read error expected, got {:?}", r),
+    }
+    assert_eq!(lua.eval::<i32>("return 1", None).unwrap(), 1);
 }
 
 #[test]
@@ -68,6 +236,256 @@ fn test_exec() {
     );
 }
 
+#[test]
+fn test_exec_all() {
+    let lua = Lua::new();
+    lua.exec_all(vec![
+        ("first.lua".to_owned(), "x = 1".to_owned()),
+        ("second.lua".to_owned(), "y = x + 1".to_owned()),
+    ]).unwrap();
+    assert_eq!(lua.globals().get::<_, i64>("y").unwrap(), 2);
+
+    match lua.exec_all(vec![("good", "z = 1"), ("bad", "z = 2 +"), ("never", "z = 3")]) {
+        Err(Error::ChunkError { ref name, ref cause }) => {
+            assert_eq!(name, "bad");
+            match **cause {
+                Error::SyntaxError { .. } => {}
+                ref err => panic!("expected SyntaxError, got {:?}", err),
+            }
+        }
+        r => panic!("expected ChunkError, got {:?}", r),
+    }
+    assert_eq!(lua.globals().get::<_, i64>("z").unwrap(), 1);
+
+    // Side effects of a failing chunk before its error are kept.
+    let err = lua
+        .exec_all(vec![("partial", "w = 1; error('boom'); w = 2")])
+        .unwrap_err();
+    assert!(err.to_string().starts_with("error in chunk 'partial': runtime error: "));
+    assert!(err.to_string().contains("boom"));
+    assert!(err.traceback().is_some());
+    assert_eq!(lua.globals().get::<_, i64>("w").unwrap(), 1);
+
+    lua.exec_all(Vec::<(&str, &str)>::new()).unwrap();
+}
+
+#[test]
+fn test_exec_file() {
+    let dir = std::env::temp_dir().join(format!("rlua_test_exec_file_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let good = dir.join("good.lua");
+    let bad = dir.join("bad.lua");
+    std::fs::write(&good, "answer = 42").unwrap();
+    std::fs::write(&bad, "local x = 1\nerror('boom')").unwrap();
+
+    let lua = Lua::new();
+    lua.exec_file(&good).unwrap();
+    assert_eq!(lua.globals().get::<_, i64>("answer").unwrap(), 42);
+
+    match lua.exec_file(&bad) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.starts_with("bad.lua:2: boom"), "{}", msg);
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    let compiled = dir.join("compiled.luac");
+    let bytecode = lua
+        .eval::<String>("string.dump(function() answer = 0 end)", None)
+        .unwrap();
+    std::fs::write(&compiled, bytecode.as_bytes()).unwrap();
+    match lua.exec_file(&compiled) {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {:?}", r),
+    }
+    assert_eq!(lua.globals().get::<_, i64>("answer").unwrap(), 42);
+
+    match lua.exec_file(dir.join("missing.lua")) {
+        Err(Error::ExternalError(ref err)) => {
+            assert_eq!(
+                err.downcast_ref::<io::Error>().unwrap().kind(),
+                io::ErrorKind::NotFound
+            );
+        }
+        r => panic!("expected ExternalError, got {:?}", r),
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_globals_snapshot() {
+    let lua = Lua::new();
+    lua.exec::<()>("config = { debug = false }; level = 1", None).unwrap();
+    let config: Table = lua.globals().get("config").unwrap();
+    let snapshot = lua.snapshot_globals().unwrap();
+
+    for _ in 0..2 {
+        lua.exec::<()>(
+            r#"
+                level = 2
+                added = true
+                print = nil
+                config.debug = true
+            "#,
+            None,
+        ).unwrap();
+        lua.restore_globals(&snapshot).unwrap();
+
+        let globals = lua.globals();
+        assert_eq!(globals.get::<_, i64>("level").unwrap(), 1);
+        assert_eq!(globals.get::<_, Option<bool>>("added").unwrap(), None);
+        assert!(globals.get::<_, Function>("print").is_ok());
+        assert_eq!(globals.get::<_, Table>("config").unwrap(), config);
+        // The snapshot is shallow, so changes inside of global tables are kept.
+        assert_eq!(config.get::<_, bool>("debug").unwrap(), true);
+        config.set("debug", false).unwrap();
+    }
+
+    let other = Lua::new();
+    match other.restore_globals(&snapshot) {
+        Err(Error::MismatchedRegistryKey) => {}
+        r => panic!("expected MismatchedRegistryKey, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_run_untrusted() {
+    let (n, s) = Lua::run_untrusted::<(i64, ::std::string::String)>(
+        "return 6 * 7, string.rep('a', 2)",
+        Limits::default(),
+    ).unwrap();
+    assert_eq!((n, s.as_str()), (42, "aa"));
+    assert_eq!(
+        Lua::run_untrusted::<bool>(
+            "return io == nil and os == nil and dofile == nil and loadstring == nil",
+            Limits::default()
+        ).unwrap(),
+        true
+    );
+    match Lua::run_untrusted::<()>("\x1bLua", Limits::default()) {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {:?}", r),
+    }
+
+    let instructions = Limits {
+        max_instructions: Some(10_000),
+        ..Limits::default()
+    };
+    let nested = r#"
+        while true do
+            pcall(function()
+                while true do pcall(error, "caught") end
+            end)
+        end
+    "#;
+    match Lua::run_untrusted::<()>(nested, instructions.clone()) {
+        Err(Error::Timeout) => {}
+        r => panic!("expected Timeout, got {:?}", r),
+    }
+    match Lua::run_untrusted::<()>("while true do end", instructions.clone()) {
+        Err(Error::Timeout) => {}
+        r => panic!("expected Timeout, got {:?}", r),
+    }
+
+    let timeout = Limits {
+        timeout: Some(Duration::from_millis(50)),
+        ..Limits::default()
+    };
+    let start = SystemTime::now();
+    match Lua::run_untrusted::<()>(nested, timeout) {
+        Err(Error::Timeout) => {}
+        r => panic!("expected Timeout, got {:?}", r),
+    }
+    assert!(start.elapsed().unwrap() < Duration::from_secs(10));
+
+    let memory = Limits {
+        max_memory: Some(1024 * 1024),
+        ..instructions
+    };
+    match Lua::run_untrusted::<()>("local s = string.rep('x', 4 * 1024 * 1024)", memory) {
+        Err(Error::MemoryError(_)) => {}
+        r => panic!("expected MemoryError, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_interrupt() {
+    let lua = Lua::new();
+    let handle = lua.interrupt_handle();
+    assert!(!handle.is_cancelled());
+
+    // The interrupt reaches Rust through a nested callback and cannot be caught by `pcall`.
+    let run = lua
+        .create_function(|lua, ()| {
+            lua.exec::<()>(
+                r#"
+                while true do
+                    pcall(function()
+                        while true do end
+                    end)
+                end
+                "#,
+                None,
+            )
+        }).unwrap();
+    lua.globals().set("run", run).unwrap();
+
+    let canceller = handle.clone();
+    let cancel = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        canceller.cancel();
+    });
+    match lua.exec::<()>("run()", None) {
+        Err(Error::Interrupted) => {}
+        r => panic!("expected Interrupted, got {:?}", r),
+    }
+    cancel.join().unwrap();
+    assert!(handle.is_cancelled());
+
+    match lua.exec::<()>("local x = 0 for i = 1, 10000 do x = x + i end", None) {
+        Err(Error::Interrupted) => {}
+        r => panic!("expected Interrupted, got {:?}", r),
+    }
+    handle.reset();
+    assert_eq!(lua.eval::<i64>("1 + 1", None).unwrap(), 2);
+
+    // The interrupt is still checked once a hook has been set and removed.
+    lua.set_hook_count(100, |_| Ok(()));
+    lua.remove_hook();
+    lua.interrupt_handle().cancel();
+    match lua.exec::<()>("while true do end", None) {
+        Err(Error::Interrupted) => {}
+        r => panic!("expected Interrupted, got {:?}", r),
+    }
+
+    // A hook without a count does not replace the interrupt check, and is not called for the
+    // count events of the check.
+    handle.reset();
+    lua.set_hook(HookMask::CALL, |_, info| {
+        assert_eq!(info.event, HookEvent::Call);
+        Ok(())
+    });
+    let canceller = handle.clone();
+    let cancel = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        canceller.cancel();
+    });
+    match lua.exec::<()>("while true do end", None) {
+        Err(Error::Interrupted) => {}
+        r => panic!("expected Interrupted, got {:?}", r),
+    }
+    cancel.join().unwrap();
+    lua.remove_hook();
+
+    // `Function::call_catch` does not catch the interrupt either.
+    let spin: Function = lua.eval("function() while true do end end", None).unwrap();
+    match spin.call_catch::<_, (), Value>(()) {
+        Err(Error::Interrupted) => {}
+        r => panic!("expected Interrupted, got {:?}", r),
+    };
+}
+
 #[test]
 fn test_eval() {
     let lua = Lua::new();
@@ -84,6 +502,276 @@ fn test_eval() {
             r
         ),
     }
+    match lua.eval::<()>("local x = 1\n\nx = = 2", None) {
+        Err(Error::SyntaxError {
+            line: Some(3),
+            incomplete_input: false,
+            ..
+        }) => {}
+        r => panic!("expected SyntaxError on line 3, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_eval_multi() {
+    let lua = Lua::new();
+
+    let values = lua.eval_multi("return 1, 'two', nil, 4", None).unwrap();
+    assert_eq!(values.len(), 4);
+    let (a, b, c, d): (i64, String, Option<i64>, i64) = lua.unpack_multi(values).unwrap();
+    assert_eq!((a, b.to_str().unwrap(), c, d), (1, "two", None, 4));
+
+    assert_eq!(lua.eval_multi("1, 2, 3", None).unwrap().len(), 3);
+    assert!(lua.eval_multi("local x = 1", None).unwrap().is_empty());
+    let values = lua.eval_multi("return ...", None).unwrap();
+    assert!(values.is_empty());
+
+    // A tuple target only takes as many values as it has elements.
+    let (a, b): (i64, i64) = lua.eval("return 1, 2, 3", None).unwrap();
+    assert_eq!((a, b), (1, 2));
+}
+
+#[test]
+fn test_compiled_cache() {
+    let lua = Lua::new();
+    lua.gc_stop();
+
+    let source = "local t = {} for i = 1, 10 do t[i] = i end return #t";
+    let allocated = |source: &str| {
+        let before = lua.gc_count();
+        assert_eq!(lua.exec::<i64>(source, None).unwrap(), 10);
+        lua.gc_count() - before
+    };
+
+    // Reusing a cached chunk only allocates what running the chunk allocates.
+    lua.set_compiled_cache_size(1);
+    let compiled = allocated(source);
+    let cached = allocated(source);
+    assert!(cached < compiled);
+
+    // Once evicted, the chunk has to be compiled again.
+    allocated("return 10");
+    assert!(allocated(source) > cached);
+    assert_eq!(allocated(source), cached);
+
+    lua.set_compiled_cache_size(0);
+    assert!(allocated(source) > cached);
+    lua.gc_restart();
+
+    // `exec` and `eval` of the same source are cached separately.
+    lua.set_compiled_cache_size(4);
+    assert_eq!(lua.eval::<i64>("1 + 1", None).unwrap(), 2);
+    assert_eq!(lua.eval::<i64>("1 + 1", None).unwrap(), 2);
+    assert!(lua.exec::<()>("1 + 1", None).is_err());
+
+    // Every run of a cached chunk creates fresh locals and closures.
+    let source = "local n = 0 return function() n = n + 1 return n end";
+    let first = lua.exec::<Function>(source, None).unwrap();
+    let second = lua.exec::<Function>(source, None).unwrap();
+    assert_eq!(first.call::<_, i64>(()).unwrap(), 1);
+    assert_eq!(first.call::<_, i64>(()).unwrap(), 2);
+    assert_eq!(second.call::<_, i64>(()).unwrap(), 1);
+
+    // Every run of a cached chunk starts with the globals as its environment.
+    let source = "local env = getfenv(1) setfenv(1, { x = 1 }) return env";
+    let globals = lua.globals();
+    assert_eq!(lua.exec::<Table>(source, None).unwrap(), globals);
+    assert_eq!(lua.exec::<Table>(source, None).unwrap(), globals);
+}
+
+#[test]
+fn test_reference_identity() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    lua.exec::<()>(
+        r#"
+            a = {}
+            b = setmetatable({}, { __eq = function() return true end })
+            f = function() end
+        "#,
+        None,
+    ).unwrap();
+
+    let a1: Table = globals.get("a").unwrap();
+    let a2: Table = globals.get("a").unwrap();
+    let b: Table = globals.get("b").unwrap();
+    assert_eq!(a1, a2);
+    assert_eq!(a1, a1.clone());
+    // Identity ignores `__eq`.
+    assert!(a1 != b);
+    assert!(globals.get::<_, Table>("_G").unwrap() == globals);
+
+    let mut names = HashMap::new();
+    names.insert(a1, "a");
+    names.insert(b.clone(), "b");
+    assert_eq!(names[&a2], "a");
+    assert_eq!(names[&b], "b");
+    assert_eq!(names.len(), 2);
+
+    let f1: Function = globals.get("f").unwrap();
+    let f2: Function = globals.get("f").unwrap();
+    let print: Function = globals.get("print").unwrap();
+    assert_eq!(f1, f2);
+    assert!(f1 != print);
+    let mut functions = HashSet::new();
+    functions.insert(f1);
+    assert!(functions.contains(&f2));
+    assert!(!functions.contains(&print));
+
+    let s1 = lua.create_string("key").unwrap();
+    let s2: String = lua.eval("'k' .. 'ey'", None).unwrap();
+    let mut strings = HashSet::new();
+    strings.insert(s1);
+    assert!(strings.contains(&s2));
+
+    // Handles from different states are never equal.
+    let other = Lua::new();
+    assert!(other.globals() != globals);
+}
+
+#[test]
+fn test_debug_info() {
+    let lua = Lua::new();
+    assert!(lua.debug_info(0).is_none());
+
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let recorded = frames.clone();
+    let record = lua
+        .create_function(move |lua, ()| {
+            let mut frames = recorded.lock().unwrap();
+            let mut level = 0;
+            while let Some(info) = lua.debug_info(level) {
+                frames.push(info);
+                level += 1;
+            }
+            Ok(())
+        }).unwrap();
+    lua.globals().set("record", record).unwrap();
+    lua.exec::<()>(
+        r#"
+            function inner()
+                record()
+            end
+            inner()
+        "#,
+        Some("@debug.lua"),
+    ).unwrap();
+
+    let frames = frames.lock().unwrap();
+    assert_eq!(frames.len(), 3);
+
+    assert_eq!(frames[0].what, "C");
+    assert_eq!(frames[0].current_line, None);
+    assert_eq!(frames[0].name.as_ref().map(|s| s.as_str()), Some("record"));
+    assert_eq!(frames[0].name_what.as_ref().map(|s| s.as_str()), Some("global"));
+
+    assert_eq!(frames[1].what, "Lua");
+    assert_eq!(frames[1].source, "@debug.lua");
+    assert_eq!(frames[1].short_source, "debug.lua");
+    assert_eq!(frames[1].current_line, Some(3));
+    assert_eq!(frames[1].name.as_ref().map(|s| s.as_str()), Some("inner"));
+
+    assert_eq!(frames[2].what, "main");
+    assert_eq!(frames[2].current_line, Some(5));
+    assert_eq!(frames[2].name, None);
+    assert_eq!(frames[2].name_what, None);
+}
+
+#[test]
+fn test_multi_value() {
+    let lua = Lua::new();
+    let f: Function = lua.eval("function(...) return ... end", None).unwrap();
+
+    let values: MultiValue = f.call((1, "two", 3.5)).unwrap();
+    assert_eq!(values.len(), 3);
+    assert!(!values.is_empty());
+    match (values.get(0), values.get(1), values.get(2), values.get(3)) {
+        (Some(&Value::Integer(1)), Some(&Value::String(ref s)), Some(&Value::Number(n)), None) => {
+            assert_eq!(*s, "two");
+            assert_eq!(n, 3.5);
+        }
+        r => panic!("unexpected values {:?}", r),
+    }
+
+    let vec = values.into_vec();
+    match vec[0] {
+        Value::Integer(1) => {}
+        ref v => panic!("unexpected first value {:?}", v),
+    }
+    let values: MultiValue = f.call(MultiValue::from_vec(vec)).unwrap();
+    let (a, b, c): (i64, String, f64) = lua.unpack_multi(values).unwrap();
+    assert_eq!((a, c), (1, 3.5));
+    assert_eq!(b, "two");
+
+    let values: MultiValue = f.call(()).unwrap();
+    assert!(values.is_empty());
+    assert!(values.get(0).is_none());
+}
+
+#[test]
+fn test_pack_multi() {
+    let lua = Lua::new();
+
+    let values = lua.pack_multi((1, "two", Nil)).unwrap();
+    assert_eq!(values.len(), 3);
+    let sum: Function = lua
+        .eval("function(a, b, c) return a + #b + (c or 0) end", None)
+        .unwrap();
+    assert_eq!(sum.call::<_, i64>(values.clone()).unwrap(), 4);
+
+    let (a, b, c): (i64, String, Option<i64>) = lua.unpack_multi(values.clone()).unwrap();
+    assert_eq!((a, c), (1, None));
+    assert_eq!(b, "two");
+    let a: i64 = lua.unpack_multi(values.clone()).unwrap();
+    assert_eq!(a, 1);
+
+    match lua.unpack_multi::<(i64, String, i64, i64)>(values) {
+        Err(Error::BadArgument { position: 3, .. }) => {}
+        r => panic!("expected a bad argument #3 error, got {:?}", r),
+    };
+}
+
+#[test]
+fn test_eval_repl() {
+    let lua = Lua::new();
+
+    match lua.eval_repl("1 + 1, 'two'") {
+        ReplResult::Values(values) => {
+            let (a, b): (i64, String) = lua.unpack_multi(values).unwrap();
+            assert_eq!(a, 2);
+            assert_eq!(b, "two");
+        }
+        r => panic!("expected values, got {:?}", r),
+    }
+
+    match lua.eval_repl("x = 5") {
+        ReplResult::Values(values) => assert!(values.is_empty()),
+        r => panic!("expected no values, got {:?}", r),
+    }
+    assert_eq!(lua.globals().get::<_, i64>("x").unwrap(), 5);
+
+    let mut line = "for i = 1, 3 do".to_owned();
+    match lua.eval_repl(&line) {
+        ReplResult::Incomplete => {}
+        r => panic!("expected incomplete input, got {:?}", r),
+    }
+    line.push_str("\nx = x + i end");
+    match lua.eval_repl(&line) {
+        ReplResult::Values(_) => {}
+        r => panic!("expected values, got {:?}", r),
+    }
+    assert_eq!(lua.globals().get::<_, i64>("x").unwrap(), 11);
+
+    match lua.eval_repl("x = = 1") {
+        ReplResult::Error(Error::SyntaxError { message, .. }) => {
+            assert!(message.starts_with("stdin:1:"), "{}", message)
+        }
+        r => panic!("expected a syntax error, got {:?}", r),
+    }
+    match lua.eval_repl("error('failed')") {
+        ReplResult::Error(Error::RuntimeError(_)) => {}
+        r => panic!("expected a runtime error, got {:?}", r),
+    };
 }
 
 #[test]
@@ -114,6 +802,69 @@ fn test_lua_multi() {
     assert_eq!(v[..], [3, 4, 5, 6]);
 }
 
+#[test]
+fn test_variadic() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    let format = lua
+        .create_function(|_, (prefix, rest): (String, Variadic<i64>)| {
+            let rest: Vec<_> = rest.iter().map(|i| i.to_string()).collect();
+            Ok(format!("{}{}", prefix.to_str()?, rest.join(",")))
+        }).unwrap();
+    globals.set("format", format).unwrap();
+    let double = lua
+        .create_function(|_, args: Variadic<i64>| {
+            Ok(args.iter().map(|i| i * 2).collect::<Variadic<_>>())
+        }).unwrap();
+    globals.set("double", double).unwrap();
+
+    assert_eq!(lua.eval::<String>("format('x')", None).unwrap(), "x");
+    assert_eq!(
+        lua.eval::<String>("format('x', 1, 2, 3)", None).unwrap(),
+        "x1,2,3"
+    );
+    assert_eq!(lua.eval::<i64>("select('#', double())", None).unwrap(), 0);
+    assert_eq!(
+        lua.eval::<i64>("select('#', double(1, 2, 3))", None).unwrap(),
+        3
+    );
+    assert_eq!(lua.eval::<i64>("select(3, double(1, 2, 3))", None).unwrap(), 6);
+
+    match lua.exec::<()>("format('x', 1, 2, {})", None) {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::BadArgument { position: 4, .. } => {}
+            ref other => panic!("wrong error: {:?}", other),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+    match lua.exec::<()>("format({})", None) {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::BadArgument { position: 1, .. } => {}
+            ref other => panic!("wrong error: {:?}", other),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    // A single argument that is not a tuple reports the conversion error as is.
+    let single = lua.create_function(|_, n: i64| Ok(n)).unwrap();
+    let tuple = lua.create_function(|_, (n,): (i64,)| Ok(n)).unwrap();
+    match single.call::<_, i64>("x") {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::FromLuaConversionError { .. } => {}
+            ref other => panic!("wrong error: {:?}", other),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+    match tuple.call::<_, i64>("x") {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::BadArgument { position: 1, .. } => {}
+            ref other => panic!("wrong error: {:?}", other),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    };
+}
+
 #[test]
 fn test_coercion() {
     let lua = Lua::new();
@@ -316,6 +1067,122 @@ fn test_error() {
     };
 }
 
+#[test]
+fn test_callback_panic() {
+    let lua = Lua::new();
+    let rust_panic = lua
+        .create_function(|_, ()| -> Result<()> { panic!("test_panic") })
+        .unwrap();
+    lua.globals().set("rust_panic", rust_panic).unwrap();
+
+    for _ in 0..3 {
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            lua.exec::<()>("local t = {} pcall(rust_panic)", None)
+        }));
+        match res {
+            Ok(r) => panic!("panic was not propagated, got {:?}", r),
+            Err(p) => assert_eq!(*p.downcast::<&str>().unwrap(), "test_panic"),
+        }
+        // The state stays usable after the panic has unwound out of it.
+        assert_eq!(lua.eval::<i64>("1 + 1", None).unwrap(), 2);
+        assert_eq!(lua.globals().get::<_, i64>("unset").ok(), None);
+    }
+
+    // A panic caught by Lua through `coroutine.resume` shows its message, and once resumed it can
+    // no longer be resumed again.
+    lua.exec::<()>(
+        r#"
+            local ok, err = coroutine.resume(coroutine.create(function() rust_panic() end))
+            assert(not ok)
+            assert(tostring(err) == "panic in Rust callback: test_panic")
+            caught = err
+        "#,
+        None,
+    ).unwrap();
+    assert!(catch_unwind(AssertUnwindSafe(|| lua.exec::<()>("error(caught)", None))).is_err());
+    match lua.exec::<()>("error(caught)", None) {
+        Err(Error::CallbackPanic(ref msg)) => assert_eq!(msg, "test_panic"),
+        r => panic!("expected a callback panic error, got {:?}", r),
+    };
+    assert_eq!(
+        Error::CallbackPanic("caught".to_owned()).to_string(),
+        "panic in Rust callback: caught"
+    );
+}
+
+#[test]
+fn test_panic_policy() {
+    let lua = Lua::new();
+    let rust_panic = lua
+        .create_function(|_, ()| -> Result<()> { panic!("test_panic") })
+        .unwrap();
+    lua.globals().set("rust_panic", rust_panic).unwrap();
+
+    lua.set_panic_handler(PanicPolicy::Convert);
+    match lua.exec::<()>("pcall(rust_panic)", None) {
+        Err(Error::CallbackPanic(ref msg)) => assert_eq!(msg, "test_panic"),
+        r => panic!("expected a callback panic error, got {:?}", r),
+    };
+    let thread = lua.create_thread(lua.globals().get("rust_panic").unwrap()).unwrap();
+    match thread.resume::<_, ()>(()) {
+        Err(Error::CallbackPanic(ref msg)) => assert_eq!(msg, "test_panic"),
+        r => panic!("expected a callback panic error, got {:?}", r),
+    };
+    assert_eq!(lua.eval::<i64>("1 + 1", None).unwrap(), 2);
+
+    lua.set_panic_handler(PanicPolicy::Resume);
+    assert!(catch_unwind(AssertUnwindSafe(|| lua.exec::<()>("rust_panic()", None))).is_err());
+}
+
+#[test]
+fn test_error_traceback() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals
+        .set(
+            "rust_error",
+            lua.create_function(|_, ()| -> Result<()> { Err(Error::BindError) })
+                .unwrap(),
+        )
+        .unwrap();
+    lua.exec::<()>(
+        r#"
+            function inner_lua_error() error("lua error") end
+            function outer_lua_error() inner_lua_error() end
+            function outer_rust_error() rust_error() end
+        "#,
+        None,
+    ).unwrap();
+
+    let err = globals
+        .get::<_, Function>("outer_lua_error")
+        .unwrap()
+        .call::<_, ()>(())
+        .unwrap_err();
+    let traceback = err.traceback().unwrap();
+    assert!(traceback.starts_with("stack traceback:"));
+    // Functions called directly from Rust are listed by their location rather than their name.
+    assert!(traceback.contains("in function 'inner_lua_error'"));
+    assert!(traceback.contains("]:3: in function"));
+
+    let err = globals
+        .get::<_, Function>("outer_rust_error")
+        .unwrap()
+        .call::<_, ()>(())
+        .unwrap_err();
+    match err {
+        Error::CallbackError { ref cause, .. } => match **cause {
+            Error::BindError => {}
+            ref err => panic!("wrong callback error cause {:?}", err),
+        },
+        ref err => panic!("expected CallbackError, got {:?}", err),
+    }
+    assert!(err.traceback().unwrap().contains("]:4: in function"));
+
+    assert!(Error::BindError.traceback().is_none());
+    assert!(Error::RuntimeError("no traceback".to_owned()).traceback().is_none());
+}
+
 #[test]
 fn test_result_conversions() {
     let lua = Lua::new();
@@ -331,45 +1198,451 @@ fn test_result_conversions() {
         .create_function(|_, ()| Ok(Ok::<_, Error>("!".to_owned())))
         .unwrap();
 
-    globals.set("err", err).unwrap();
-    globals.set("ok", ok).unwrap();
+    globals.set("err", err).unwrap();
+    globals.set("ok", ok).unwrap();
+
+    lua.exec::<()>(
+        r#"
+            local r, e = err()
+            assert(r == nil)
+            assert(tostring(e) == "only through failure can we succeed")
+
+            local r, e = ok()
+            assert(r == "!")
+            assert(e == nil)
+        "#,
+        None,
+    ).unwrap();
+}
+
+#[test]
+fn test_io_error_conversion() {
+    fn open_script<'lua>(lua: &'lua Lua, path: &str) -> Result<Function<'lua>> {
+        let file = std::fs::File::open(path)?;
+        lua.load_from_reader(file, Some(path), ChunkMode::Text)
+    }
+
+    let lua = Lua::new();
+    let err = open_script(&lua, "/nonexistent/script.lua").unwrap_err();
+    let io_err = std::fs::File::open("/nonexistent/script.lua").unwrap_err();
+    assert_eq!(err.to_string(), io_err.to_string());
+    match err {
+        Error::ExternalError(ref err) => {
+            assert_eq!(
+                err.downcast_ref::<io::Error>().unwrap().kind(),
+                io::ErrorKind::NotFound
+            );
+        }
+        ref err => panic!("expected ExternalError, got {:?}", err),
+    }
+
+    let read_error = lua
+        .create_function(|_, ()| -> Result<()> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk on fire"))?
+        }).unwrap();
+    lua.globals().set("read_error", read_error).unwrap();
+    lua.exec::<()>(
+        r#"
+            local ok, err = pcall(read_error)
+            assert(not ok)
+            assert(string.find(tostring(err), "disk on fire"))
+        "#,
+        None,
+    ).unwrap();
+}
+
+#[test]
+fn test_error_downcast() {
+    #[derive(Debug, PartialEq)]
+    struct NotAuthorized(&'static str);
+
+    impl fmt::Display for NotAuthorized {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} is not authorized", self.0)
+        }
+    }
+
+    impl error::Error for NotAuthorized {}
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals
+        .set(
+            "delete_file",
+            lua.create_function(|_, ()| -> Result<()> {
+                Err(NotAuthorized("delete_file").to_lua_err())
+            }).unwrap(),
+        ).unwrap();
+    globals
+        .set(
+            "forward",
+            lua.create_function(|_, f: Function| f.call::<_, ()>(()))
+                .unwrap(),
+        ).unwrap();
+    globals
+        .set(
+            "fail",
+            lua.create_function(|_, ()| -> Result<()> {
+                Err(err_msg("generic failure").to_lua_err())
+            }).unwrap(),
+        ).unwrap();
+
+    let err = lua.exec::<()>("delete_file()", None).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NotAuthorized>(),
+        Some(&NotAuthorized("delete_file"))
+    );
+
+    // The error is found through several layers of callbacks.
+    let err = lua.exec::<()>("forward(delete_file)", None).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NotAuthorized>(),
+        Some(&NotAuthorized("delete_file"))
+    );
+
+    let err = lua.exec::<()>("fail()", None).unwrap_err();
+    assert!(err.downcast_ref::<NotAuthorized>().is_none());
+    let err = lua.exec::<()>("error('lua error')", None).unwrap_err();
+    assert!(err.downcast_ref::<NotAuthorized>().is_none());
+}
+
+#[test]
+fn test_error_predicates() {
+    let lua = Lua::new();
+
+    let err = lua.exec::<()>("x = ", None).unwrap_err();
+    assert!(err.is_syntax_error());
+    assert!(!err.is_runtime_error());
+
+    let err = lua.exec::<()>("error('boom')", None).unwrap_err();
+    assert!(err.is_runtime_error());
+    assert!(!err.is_callback_error());
+
+    let fail = lua
+        .create_function(|_, ()| -> Result<()> { Err(Error::RuntimeError("x".to_owned())) })
+        .unwrap();
+    let err = fail.call::<_, ()>(()).unwrap_err();
+    assert!(err.is_callback_error());
+    assert!(!err.is_runtime_error());
+
+    let err = lua.eval::<i64>("{}", None).unwrap_err();
+    assert!(err.is_from_lua_conversion_error());
+
+    lua.set_memory_limit(Some(lua.gc_count() + 64 * 1024));
+    let err = lua
+        .exec::<()>("local s = string.rep('x', 1024 * 1024)", None)
+        .unwrap_err();
+    assert!(err.is_memory_error());
+    assert!(!err.is_runtime_error());
+    assert!(err.to_string().starts_with("memory error: "));
+}
+
+#[test]
+fn test_error_reraise() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals
+        .set(
+            "fail",
+            lua.create_function(|_, ()| -> Result<()> { Err(Error::RecursiveMutCallback) })
+                .unwrap(),
+        ).unwrap();
+
+    let reraise: Function = lua
+        .eval(
+            r#"
+                function()
+                    local ok, err = pcall(fail)
+                    assert(not ok)
+                    assert(tostring(err) == "mutable callback called recursively")
+                    error(err)
+                end
+            "#,
+            None,
+        ).unwrap();
+    match reraise.call::<_, ()>(()) {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RecursiveMutCallback => {}
+            ref err => panic!("wrong callback error cause {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_error_converter() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals
+        .set(
+            "fail",
+            lua.create_function(|_, code: i64| -> Result<()> {
+                Err(Error::RuntimeError(format!("failure {}", code)))
+            }).unwrap(),
+        ).unwrap();
+
+    // Without a converter, the error itself is raised.
+    lua.exec::<()>(
+        r#"
+            local ok, err = pcall(fail, 1)
+            assert(type(err) == "userdata")
+            assert(tostring(err) == "runtime error: failure 1")
+        "#,
+        None,
+    ).unwrap();
+
+    lua.set_error_converter(|lua, err| {
+        let message = err.to_string();
+        if message.contains("unconvertible") {
+            return Err(Error::RuntimeError("converter failed".to_owned()));
+        }
+        let error = lua.create_table()?;
+        error.set("code", 42)?;
+        error.set("message", message)?;
+        Ok(Value::Table(error))
+    });
+    lua.exec::<()>(
+        r#"
+            local ok, err = pcall(fail, 2)
+            assert(not ok)
+            assert(err.code == 42)
+            assert(err.message == "runtime error: failure 2")
+        "#,
+        None,
+    ).unwrap();
+
+    // If the converter fails, the original error is raised.
+    globals
+        .set(
+            "fail_unconvertible",
+            lua.create_function(|_, ()| -> Result<()> {
+                Err(Error::RuntimeError("unconvertible".to_owned()))
+            }).unwrap(),
+        ).unwrap();
+    lua.exec::<()>(
+        r#"
+            local ok, err = pcall(fail_unconvertible)
+            assert(type(err) == "userdata")
+            assert(tostring(err) == "runtime error: unconvertible")
+        "#,
+        None,
+    ).unwrap();
+    match lua.exec::<()>("fail_unconvertible()", None) {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "unconvertible"),
+            ref err => panic!("wrong callback error cause {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_num_conversion() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set("n", "1.0").unwrap();
+    assert_eq!(globals.get::<_, i64>("n").unwrap(), 1);
+    assert_eq!(globals.get::<_, f64>("n").unwrap(), 1.0);
+    assert_eq!(globals.get::<_, String>("n").unwrap(), "1.0");
+
+    globals.set("n", "1.5").unwrap();
+    assert!(globals.get::<_, i64>("n").is_err());
+    assert_eq!(globals.get::<_, f64>("n").unwrap(), 1.5);
+    assert_eq!(globals.get::<_, String>("n").unwrap(), "1.5");
+
+    globals.set("n", 1.5).unwrap();
+    assert!(globals.get::<_, i64>("n").is_err());
+    assert_eq!(globals.get::<_, f64>("n").unwrap(), 1.5);
+    assert_eq!(globals.get::<_, String>("n").unwrap(), "1.5");
+
+    lua.exec::<()>("a = math.huge", None).unwrap();
+    assert!(globals.get::<_, i64>("n").is_err());
+}
+
+#[test]
+fn test_num_conversion_range() {
+    let lua = Lua::new();
+
+    fn is_conversion_error<T>(r: Result<T>) -> bool {
+        match r {
+            Err(Error::FromLuaConversionError { .. }) => true,
+            _ => false,
+        }
+    }
+
+    // 2^53 is exactly representable, but does not fit in an i32.
+    let big: Value = lua.eval("2^53", None).unwrap();
+    assert_eq!(lua.unpack::<i64>(big.clone()).unwrap(), 1 << 53);
+    assert!(is_conversion_error(lua.unpack::<i32>(big.clone())));
+    assert!(is_conversion_error(lua.unpack::<u8>(big)));
+
+    assert!(is_conversion_error(lua.unpack::<u8>(Value::Integer(256))));
+    assert!(is_conversion_error(lua.unpack::<u8>(Value::Integer(-1))));
+    assert!(is_conversion_error(lua.unpack::<u32>(Value::Integer(-1))));
+    assert_eq!(lua.unpack::<u8>(Value::Integer(255)).unwrap(), 255);
+    assert_eq!(lua.unpack::<i8>(Value::Number(-128.0)).unwrap(), -128);
+    assert!(is_conversion_error(lua.unpack::<i32>(Value::Number(0.5))));
+    assert!(is_conversion_error(lua.unpack::<i64>(Value::Number(1e19))));
+    assert!(is_conversion_error(lua.unpack::<i64>(Value::Number(std::f64::NAN))));
+
+    // Values above `Integer::max_value()` are converted to numbers, which is lossy.
+    let value = lua.pack(u64::max_value()).unwrap();
+    match value {
+        Value::Number(n) => assert_eq!(n, 18446744073709551616.0),
+        ref v => panic!("expected a number, got {:?}", v),
+    }
+    assert!(is_conversion_error(lua.unpack::<u64>(value)));
+    let value = lua.pack(1u64 << 63).unwrap();
+    assert_eq!(lua.unpack::<u64>(value).unwrap(), 1 << 63);
+    let value = lua.pack((1u64 << 53) + 1).unwrap();
+    assert_eq!(lua.unpack::<u64>(value).unwrap(), (1 << 53) + 1);
+    assert_eq!(lua.unpack::<usize>(Value::Number(9007199254740992.0)).unwrap(), 1 << 53);
+
+    // Lua stores integers as numbers, so integers above 2^53 are rounded when they pass through
+    // Lua.
+    let globals = lua.globals();
+    globals.set("exact", (1i64 << 53) - 1).unwrap();
+    assert_eq!(globals.get::<_, i64>("exact").unwrap(), (1 << 53) - 1);
+    globals.set("rounded", (1u64 << 53) + 1).unwrap();
+    assert_eq!(globals.get::<_, u64>("rounded").unwrap(), 1 << 53);
+    globals.set("rounded", -(1i64 << 53) - 1).unwrap();
+    assert_eq!(globals.get::<_, i64>("rounded").unwrap(), -(1 << 53));
+    globals.set("rounded", i64::max_value()).unwrap();
+    assert!(is_conversion_error(globals.get::<_, i64>("rounded")));
+    assert_eq!(globals.get::<_, u64>("rounded").unwrap(), 1 << 63);
+}
+
+#[test]
+fn test_small_num_conversions() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set("x", 1.5f32).unwrap();
+    globals.set("id", 65535u16).unwrap();
+    globals.set("offset", -32768i16).unwrap();
+    globals.set("byte", 255u8).unwrap();
+    lua.exec::<()>(
+        r#"
+            assert(x == 1.5)
+            assert(id == 65535)
+            assert(offset == -32768)
+            assert(byte == 255)
+            x = x * 2
+            id = id - 1
+        "#,
+        None,
+    ).unwrap();
+    assert_eq!(globals.get::<_, f32>("x").unwrap(), 3.0);
+    assert_eq!(globals.get::<_, u16>("id").unwrap(), 65534);
+    assert_eq!(globals.get::<_, i16>("offset").unwrap(), -32768);
+    assert_eq!(globals.get::<_, u8>("byte").unwrap(), 255);
+    assert!(globals.get::<_, i8>("byte").is_err());
+    assert!(globals.get::<_, u16>("offset").is_err());
+
+    // Numbers are rounded to the nearest `f32`, but too large numbers are an error.
+    globals.set("x", 0.1f64).unwrap();
+    assert_eq!(globals.get::<_, f32>("x").unwrap(), 0.1f32);
+    globals.set("x", 1e300).unwrap();
+    assert!(globals.get::<_, f32>("x").is_err());
+    lua.exec::<()>("x = math.huge", None).unwrap();
+    assert_eq!(globals.get::<_, f32>("x").unwrap(), std::f32::INFINITY);
+}
+
+#[test]
+fn test_char_conversion() {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    for &c in &['a', 'é', '€', '🦀'] {
+        globals.set("c", c).unwrap();
+        assert_eq!(globals.get::<_, std::string::String>("c").unwrap(), c.to_string());
+        assert_eq!(globals.get::<_, char>("c").unwrap(), c);
+    }
+
+    lua.exec::<()>("c = 0x20AC", None).unwrap();
+    assert_eq!(globals.get::<_, char>("c").unwrap(), '€');
+
+    for source in &["c = ''", "c = 'ab'", "c = 0xD800", "c = -1", "c = 65.5", "c = {}"] {
+        lua.exec::<()>(source, None).unwrap();
+        match globals.get::<_, char>("c") {
+            Err(Error::FromLuaConversionError { .. }) => {}
+            r => panic!("expected FromLuaConversionError for `{}`, got {:?}", source, r),
+        }
+    }
+}
+
+#[test]
+fn test_path_conversion() {
+    let lua = Lua::new();
+    let globals = lua.globals();
 
+    globals.set("path", Path::new("/tmp/scripts/init.lua")).unwrap();
     lua.exec::<()>(
         r#"
-            local r, e = err()
-            assert(r == nil)
-            assert(tostring(e) == "only through failure can we succeed")
-
-            local r, e = ok()
-            assert(r == "!")
-            assert(e == nil)
+            assert(path == "/tmp/scripts/init.lua")
+            path = path:gsub("init", "main")
         "#,
         None,
     ).unwrap();
+    assert_eq!(
+        globals.get::<_, PathBuf>("path").unwrap(),
+        PathBuf::from("/tmp/scripts/main.lua")
+    );
+    assert!(globals.get::<_, PathBuf>("nonexistent").is_err());
+
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(OsStr::from_bytes(b"/tmp/\xff\xfe.lua"));
+        globals.set("path", path.clone()).unwrap();
+        assert_eq!(
+            globals.get::<_, String>("path").unwrap().as_bytes(),
+            b"/tmp/\xff\xfe.lua"
+        );
+        assert_eq!(globals.get::<_, PathBuf>("path").unwrap(), path);
+    }
 }
 
 #[test]
-fn test_num_conversion() {
+fn test_time_conversions() {
     let lua = Lua::new();
     let globals = lua.globals();
 
-    globals.set("n", "1.0").unwrap();
-    assert_eq!(globals.get::<_, i64>("n").unwrap(), 1);
-    assert_eq!(globals.get::<_, f64>("n").unwrap(), 1.0);
-    assert_eq!(globals.get::<_, String>("n").unwrap(), "1.0");
+    globals.set("d", Duration::from_millis(1500)).unwrap();
+    assert_eq!(globals.get::<_, f64>("d").unwrap(), 1.5);
+    assert_eq!(
+        globals.get::<_, Duration>("d").unwrap(),
+        Duration::from_millis(1500)
+    );
+    assert_eq!(lua.eval::<Duration>("'2'", None).unwrap(), Duration::from_secs(2));
 
-    globals.set("n", "1.5").unwrap();
-    assert!(globals.get::<_, i64>("n").is_err());
-    assert_eq!(globals.get::<_, f64>("n").unwrap(), 1.5);
-    assert_eq!(globals.get::<_, String>("n").unwrap(), "1.5");
+    for invalid in &["-1", "0/0", "math.huge", "'soon'"] {
+        match lua.eval::<Duration>(invalid, None) {
+            Err(Error::FromLuaConversionError { .. }) => {}
+            r => panic!("expected conversion error for {}, got {:?}", invalid, r),
+        }
+    }
 
-    globals.set("n", 1.5).unwrap();
-    assert!(globals.get::<_, i64>("n").is_err());
-    assert_eq!(globals.get::<_, f64>("n").unwrap(), 1.5);
-    assert_eq!(globals.get::<_, String>("n").unwrap(), "1.5");
+    let time = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    globals.set("t", time).unwrap();
+    assert_eq!(globals.get::<_, f64>("t").unwrap(), 1_500_000_000.0);
+    assert_eq!(globals.get::<_, SystemTime>("t").unwrap(), time);
 
-    lua.exec::<()>("a = math.huge", None).unwrap();
-    assert!(globals.get::<_, i64>("n").is_err());
+    let before_epoch = lua.eval::<SystemTime>("-60.5", None).unwrap();
+    assert_eq!(
+        UNIX_EPOCH.duration_since(before_epoch).unwrap(),
+        Duration::from_millis(60_500)
+    );
+    globals.set("t", before_epoch).unwrap();
+    assert_eq!(globals.get::<_, f64>("t").unwrap(), -60.5);
+
+    match lua.eval::<SystemTime>("0/0", None) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected conversion error, got {:?}", r),
+    }
 }
 
 #[test]
@@ -530,6 +1803,13 @@ fn test_named_registry_value() {
         Nil => {}
         val => panic!("registry value was not Nil, was {:?}", val),
     };
+
+    for &name in &["_LOADED", "_PRELOAD", "FILE*"] {
+        assert!(lua.set_named_registry_value(name, 1).is_err());
+        assert!(lua.unset_named_registry_value(name).is_err());
+    }
+    lua.named_registry_value::<Table>("_LOADED").unwrap();
+    lua.exec::<()>("require('string')", None).unwrap();
 }
 
 #[test]
@@ -551,28 +1831,69 @@ fn test_registry_value() {
     f.call::<_, ()>(()).unwrap();
 }
 
-// TODO: re-implement without Userdata?
-//#[test]
-//fn test_drop_registry_value() {
-//    struct MyUserdata(Arc<()>);
-//
-//    impl UserData for MyUserdata {}
-//
-//    let lua = Lua::new();
-//
-//    let rc = Arc::new(());
-//
-//    let r = lua.create_registry_value(MyUserdata(rc.clone())).unwrap();
-//    assert_eq!(Arc::strong_count(&rc), 2);
-//
-//    drop(r);
-//    lua.expire_registry_values();
-//
-//    lua.exec::<()>(r#"collectgarbage("collect")"#, None)
-//        .unwrap();
-//
-//    assert_eq!(Arc::strong_count(&rc), 1);
-//}
+#[test]
+fn test_typed_registry_value() {
+    let lua = Lua::new();
+
+    let callbacks = vec![
+        lua.create_registry_value(lua.create_function(|_, n: i64| Ok(n + 1)).unwrap())
+            .unwrap(),
+        lua.create_registry_value(lua.create_function(|_, n: i64| Ok(n * 2)).unwrap())
+            .unwrap(),
+    ];
+    let config = lua.create_table().unwrap();
+    config.set("name", "test").unwrap();
+    let config = lua.create_registry_value(config).unwrap();
+
+    let mut n = 20;
+    for key in &callbacks {
+        n = lua.registry_value::<Function>(key).unwrap().call(n).unwrap();
+    }
+    assert_eq!(n, 42);
+    let config = lua.registry_value::<Table>(&config).unwrap();
+    assert_eq!(config.get::<_, String>("name").unwrap(), "test");
+
+    match lua.registry_value::<Table>(&callbacks[0]) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("function was converted to a table, got {:?}", r),
+    }
+    match lua.registry_value(&callbacks[0]).unwrap() {
+        Value::Function(_) => {}
+        v => panic!("expected a function, got {:?}", v),
+    };
+}
+
+#[test]
+fn test_drop_registry_value() {
+    let lua = Lua::new();
+    let alive: Table = lua
+        .eval("setmetatable({}, { __mode = 'k' })", None)
+        .unwrap();
+    let count = |alive: &Table| alive.clone().pairs::<Value, Value>().count();
+
+    let key = {
+        let table = lua.create_table().unwrap();
+        alive.set(table.clone(), true).unwrap();
+        lua.create_registry_value(table).unwrap()
+    };
+    lua.gc_collect().unwrap();
+    assert_eq!(count(&alive), 1);
+    drop(key);
+    lua.gc_collect().unwrap();
+    assert_eq!(count(&alive), 0);
+
+    // Keys dropped inside of a callback are reaped later on.
+    let key = Mutex::new(Some(lua.create_registry_value("value").unwrap()));
+    let id = format!("{:?}", key.lock().unwrap().as_ref().unwrap());
+    let drop_key = lua.create_function(move |_, ()| {
+        drop(key.lock().unwrap().take());
+        Ok(())
+    }).unwrap();
+    drop_key.call::<_, ()>(()).unwrap();
+    let key = lua.create_registry_value("other").unwrap();
+    assert_eq!(format!("{:?}", key), id);
+    assert_eq!(lua.registry_value::<String>(&key).unwrap(), "other");
+}
 
 #[test]
 #[should_panic]
@@ -725,3 +2046,562 @@ fn large_args_ref() {
     f.call::<_, ()>((0..100).map(|i| i.to_string()).collect::<Variadic<_>>())
         .unwrap();
 }
+
+#[test]
+fn test_app_data() {
+    struct Counter(u32);
+
+    let lua = Lua::new();
+    assert!(lua.app_data_ref::<Counter>().unwrap().is_none());
+    assert!(lua.set_app_data(Counter(0)).unwrap().is_none());
+    assert!(lua.set_app_data("name").unwrap().is_none());
+
+    let count = lua
+        .create_function(|lua, ()| {
+            let mut counter = lua.app_data_mut::<Counter>()?.unwrap();
+            counter.0 += 1;
+            Ok(counter.0)
+        }).unwrap();
+    lua.globals().set("count", count).unwrap();
+    lua.exec::<()>("count(); count()", None).unwrap();
+    lua.create_thread(lua.eval("count", None).unwrap())
+        .unwrap()
+        .resume::<_, ()>(())
+        .unwrap();
+
+    {
+        let counter = lua.app_data_ref::<Counter>().unwrap().unwrap();
+        assert_eq!(counter.0, 3);
+        // Data of other types can be used while `Counter` is borrowed.
+        assert_eq!(*lua.app_data_ref::<&str>().unwrap().unwrap(), "name");
+        *lua.app_data_mut::<&str>().unwrap().unwrap() = "other";
+        match lua.set_app_data(Counter(5)) {
+            Err(Error::AppDataBorrowMutError) => {}
+            r => panic!("expected AppDataBorrowMutError, got {:?}", r.map(|_| ())),
+        }
+        match lua.exec::<()>("count()", None) {
+            Err(Error::CallbackError { ref cause, .. }) => match **cause {
+                Error::AppDataBorrowMutError => {}
+                ref err => panic!("expected AppDataBorrowMutError, got {:?}", err),
+            },
+            r => panic!("expected CallbackError, got {:?}", r),
+        }
+        assert_eq!(counter.0, 3);
+    }
+
+    assert_eq!(lua.set_app_data(Counter(10)).unwrap().unwrap().0, 3);
+    assert_eq!(lua.remove_app_data::<Counter>().unwrap().unwrap().0, 10);
+    assert!(lua.app_data_ref::<Counter>().unwrap().is_none());
+    assert!(lua.remove_app_data::<Counter>().unwrap().is_none());
+}
+
+#[test]
+fn test_app_data_reentrancy() {
+    struct Log(Vec<&'static str>);
+
+    let lua = Lua::new();
+    lua.set_app_data(Log(Vec::new())).unwrap();
+
+    let read = lua
+        .create_function(|lua, ()| Ok(lua.app_data_ref::<Log>()?.unwrap().0.len()))
+        .unwrap();
+    lua.globals().set("read", read).unwrap();
+
+    // A callback that keeps the data borrowed while calling back into Lua.
+    let write = lua
+        .create_function(|lua, f: Function| {
+            let mut log = lua.app_data_mut::<Log>()?.unwrap();
+            log.0.push("write");
+            f.call::<_, ()>(())
+        }).unwrap();
+    lua.globals().set("write", write).unwrap();
+
+    match lua.exec::<()>("write(read)", None) {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::CallbackError { ref cause, .. } => match **cause {
+                Error::AppDataBorrowError => {}
+                ref err => panic!("expected AppDataBorrowError, got {:?}", err),
+            },
+            ref err => panic!("expected CallbackError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    // Once the outer borrow ends, the data can be borrowed again.
+    assert_eq!(lua.eval::<usize>("read()", None).unwrap(), 1);
+    lua.exec::<()>("write(function() end)", None).unwrap();
+    assert_eq!(lua.app_data_ref::<Log>().unwrap().unwrap().0, vec!["write", "write"]);
+}
+
+#[test]
+fn test_memory_limit() {
+    let lua = Lua::new();
+    lua.set_memory_limit(Some(1024 * 1024));
+
+    let f = lua
+        .load(
+            br#"
+                local t = {}
+                for i = 1, 1000000 do
+                    t[i] = "string number " .. i
+                end
+            "#,
+            None,
+            ChunkMode::Text,
+        ).unwrap();
+    match f.call::<_, ()>(()) {
+        Err(Error::MemoryError(_)) => {}
+        r => panic!("memory limit was not enforced, got {:?}", r),
+    }
+
+    lua.set_memory_limit(None);
+    f.call::<_, ()>(()).unwrap();
+}
+
+#[test]
+fn test_memory_error() {
+    let lua = Lua::new();
+    let source = format!("return '{}'", "x".repeat(256 * 1024));
+    let big = lua.create_string(&"y".repeat(256 * 1024)).unwrap();
+    lua.gc_collect().unwrap();
+    lua.set_memory_limit(Some(lua.gc_count() + 64 * 1024));
+
+    match lua.load(source.as_bytes(), None, ChunkMode::Text) {
+        Err(Error::MemoryError(_)) => {}
+        r => panic!("expected MemoryError, got {:?}", r),
+    }
+    match lua.create_table_from((0..100_000).map(|i| (i, i))) {
+        Err(Error::MemoryError(_)) => {}
+        r => panic!("expected MemoryError, got {:?}", r),
+    }
+
+    let concat = lua
+        .create_function(move |lua, s: String| lua.create_string(&[s.as_bytes(); 2].concat()))
+        .unwrap();
+    match concat.call::<_, String>(big.clone()) {
+        Err(Error::CallbackError { ref cause, .. }) if cause.is_memory_error() => {}
+        r => panic!("expected CallbackError caused by MemoryError, got {:?}", r),
+    }
+
+    // Lua code can catch memory errors.
+    lua.globals().set("big", big).unwrap();
+    assert!(lua.eval::<bool>("not pcall(string.rep, big, 4)", None).unwrap());
+}
+
+#[test]
+fn test_memory_limit_error_handling() {
+    let lua = Lua::new();
+
+    // Once the limit is exhausted, the error value, its traceback and its message still have to
+    // be created.
+    let fail = lua
+        .create_function(|lua, ()| -> Result<()> {
+            lua.set_memory_limit(Some(1));
+            Err(Error::RuntimeError("out of budget".to_owned()))
+        }).unwrap();
+    match fail.call::<_, ()>(()) {
+        Err(Error::CallbackError {
+            ref cause,
+            ref traceback,
+        }) => {
+            assert!(traceback.contains("stack traceback"));
+            match **cause {
+                Error::RuntimeError(ref msg) => assert_eq!(msg, "out of budget"),
+                ref err => panic!("expected RuntimeError, got {:?}", err),
+            }
+        }
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+    lua.set_memory_limit(None);
+
+    lua.globals().set("fail", fail).unwrap();
+    let (ok, msg) = lua
+        .eval::<(bool, String)>("local ok, err = pcall(fail) return ok, tostring(err)", None)
+        .unwrap();
+    assert!(!ok);
+    assert!(msg.to_str().unwrap().contains("out of budget"));
+    lua.set_memory_limit(None);
+
+    // Growing the stack that holds references does not raise a memory error.
+    lua.set_memory_limit(Some(lua.gc_count() + 64));
+    let globals = (0..2000).map(|_| lua.globals()).collect::<Vec<_>>();
+    lua.set_memory_limit(None);
+    assert_eq!(globals.len(), 2000);
+    drop(globals);
+
+    // Neither does growing the stack of a callback to hold its results, which would otherwise
+    // skip the bookkeeping of the callback.
+    let many = lua
+        .create_function(|_, ()| Ok(Variadic::from_iter(0..5000)))
+        .unwrap();
+    lua.gc_collect().unwrap();
+    lua.set_memory_limit(Some(lua.gc_count() + 64));
+    let count = many.call::<_, Variadic<i64>>(()).map(|values| values.len());
+    lua.set_memory_limit(None);
+    match count {
+        Ok(count) => assert_eq!(count, 5000),
+        Err(Error::MemoryError(_)) => {}
+        Err(err) => panic!("expected MemoryError, got {:?}", err),
+    }
+    assert_eq!(lua.call_depth(), 0);
+}
+
+#[test]
+fn test_hook_count() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let lua = Lua::new();
+    let interrupted = Arc::new(AtomicUsize::new(0));
+    {
+        let interrupted = interrupted.clone();
+        lua.set_hook_count(1000, move |_| {
+            interrupted.fetch_add(1, Ordering::SeqCst);
+            Err(Error::RuntimeError("time limit exceeded".to_owned()))
+        });
+    }
+
+    let check_interrupted = |r: Result<()>| match r {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "time limit exceeded"),
+            ref e => panic!("unexpected error cause {:?}", e),
+        },
+        r => panic!("expected the hook to interrupt execution, got {:?}", r),
+    };
+
+    let infinite: Function = lua.eval("function() while true do end end", None).unwrap();
+    check_interrupted(infinite.call::<_, ()>(()));
+
+    let nested = lua
+        .create_function(move |_, f: Function| f.call::<_, ()>(()))
+        .unwrap();
+    lua.globals().set("nested", nested).unwrap();
+    let outer: Function = lua
+        .eval(
+            r#"
+                function()
+                    nested(function() while true do end end)
+                end
+            "#,
+            None,
+        ).unwrap();
+    assert!(outer.call::<_, ()>(()).is_err());
+
+    let coroutine: Function = lua
+        .eval(
+            r#"
+                function()
+                    local co = coroutine.create(function() while true do end end)
+                    local ok, err = coroutine.resume(co)
+                    error(err, 0)
+                end
+            "#,
+            None,
+        ).unwrap();
+    assert!(coroutine.call::<_, ()>(()).is_err());
+    assert_eq!(interrupted.load(Ordering::SeqCst), 3);
+
+    lua.remove_hook();
+    let finite: Function = lua
+        .eval("function() for i = 1, 100000 do end end", None)
+        .unwrap();
+    finite.call::<_, ()>(()).unwrap();
+    assert_eq!(interrupted.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_set_hook() {
+    let lua = Lua::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    {
+        let events = events.clone();
+        lua.set_hook(HookMask::CALL | HookMask::RETURN | HookMask::LINE, move |_, info| {
+            events.lock().unwrap().push(info);
+            Ok(())
+        });
+    }
+    let f: Function = lua
+        .load(
+            b"local function g()\n  return 1\nend\nreturn g() + 1",
+            None,
+            ChunkMode::Text,
+        ).unwrap();
+    events.lock().unwrap().clear();
+    assert_eq!(f.call::<_, i64>(()).unwrap(), 2);
+    lua.remove_hook();
+
+    let events = events.lock().unwrap();
+    let lines: Vec<_> = events
+        .iter()
+        .filter(|info| info.event == HookEvent::Line)
+        .map(|info| info.current_line.unwrap())
+        .collect();
+    assert_eq!(lines, vec![3, 4, 2]);
+    let calls = events.iter().filter(|info| info.event == HookEvent::Call).count();
+    let returns = events.iter().filter(|info| info.event == HookEvent::Return).count();
+    assert_eq!(calls, 2);
+    assert_eq!(calls, returns);
+
+    // A breakpoint on line 2 that aborts the running call.
+    lua.set_hook(HookMask::LINE, |_, info| {
+        if info.current_line == Some(2) {
+            Err(Error::RuntimeError("breakpoint".to_owned()))
+        } else {
+            Ok(())
+        }
+    });
+    match f.call::<_, i64>(()) {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => assert_eq!(msg, "breakpoint"),
+            ref e => panic!("unexpected error cause {:?}", e),
+        },
+        r => panic!("expected the hook to abort the call, got {:?}", r),
+    }
+
+    let count = Arc::new(Mutex::new(0));
+    {
+        let count = count.clone();
+        lua.set_hook(HookMask::count(1), move |_, info| {
+            assert_eq!(info.event, HookEvent::Count);
+            *count.lock().unwrap() += 1;
+            Ok(())
+        });
+    }
+    lua.exec::<()>("for i = 1, 10 do end", None).unwrap();
+    lua.remove_hook();
+    assert!(*count.lock().unwrap() >= 10);
+}
+
+#[test]
+fn test_gc_control() {
+    let lua = Lua::new();
+
+    assert!(lua.gc_is_running());
+    lua.gc_stop();
+    assert!(!lua.gc_is_running());
+
+    // Error handling inside rlua temporarily disables the GC, which must not restart it.
+    assert!(
+        lua.load(b"error('error')", None, ChunkMode::Text)
+            .unwrap()
+            .call::<_, ()>(())
+            .is_err()
+    );
+    assert!(!lua.gc_is_running());
+
+    lua.exec::<()>(
+        r#"
+            garbage = {}
+            for i = 1, 10000 do
+                garbage[i] = {}
+            end
+        "#,
+        None,
+    ).unwrap();
+    lua.globals().set("garbage", Nil).unwrap();
+    let before = lua.gc_count();
+    lua.gc_collect().unwrap();
+    assert!(lua.gc_count() < before);
+
+    while !lua.gc_step(1).unwrap() {}
+    assert!(!lua.gc_is_running());
+    lua.gc_restart();
+    assert!(lua.gc_is_running());
+
+    assert_eq!(lua.gc_set_pause(100), 200);
+    assert_eq!(lua.gc_set_pause(200), 100);
+    assert_eq!(lua.gc_set_step_multiplier(400), 200);
+
+    lua.gc_set_incremental(150, 300);
+    assert_eq!(lua.gc_set_pause(200), 150);
+    assert_eq!(lua.gc_set_step_multiplier(200), 300);
+    assert!(lua.gc_is_running());
+}
+
+#[test]
+fn test_max_call_depth() {
+    let lua = Lua::new();
+    lua.set_max_call_depth(Some(10));
+
+    let recurse = lua
+        .create_function(|lua, n: u32| {
+            assert_eq!(lua.call_depth(), n as usize);
+            lua.globals()
+                .get::<_, Function>("recurse")?
+                .call::<_, ()>(n + 1)
+        }).unwrap();
+    lua.globals().set("recurse", recurse.clone()).unwrap();
+
+    assert_eq!(lua.call_depth(), 0);
+    match recurse.call::<_, ()>(1) {
+        Err(Error::CallbackError { ref cause, .. }) => {
+            let mut cause = cause.clone();
+            while let Error::CallbackError { cause: ref inner, .. } = *cause.clone() {
+                cause = inner.clone();
+            }
+            match *cause {
+                Error::CallDepthExceeded => {}
+                ref e => panic!("unexpected error {:?}", e),
+            }
+        }
+        r => panic!("expected the call depth to be exceeded, got {:?}", r),
+    }
+    assert_eq!(lua.call_depth(), 0);
+
+    lua.set_max_call_depth(None);
+    let count = lua
+        .create_function(|lua, ()| Ok(lua.call_depth()))
+        .unwrap();
+    assert_eq!(count.call::<_, usize>(()).unwrap(), 1);
+}
+
+#[test]
+fn test_warning_handler() {
+    let lua = Lua::new();
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let handler_warnings = warnings.clone();
+    lua.set_warning_handler(move |msg| handler_warnings.lock().unwrap().push(msg.to_owned()))
+        .unwrap();
+
+    lua.exec::<()>(
+        r#"
+            warn("a", "b", 1)
+            warn("@off")
+            warn("ignored")
+            warn("@on")
+            warn("@unknown")
+            warn("c")
+        "#,
+        None,
+    ).unwrap();
+    assert_eq!(*warnings.lock().unwrap(), vec!["ab1", "c"]);
+
+    assert!(lua.exec::<()>("warn()", None).is_err());
+}
+
+#[test]
+fn test_builder() {
+    let lua = Lua::builder()
+        .stdlib(StdLib::BASE | StdLib::STRING)
+        .build()
+        .unwrap();
+    assert!(
+        lua.eval::<bool>("os == nil and io == nil and package == nil", None)
+            .unwrap()
+    );
+    assert_eq!(lua.eval::<String>("('x'):rep(2)", None).unwrap(), "xx");
+    assert!(lua.eval::<bool>("coroutine ~= nil", None).unwrap());
+
+    // pcall is still replaced by the version that cannot catch Rust panics
+    assert!(
+        catch_unwind(|| {
+            let lua = Lua::builder().stdlib(StdLib::BASE).build().unwrap();
+            let panic = lua
+                .create_function(|_, ()| -> Result<()> { panic!("test_panic") })
+                .unwrap();
+            lua.globals().set("rust_panic", panic).unwrap();
+            lua.exec::<()>("pcall(rust_panic)", None)
+        }).is_err()
+    );
+
+    let lua = Lua::builder().stdlib(StdLib::NONE).build().unwrap();
+    assert!(
+        lua.eval::<bool>("print == nil and string == nil", None)
+            .unwrap()
+    );
+
+    let lua = Lua::builder().memory_limit(1024 * 1024).build().unwrap();
+    assert!(
+        lua.exec::<()>("local t = {} for i = 1, 1000000 do t[i] = i end", None)
+            .is_err()
+    );
+    assert!(Lua::builder().memory_limit(1).build().is_err());
+
+    let lua = unsafe { Lua::builder().load_debug(true).build().unwrap() };
+    assert!(lua.eval::<bool>("debug ~= nil", None).unwrap());
+}
+
+#[test]
+fn test_register_module() {
+    let lua = Lua::new();
+    let loads = Arc::new(Mutex::new(0));
+
+    let counter = loads.clone();
+    lua.register_module("mymod", move |lua| {
+        *counter.lock().unwrap() += 1;
+        let module = lua.create_table()?;
+        module.set("answer", 42)?;
+        module.set("double", lua.create_function(|_, n: i64| Ok(n * 2))?)?;
+        Ok(Value::Table(module))
+    }).unwrap();
+    lua.register_module("empty", |_| Ok(Value::Nil)).unwrap();
+    assert_eq!(*loads.lock().unwrap(), 0);
+
+    lua.exec::<()>(
+        r#"
+            local mymod = require("mymod")
+            assert(mymod.answer == 42)
+            assert(mymod.double(21) == 42)
+            assert(require("mymod") == mymod)
+            assert(package.loaded.mymod == mymod)
+            assert(require("empty") == true)
+        "#,
+        None,
+    ).unwrap();
+    assert_eq!(*loads.lock().unwrap(), 1);
+
+    let lua = Lua::builder().stdlib(StdLib::BASE).build().unwrap();
+    assert!(lua.register_module("mymod", |_| Ok(Value::Nil)).is_err());
+}
+
+#[test]
+fn test_package_searcher() {
+    let lua = Lua::new();
+    lua.set_package_path("/nonexistent/?.lua").unwrap();
+    lua.set_package_cpath("/nonexistent/?.so").unwrap();
+    assert_eq!(
+        lua.eval::<String>("package.path .. ';' .. package.cpath", None).unwrap(),
+        "/nonexistent/?.lua;/nonexistent/?.so"
+    );
+
+    let mut files = HashMap::new();
+    files.insert("greet", "return function(...) return 'hello ' .. select('#', ...) end");
+    files.insert("answer", "local name = ... return { name = name, value = 42 }");
+    lua.add_package_searcher(move |lua, name| match files.get(name) {
+        Some(source) => Ok(Some(lua.load(source.as_bytes(), Some(name), ChunkMode::Text)?)),
+        None => Ok(None),
+    }).unwrap();
+
+    lua.exec::<()>(
+        r#"
+            local answer = require("answer")
+            assert(answer.name == "answer" and answer.value == 42)
+            assert(require("answer") == answer)
+            assert(require("greet")(1, 2) == "hello 2")
+        "#,
+        None,
+    ).unwrap();
+
+    match lua.exec::<()>("require('missing')", None) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("no module 'missing' in Rust searcher"));
+            assert!(msg.contains("/nonexistent/missing.lua"));
+        }
+        r => panic!("missing module was found, got {:?}", r),
+    };
+}
+
+#[test]
+fn test_load_stdlib() {
+    let lua = Lua::builder().stdlib(StdLib::NONE).build().unwrap();
+    lua.load_stdlib(StdLib::BASE | StdLib::TABLE).unwrap();
+    assert!(lua.eval::<bool>("_G == _G._G", None).unwrap());
+    assert!(lua.eval::<bool>("table ~= nil and string == nil", None).unwrap());
+
+    lua.exec::<()>("table.custom = 1", None).unwrap();
+    lua.load_stdlib(StdLib::TABLE | StdLib::STRING).unwrap();
+    assert_eq!(lua.eval::<i64>("table.custom", None).unwrap(), 1);
+    assert_eq!(lua.eval::<i64>("#string.rep('a', 4)", None).unwrap(), 4);
+    assert!(StdLib::ALL.contains(StdLib::BASE | StdLib::OS));
+    assert!(!(StdLib::BASE | StdLib::IO).contains(StdLib::OS));
+}
@@ -2,7 +2,7 @@ extern crate rlua;
 
 use std::panic::catch_unwind;
 
-use rlua::{Error, Function, Lua, Result, Thread, ThreadStatus};
+use rlua::{Error, Function, Lua, MultiValue, Result, Thread, ThreadStatus, Value};
 
 #[test]
 fn test_thread() {
@@ -33,7 +33,7 @@ fn test_thread() {
     assert_eq!(thread.resume::<_, i64>(3).unwrap(), 6);
     assert_eq!(thread.status(), ThreadStatus::Resumable);
     assert_eq!(thread.resume::<_, i64>(4).unwrap(), 10);
-    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+    assert_eq!(thread.status(), ThreadStatus::Dead);
 
     let accumulate =
         lua.create_thread(
@@ -94,13 +94,210 @@ fn test_thread() {
     }
 }
 
+#[test]
+fn test_thread_status() {
+    let lua = Lua::new();
+
+    let fail = lua
+        .create_function(|_, ()| -> Result<()> { Err(Error::RuntimeError("fail".to_owned())) })
+        .unwrap();
+    lua.globals().set("fail", fail).unwrap();
+    let status = lua
+        .create_function(|_, thread: Thread| Ok(thread.status() == ThreadStatus::Running))
+        .unwrap();
+    lua.globals().set("is_running", status).unwrap();
+
+    let thread: Thread = lua
+        .eval(
+            r#"
+                coroutine.create(function()
+                    assert(is_running(coroutine.running()))
+                    local outer = coroutine.running()
+                    local inner = coroutine.create(function()
+                        assert(is_running(outer))
+                    end)
+                    assert(coroutine.resume(inner))
+                    coroutine.yield()
+                    fail()
+                end)
+            "#,
+            None,
+        ).unwrap();
+
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+    thread.resume::<_, ()>(()).unwrap();
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+    match thread.resume::<_, ()>(()) {
+        Err(Error::CallbackError { .. }) => {}
+        r => panic!("expected a callback error, got {:?}", r),
+    }
+    assert_eq!(thread.status(), ThreadStatus::Error);
+    match thread.resume::<_, ()>(()) {
+        Err(Error::CoroutineInactive) => {}
+        r => panic!("expected CoroutineInactive, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_thread_resume_values() {
+    let lua = Lua::new();
+    let thread: Thread = lua
+        .eval(
+            r#"
+                coroutine.create(function(a, b, c)
+                    local d, e = coroutine.yield(a + b, c)
+                    local f = coroutine.yield()
+                    return d .. e .. f
+                end)
+            "#,
+            None,
+        ).unwrap();
+
+    // The first resume passes its arguments to the main function.
+    let (sum, c): (i64, String) = thread.resume((1, 2, "c")).unwrap();
+    assert_eq!((sum, &c[..]), (3, "c"));
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+
+    // Later resumes pass theirs to `coroutine.yield`.
+    let values: MultiValue = thread.resume(("d", "e")).unwrap();
+    assert!(values.is_empty());
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+
+    let result: String = thread.resume("f").unwrap();
+    assert_eq!(result, "def");
+    assert_eq!(thread.status(), ThreadStatus::Dead);
+}
+
+#[test]
+fn test_thread_into_iter() {
+    let lua = Lua::new();
+
+    let thread: Thread = lua
+        .eval(
+            r#"
+                coroutine.create(function()
+                    coroutine.yield(1)
+                    coroutine.yield(2, "ignored")
+                    coroutine.yield()
+                    return 3
+                end)
+            "#,
+            None,
+        ).unwrap();
+    let values = thread.into_iter::<Option<i64>>().collect::<Result<Vec<_>>>().unwrap();
+    assert_eq!(values, vec![Some(1), Some(2), None, Some(3)]);
+
+    let thread: Thread = lua
+        .eval(
+            r#"
+                coroutine.create(function()
+                    coroutine.yield(1)
+                    coroutine.yield({})
+                    coroutine.yield(2)
+                    error("generator failed")
+                end)
+            "#,
+            None,
+        ).unwrap();
+    let mut iter = thread.into_iter::<i64>();
+    assert_eq!(iter.next().unwrap().unwrap(), 1);
+    match iter.next() {
+        Some(Err(Error::FromLuaConversionError { .. })) => {}
+        r => panic!("expected a conversion error, got {:?}", r),
+    }
+    assert_eq!(iter.next().unwrap().unwrap(), 2);
+    match iter.next() {
+        Some(Err(Error::RuntimeError(ref msg))) => assert!(msg.contains("generator failed")),
+        r => panic!("expected a runtime error, got {:?}", r),
+    }
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_current_thread() {
+    let lua = Lua::new();
+    assert!(lua.is_main_thread());
+    let main = lua.current_thread();
+    assert_eq!(main, lua.current_thread());
+    assert_eq!(main.status(), ThreadStatus::Running);
+
+    let current = lua
+        .create_function(|lua, ()| Ok((lua.current_thread(), lua.is_main_thread())))
+        .unwrap();
+    lua.globals().set("current", current).unwrap();
+
+    let (thread, is_main): (Thread, bool) = lua.eval("current()", None).unwrap();
+    assert_eq!(thread, main);
+    assert!(is_main);
+
+    let coroutine: Thread = lua
+        .eval(
+            r#"
+                coroutine.create(function()
+                    local thread, is_main = current()
+                    assert(thread == coroutine.running())
+                    coroutine.yield(thread, is_main)
+                end)
+            "#,
+            None,
+        ).unwrap();
+    let (thread, is_main): (Thread, bool) = coroutine.resume(()).unwrap();
+    assert_eq!(thread, coroutine);
+    assert_ne!(thread, main);
+    assert!(!is_main);
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+    thread.resume::<_, ()>(()).unwrap();
+    assert_eq!(coroutine.status(), ThreadStatus::Dead);
+}
+
 #[test]
 fn coroutine_from_closure() {
     let lua = Lua::new();
     let thrd_main = lua.create_function(|_, ()| Ok(())).unwrap();
-    lua.globals().set("main", thrd_main).unwrap();
-    let thrd: Thread = lua.eval("coroutine.create(main)", None).unwrap();
+    let thrd = lua.create_thread(thrd_main).unwrap();
     thrd.resume::<_, ()>(()).unwrap();
+    assert_eq!(thrd.status(), ThreadStatus::Dead);
+}
+
+#[test]
+fn test_thread_from_callable() {
+    let lua = Lua::new();
+
+    let callable: Value = lua
+        .eval(
+            r#"
+                setmetatable({}, {
+                    __call = function(self, a)
+                        local b = coroutine.yield(a + 1)
+                        return b * 2
+                    end
+                })
+            "#,
+            None,
+        ).unwrap();
+    let thread = lua.create_thread_from(callable).unwrap();
+    assert_eq!(thread.resume::<_, i64>(1).unwrap(), 2);
+    assert_eq!(thread.resume::<_, i64>(5).unwrap(), 10);
+    assert_eq!(thread.status(), ThreadStatus::Dead);
+
+    assert!(lua.create_thread_from(Value::Integer(1)).is_err());
+    let table: Value = lua.eval("{}", None).unwrap();
+    assert!(lua.create_thread_from(table).is_err());
+
+    // Rust functions can be coroutine bodies, but they cannot yield.
+    let rust_body = lua
+        .create_function(|_, yield_fn: Function| yield_fn.call::<_, ()>(()))
+        .unwrap();
+    let thread = lua.create_thread(rust_body.clone()).unwrap();
+    let yield_fn: Function = lua.eval("function() coroutine.yield() end", None).unwrap();
+    match thread.resume::<_, ()>(yield_fn) {
+        Err(Error::CallbackError { .. }) => {}
+        r => panic!("expected yielding from a Rust function to fail, got {:?}", r),
+    }
+    assert_eq!(thread.status(), ThreadStatus::Error);
+
+    lua.globals().set("rust_body", rust_body).unwrap();
+    assert!(lua.exec::<()>("coroutine.create(rust_body)", None).is_err());
 }
 
 #[test]
@@ -1,8 +1,9 @@
 extern crate rlua;
 
 use std::os::raw::c_void;
+use std::string::String as StdString;
 
-use rlua::{Function, LightUserData, Lua};
+use rlua::{Error, Function, LightUserData, Lua, MultiValue, Value};
 
 #[test]
 fn test_lightuserdata() {
@@ -23,3 +24,201 @@ fn test_lightuserdata() {
         .unwrap();
     assert_eq!(res, LightUserData(42 as *mut c_void));
 }
+
+#[test]
+fn test_type_name() {
+    let lua = Lua::new();
+    let type_of: Function = lua.eval("type", None).unwrap();
+
+    let values = vec![
+        Value::Nil,
+        Value::Boolean(true),
+        Value::LightUserData(LightUserData(42 as *mut c_void)),
+        Value::Integer(1),
+        Value::Number(1.5),
+        lua.pack("string").unwrap(),
+        Value::Table(lua.create_table().unwrap()),
+        Value::Function(type_of.clone()),
+        lua.eval("coroutine.create(function() end)", None).unwrap(),
+        Value::Error(Error::BindError),
+    ];
+    for value in values {
+        let lua_name: String = type_of.call(value.clone()).unwrap();
+        assert_eq!(value.type_name(), lua_name);
+    }
+
+    assert!(Value::Nil.is_nil());
+    assert!(!Value::Boolean(false).is_nil());
+    assert!(Value::Integer(1).is_integer());
+    assert!(Value::Integer(1).is_number());
+    assert!(Value::Number(1.0).is_number());
+    assert!(!Value::Number(1.0).is_integer());
+    assert!(lua.pack("string").unwrap().is_string());
+    assert!(Value::Table(lua.create_table().unwrap()).is_table());
+    assert!(Value::Function(type_of).is_function());
+    assert!(!Value::Nil.is_table());
+}
+
+#[test]
+fn test_accessors() {
+    use std::ptr;
+
+    let lua = Lua::new();
+    let table = lua.create_table().unwrap();
+    table.set("answer", 42).unwrap();
+    let value = Value::Table(table);
+
+    assert_eq!(value.as_table().unwrap().get::<_, i64>("answer").unwrap(), 42);
+    assert!(value.as_function().is_none());
+    assert!(value.as_string().is_none());
+    assert!(value.as_integer().is_none());
+    assert!(value.as_error().is_none());
+    // The accessors only borrow, so the value can still be used afterwards.
+    let table = value.into_table().unwrap();
+    assert_eq!(table.get::<_, i64>("answer").unwrap(), 42);
+
+    assert_eq!(Value::Boolean(true).as_boolean(), Some(true));
+    assert_eq!(Value::Nil.as_boolean(), None);
+    assert_eq!(Value::Integer(3).as_integer(), Some(3));
+    assert_eq!(Value::Integer(3).as_number(), Some(3.0));
+    assert_eq!(Value::Number(0.5).as_number(), Some(0.5));
+    assert_eq!(Value::Number(3.0).as_integer(), None);
+    assert_eq!(lua.pack("3").unwrap().as_number(), None);
+    assert_eq!(
+        Value::LightUserData(LightUserData(ptr::null_mut())).as_light_userdata(),
+        Some(LightUserData(ptr::null_mut()))
+    );
+
+    let string = lua.pack("hello").unwrap();
+    assert_eq!(string.as_string().unwrap().to_str().unwrap(), "hello");
+    assert_eq!(string.into_string().unwrap(), "hello");
+    assert!(Value::Nil.into_string().is_none());
+
+    let function: Value = lua.eval("function() end", None).unwrap();
+    assert!(function.as_function().is_some());
+    assert!(function.into_function().is_some());
+    let thread: Value = lua.eval("coroutine.create(function() end)", None).unwrap();
+    assert!(thread.as_thread().is_some());
+    assert!(thread.into_thread().is_some());
+
+    match Value::Error(Error::BindError).into_error() {
+        Some(Error::BindError) => {}
+        r => panic!("expected BindError, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_coercions() {
+    let lua = Lua::new();
+    let tostring: Function = lua.globals().get("tostring").unwrap();
+
+    assert_eq!(lua.pack("3.5").unwrap().to_number(), Some(3.5));
+    assert_eq!(lua.pack(" 0x10 ").unwrap().to_number(), Some(16.0));
+    assert_eq!(lua.pack("3.5").unwrap().to_integer(), None);
+    assert_eq!(lua.pack("12").unwrap().to_integer(), Some(12));
+    assert_eq!(lua.pack("twelve").unwrap().to_number(), None);
+    assert_eq!(Value::Number(4.0).to_integer(), Some(4));
+    assert_eq!(Value::Number(1e300).to_integer(), None);
+    assert_eq!(Value::Boolean(true).to_number(), None);
+
+    let values = vec![
+        Value::Nil,
+        Value::Boolean(false),
+        Value::Integer(123456789012345),
+        Value::Number(0.1),
+        Value::Number(-2.0),
+        lua.pack("text").unwrap(),
+        Value::Table(lua.create_table().unwrap()),
+        Value::Function(tostring.clone()),
+    ];
+    for value in values {
+        let expected: StdString = tostring.call(value.clone()).unwrap();
+        assert_eq!(value.tostring().unwrap(), expected);
+        if value.is_number() || value.is_string() {
+            assert_eq!(value.to_string_lossy().unwrap(), expected);
+        }
+    }
+    assert_eq!(Value::Nil.to_string_lossy(), None);
+    assert_eq!(Value::Error(Error::BindError).tostring().unwrap(), Error::BindError.to_string());
+
+    let custom: Value = lua
+        .eval(r#"setmetatable({}, { __tostring = function() return "custom" end })"#, None)
+        .unwrap();
+    assert_eq!(custom.tostring().unwrap(), "custom");
+    assert_eq!(custom.to_string_lossy(), None);
+    let failing: Value = lua
+        .eval(r#"setmetatable({}, { __tostring = function() error("no") end })"#, None)
+        .unwrap();
+    assert!(failing.tostring().is_err());
+}
+
+#[test]
+fn test_coerce_to_string() {
+    let lua = Lua::new();
+    let tostring: Function = lua.globals().get("tostring").unwrap();
+
+    let values: MultiValue = lua
+        .eval(
+            r#"
+            nil, true, 42, 0.5, "text", {}, print, coroutine.create(function() end),
+                setmetatable({}, { __tostring = function() return "custom" end })
+            "#,
+            None,
+        ).unwrap();
+    assert_eq!(values.len(), 9);
+    for value in values {
+        let expected: StdString = tostring.call(value.clone()).unwrap();
+        assert_eq!(lua.coerce_to_string(&value).unwrap(), expected.as_str());
+    }
+    assert_eq!(
+        lua.coerce_to_string(&Value::Error(Error::BindError)).unwrap(),
+        Error::BindError.to_string().as_str()
+    );
+
+    let failing: Value = lua
+        .eval(r#"setmetatable({}, { __tostring = function() error("no") end })"#, None)
+        .unwrap();
+    match lua.coerce_to_string(&failing) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    let not_string: Value = lua
+        .eval(r#"setmetatable({}, { __tostring = function() return {} end })"#, None)
+        .unwrap();
+    assert!(lua.coerce_to_string(&not_string).is_err());
+    assert_eq!(lua.eval::<i64>("1 + 1", None).unwrap(), 2);
+}
+
+#[test]
+fn test_to_pointer() {
+    let lua = Lua::new();
+    let tostring: Function = lua.globals().get("tostring").unwrap();
+
+    let values = vec![
+        Value::Table(lua.create_table().unwrap()),
+        Value::Function(tostring.clone()),
+        lua.eval("coroutine.create(function() end)", None).unwrap(),
+    ];
+    for value in values {
+        let pointer = value.to_pointer();
+        assert!(!pointer.is_null());
+        assert_eq!(value.clone().to_pointer(), pointer);
+        let shown: String = tostring.call(value.clone()).unwrap();
+        assert_eq!(shown, format!("{}: {:p}", value.type_name(), pointer));
+    }
+
+    // `__tostring` and `__eq` do not affect the pointer.
+    let table: Value = lua
+        .eval("setmetatable({}, { __tostring = error, __eq = error })", None)
+        .unwrap();
+    assert!(!table.to_pointer().is_null());
+    let other = Value::Table(lua.create_table().unwrap());
+    assert_ne!(table.to_pointer(), other.to_pointer());
+
+    let ud = LightUserData(42 as *mut c_void);
+    assert_eq!(Value::LightUserData(ud).to_pointer(), 42 as *const c_void);
+    assert!(Value::Nil.to_pointer().is_null());
+    assert!(Value::Boolean(true).to_pointer().is_null());
+    assert!(Value::Integer(1).to_pointer().is_null());
+    assert!(lua.pack("string").unwrap().to_pointer().is_null());
+}